@@ -0,0 +1,107 @@
+//! Compares `IndexedQueue` against the `VecDeque` the queue used to be stored in.
+//! Run with `cargo bench`
+
+use std::collections::VecDeque;
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use queuebot::queue::IndexedQueue;
+
+/// Queue lengths to benchmark at
+const SIZES: [u64; 2] = [10_000, 100_000];
+
+fn filled(n: u64) -> (VecDeque<u64>, IndexedQueue) {
+    let mut deque = VecDeque::new();
+    let mut indexed = IndexedQueue::new();
+    for user in 0..n {
+        deque.push_back(user);
+        indexed.push_back(user);
+    }
+    (deque, indexed)
+}
+
+/// `q_join` checks if the student is already in the queue (the worst case is someone who isn't)
+fn contains(c: &mut Criterion) {
+    let mut group = c.benchmark_group("contains");
+    for n in SIZES {
+        let (deque, indexed) = filled(n);
+        group.bench_with_input(BenchmarkId::new("VecDeque", n), &n, |b, &n| b.iter(|| deque.contains(black_box(&n))));
+        group.bench_with_input(BenchmarkId::new("IndexedQueue", n), &n, |b, &n| b.iter(|| indexed.contains(black_box(n))));
+    }
+    group.finish();
+}
+
+/// `q_position` for the student at the back of the queue
+fn position(c: &mut Criterion) {
+    let mut group = c.benchmark_group("position");
+    for n in SIZES {
+        let (deque, indexed) = filled(n);
+        let last = n - 1;
+        group.bench_with_input(BenchmarkId::new("VecDeque", n), &last, |b, &last| {
+            b.iter(|| deque.iter().position(|&id| id == black_box(last)))
+        });
+        group.bench_with_input(BenchmarkId::new("IndexedQueue", n), &last, |b, &last| b.iter(|| indexed.position(black_box(last))));
+    }
+    group.finish();
+}
+
+/// `q_leave` / `q_remove_other` for a student in the middle, who then rejoins at the back
+fn remove_and_rejoin(c: &mut Criterion) {
+    let mut group = c.benchmark_group("remove_and_rejoin");
+    for n in SIZES {
+        let (mut deque, mut indexed) = filled(n);
+        let middle = n / 2;
+        group.bench_with_input(BenchmarkId::new("VecDeque", n), &middle, |b, &middle| b.iter(|| {
+            let i = deque.iter().position(|&id| id == black_box(middle)).unwrap();
+            deque.remove(i);
+            deque.push_back(middle);
+        }));
+        group.bench_with_input(BenchmarkId::new("IndexedQueue", n), &middle, |b, &middle| b.iter(|| {
+            indexed.remove(black_box(middle));
+            indexed.push_back(middle);
+        }));
+    }
+    group.finish();
+}
+
+/// `!q noshow` moving a student from the back of the queue to near the front
+fn move_near_front(c: &mut Criterion) {
+    let mut group = c.benchmark_group("move_near_front");
+    for n in SIZES {
+        let (mut deque, mut indexed) = filled(n);
+        group.bench_with_input(BenchmarkId::new("VecDeque", n), &n, |b, _| b.iter(|| {
+            let user = deque.pop_back().unwrap();
+            deque.insert(2, black_box(user));
+        }));
+        group.bench_with_input(BenchmarkId::new("IndexedQueue", n), &n, |b, _| b.iter(|| {
+            let user = indexed.get(indexed.len() - 1).unwrap();
+            indexed.move_to(black_box(user), 2);
+        }));
+    }
+    group.finish();
+}
+
+/// `q_join` followed by `q_next`, which both queues are already good at
+fn join_and_next(c: &mut Criterion) {
+    let mut group = c.benchmark_group("join_and_next");
+    for n in SIZES {
+        let (mut deque, mut indexed) = filled(n);
+        let mut next = n;
+        group.bench_with_input(BenchmarkId::new("VecDeque", n), &n, |b, _| b.iter(|| {
+            deque.push_back(black_box(next));
+            next += 1;
+            deque.pop_front()
+        }));
+        let mut next = n;
+        group.bench_with_input(BenchmarkId::new("IndexedQueue", n), &n, |b, _| b.iter(|| {
+            indexed.push_back(black_box(next));
+            next += 1;
+            indexed.pop_front()
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, contains, position, remove_and_rejoin, move_near_front, join_and_next);
+criterion_main!(benches);
@@ -0,0 +1,198 @@
+use tokio::sync::{mpsc, oneshot};
+
+use crate::audit::Outcome;
+use crate::{DiscordUser, Message, QueueBot};
+
+/// How many requests can be waiting for the bot before senders have to wait
+const MAILBOX_SIZE: usize = 1024;
+
+/// Some work to do with the bot (see `BotHandle::query`)
+type Job = Box<dyn FnOnce(&mut QueueBot) + Send>;
+
+/// A message which owns all of its data, so (unlike `Message`) it
+/// can be handed to another task. A real gateway delivers these
+/// from many connections at once
+#[derive(Clone)]
+pub struct IncomingMessage {
+    /// The message's ID (0 if it didn't come from Discord, see `Message`)
+    pub id: u64,
+    pub guild_id: u64,
+    pub author: DiscordUser,
+    pub message: String,
+    pub mentions: Option<Vec<DiscordUser>>,
+}
+
+impl IncomingMessage {
+    /// Create a message without any mentions which didn't come from Discord
+    pub fn new(guild_id: u64, author: &DiscordUser, message: &str) -> IncomingMessage {
+        IncomingMessage { id: 0, guild_id, author: author.clone(), message: String::from(message), mentions: None }
+    }
+
+    /// Borrow this as the `Message` the bot handles
+    pub fn as_message(&self) -> Message<'_> {
+        Message {
+            id: self.id,
+            guild_id: self.guild_id,
+            author: &self.author,
+            message: self.message.clone(),
+            mentions: self.mentions.clone(),
+        }
+    }
+}
+
+/// Runs a `QueueBot` as an actor.
+///
+/// The bot is moved onto its own task and is only ever touched by that
+/// task, which works through requests one at a time in the order they
+/// arrived. Any number of tasks (or threads) can hold a handle and send it
+/// requests at the same time. Since every request sees the result of the one
+/// before it, two students joining at the same instant can never both take
+/// the same spot or end up in the queue twice.
+///
+/// Cloning a handle is cheap and every clone talks to the same bot. The
+/// bot stops once every handle has been dropped
+#[derive(Clone)]
+pub struct BotHandle {
+    sender: mpsc::Sender<Job>,
+}
+
+impl BotHandle {
+    /// Move the bot onto a new task. Must be called from within a Tokio runtime
+    pub fn spawn(mut bot: QueueBot) -> BotHandle {
+        let (sender, mut receiver) = mpsc::channel::<Job>(MAILBOX_SIZE);
+        tokio::spawn(async move {
+            while let Some(job) = receiver.recv().await {
+                job(&mut bot);
+            }
+        });
+        BotHandle { sender }
+    }
+
+    /// Handle a message and wait until the bot is done with it.
+    /// Returns how the command went (`None` if the message wasn't a command)
+    pub async fn on_message(&self, msg: IncomingMessage) -> Option<Outcome> {
+        self.query(move |bot| bot.on_message(msg.as_message())).await
+    }
+
+    /// Same as `on_message` but for threads which aren't running within Tokio
+    /// (see `blocking_query`)
+    pub fn blocking_on_message(&self, msg: IncomingMessage) -> Option<Outcome> {
+        self.blocking_query(move |bot| bot.on_message(msg.as_message()))
+    }
+
+    /// Send any reminders which are due
+    pub async fn tick(&self) {
+        self.query(|bot| bot.tick()).await
+    }
+
+    /// Run `f` on the bot's task (after everything sent before it) and return its result
+    pub async fn query<T, F>(&self, f: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut QueueBot) -> T + Send + 'static,
+    {
+        let (job, reply) = BotHandle::job(f);
+        self.sender.send(job).await.expect("the bot's task has stopped");
+        reply.await.expect("the bot's task has stopped")
+    }
+
+    /// Same as `query` but for threads which aren't running within Tokio
+    /// (such as the web server's). Panics if called from within an async task
+    pub fn blocking_query<T, F>(&self, f: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut QueueBot) -> T + Send + 'static,
+    {
+        let (job, reply) = BotHandle::job(f);
+        self.sender.blocking_send(job).expect("the bot's task has stopped");
+        reply.blocking_recv().expect("the bot's task has stopped")
+    }
+
+    /// Wrap `f` so its result is sent back through a channel
+    fn job<T, F>(f: F) -> (Job, oneshot::Receiver<T>)
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut QueueBot) -> T + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let job = Box::new(move |bot: &mut QueueBot| {
+            // The caller may have given up waiting, which is fine
+            let _ = sender.send(f(bot));
+        });
+        (job, receiver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::guild::GuildConfig;
+    use crate::transport::{MockTransport, Sent};
+    use crate::users::UserRegistry;
+
+    const GUILD: u64 = 1;
+    const STUDENTS: usize = 2000;
+
+    /// Send every message at the same time and wait for all of them to be handled
+    async fn send_all(handle: &BotHandle, messages: Vec<IncomingMessage>) {
+        let tasks = messages.into_iter()
+            .map(|msg| {
+                let handle = handle.clone();
+                tokio::spawn(async move { handle.on_message(msg).await })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            task.await.unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn concurrent_joins_and_leaves_keep_the_queue_consistent() {
+        let mut users = UserRegistry::new();
+        let students = (0..STUDENTS)
+            .map(|i| users.register(&format!("Student{}", i), "0001", false).unwrap())
+            .collect::<Vec<DiscordUser>>();
+        let transport = MockTransport::default();
+        let handle = BotHandle::spawn(QueueBot::new()
+            .with_guild(GUILD, GuildConfig::new("CS 120"))
+            .with_users(users)
+            .with_transport(transport.clone()));
+
+        // Every student tries to join twice at the same time
+        let joins = students.iter()
+            .flat_map(|s| [IncomingMessage::new(GUILD, s, "!q join"), IncomingMessage::new(GUILD, s, "!q join")])
+            .collect();
+        send_all(&handle, joins).await;
+
+        // Half of them leave while the other half try to join again
+        let (leaving, staying) = students.split_at(STUDENTS / 2);
+        let churn = leaving.iter().map(|s| IncomingMessage::new(GUILD, s, "!q leave"))
+            .chain(staying.iter().map(|s| IncomingMessage::new(GUILD, s, "!q join")))
+            .collect();
+        send_all(&handle, churn).await;
+
+        let (queue, joined) = handle.query(|bot| {
+            let g = bot.guild(GUILD);
+            (g.queue.iter().collect::<Vec<u64>>(), g.joined.keys().copied().collect::<HashSet<u64>>())
+        }).await;
+
+        // No duplicates, and exactly the students who stayed
+        let unique = queue.iter().copied().collect::<HashSet<u64>>();
+        assert_eq!(unique.len(), queue.len());
+        assert_eq!(unique, staying.iter().map(|s| s.uuid).collect::<HashSet<u64>>());
+        assert_eq!(joined, unique);
+
+        // Everyone asks for their position at once and is told the spot they are really in
+        transport.take();
+        send_all(&handle, staying.iter().map(|s| IncomingMessage::new(GUILD, s, "!q position")).collect()).await;
+        let replies = transport.take();
+        assert_eq!(replies.len(), staying.len());
+        for (index, id) in queue.iter().enumerate() {
+            let name = &students.iter().find(|s| s.uuid == *id).unwrap().name;
+            let expected = Sent::Channel(format!("<@{}> You are at position {}", name, index + 1));
+            assert!(replies.contains(&expected), "missing {:?}", expected);
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+/// Where the audit log is written if nothing else is specified
+pub const DEFAULT_AUDIT_PATH: &str = "queuebot-audit.jsonl";
+
+/// The outcome of running a command.
+/// This mirrors the emote that is prepended to the bot's response
+/// (a warning means the command was rejected, e.g. "You are not in the queue!")
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Ok,
+    Rejected,
+    Error,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Ok => write!(f, "ok"),
+            Outcome::Rejected => write!(f, "rejected"),
+            Outcome::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A single line within the audit log.
+/// Every `!q` command the bot handles produces one of these
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    /// ID of the server the command was run in
+    #[serde(default)]
+    pub guild_id: u64,
+    /// Tag (`USERNAME#DDDD`) of the user who ran the command
+    pub author: String,
+    pub author_id: u64,
+    /// The command that was run (`join`, `remove`, etc.)
+    pub command: String,
+    /// Everything typed after the command
+    pub arguments: Vec<String>,
+    /// Tags of every user that was mentioned in the message
+    pub mentions: Vec<String>,
+    /// IDs of every user that was mentioned in the message.
+    /// Unlike tags, these stay the same when a user changes their name
+    #[serde(default)]
+    pub mention_ids: Vec<u64>,
+    pub result: Outcome,
+}
+
+impl AuditEntry {
+    /// Check if the given user either ran the command or was mentioned by it.
+    /// `user` can either be a full tag (`Russ#0004`) or just a username
+    fn involves(&self, user: &str) -> bool {
+        let user = user.trim_start_matches("<@").trim_end_matches('>');
+        std::iter::once(&self.author)
+            .chain(self.mentions.iter())
+            .any(|tag| {
+                tag.eq_ignore_ascii_case(user)
+                    || tag.split('#').next().unwrap_or("").eq_ignore_ascii_case(user)
+            })
+    }
+}
+
+impl fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {} {}", self.timestamp.format("%Y-%m-%d %H:%M:%S UTC"), self.author, self.command)?;
+        for arg in self.arguments.iter() {
+            write!(f, " {}", arg)?;
+        }
+        write!(f, " -> {}", self.result)
+    }
+}
+
+/// Criteria used to search through the audit log.
+/// Any field left as `None` matches everything
+#[derive(Default)]
+pub struct AuditFilter {
+    pub guild_id: Option<u64>,
+    pub user: Option<String>,
+    /// Matches the same entries as `user` but by ID so renamed users are still found
+    pub user_id: Option<u64>,
+    pub command: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl AuditFilter {
+    /// Build a filter from command line arguments such as
+    /// `--guild 1234 --user Russ --command remove --since 2022-02-01 --until 2022-02-02T12:00:00Z`
+    pub fn from_args(args: &[String]) -> Result<AuditFilter, String> {
+        let mut filter = AuditFilter::default();
+        let mut iter = args.iter();
+        while let Some(flag) = iter.next() {
+            let value = iter.next().ok_or(format!("missing value for {}", flag))?;
+            match flag.as_str() {
+                "--guild" => filter.guild_id = Some(value.parse().map_err(|_| format!("invalid guild ID `{}`", value))?),
+                "--user" => filter.user = Some(value.to_string()),
+                "--command" => filter.command = Some(value.to_ascii_lowercase()),
+                "--since" => filter.since = Some(parse_time(value)?),
+                "--until" => filter.until = Some(parse_time(value)?),
+                // Handled by the caller
+                "--file" => {},
+                _ => return Err(format!("unknown option {}", flag)),
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Check if an entry satisfies every criteria of the filter
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.guild_id.is_none_or(|id| entry.guild_id == id)
+            && self.user.as_ref().is_none_or(|u| entry.involves(u))
+            && self.user_id.is_none_or(|id| entry.author_id == id || entry.mention_ids.contains(&id))
+            && self.command.as_ref().is_none_or(|c| entry.command == *c)
+            && self.since.is_none_or(|t| entry.timestamp >= t)
+            && self.until.is_none_or(|t| entry.timestamp <= t)
+    }
+}
+
+/// Parse either an RFC 3339 timestamp or a plain `YYYY-MM-DD` date (midnight UTC)
+fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(value) {
+        return Ok(t.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|d| d.and_hms_opt(0, 0, 0).unwrap().and_utc())
+        .map_err(|_| format!("invalid time `{}` (expected YYYY-MM-DD or RFC 3339)", value))
+}
+
+/// An append-only log of every command the bot has processed.
+/// Entries are stored as JSON-lines (one JSON object per line).
+/// Once the file grows past `max_bytes` it is rotated: `audit.jsonl`
+/// becomes `audit.jsonl.1`, `audit.jsonl.1` becomes `audit.jsonl.2`, etc.
+/// and anything past `max_files` is deleted
+pub struct AuditLog {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl AuditLog {
+    /// Create an audit log at the given path which rotates
+    /// every megabyte and keeps five old files around
+    pub fn new<P: AsRef<Path>>(path: P) -> AuditLog {
+        AuditLog { path: path.as_ref().to_path_buf(), max_bytes: 1024 * 1024, max_files: 5 }
+    }
+
+    /// Add an entry to the end of the log, rotating it if it has grown too large
+    pub fn append(&self, entry: &AuditEntry) -> io::Result<()> {
+        if fs::metadata(&self.path).map(|m| m.len() >= self.max_bytes).unwrap_or(false) {
+            self.rotate()?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        let line = serde_json::to_string(entry)?;
+        writeln!(file, "{}", line)
+    }
+
+    /// Shift every file back by one (`.1` -> `.2`, etc.) and drop the oldest
+    fn rotate(&self) -> io::Result<()> {
+        let oldest = self.rotated_path(self.max_files);
+        if oldest.exists() {
+            fs::remove_file(oldest)?;
+        }
+        for i in (1..self.max_files).rev() {
+            let from = self.rotated_path(i);
+            if from.exists() {
+                fs::rename(from, self.rotated_path(i + 1))?;
+            }
+        }
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)
+        } else {
+            fs::rename(&self.path, self.rotated_path(1))
+        }
+    }
+
+    /// Path of the `n`th rotated file (0 is the file currently being written to)
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        if n == 0 {
+            return self.path.clone();
+        }
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    /// Read every entry (including rotated files) from oldest to newest
+    pub fn read_all(&self) -> io::Result<Vec<AuditEntry>> {
+        let mut entries = Vec::new();
+        for i in (0..=self.max_files).rev() {
+            let path = self.rotated_path(i);
+            if !path.exists() {
+                continue;
+            }
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                entries.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Delete every entry (including within rotated files) for which `keep` returns false.
+    /// Returns how many entries were deleted
+    pub fn retain<F: Fn(&AuditEntry) -> bool>(&self, keep: F) -> io::Result<usize> {
+        let mut deleted = 0;
+        for i in 0..=self.max_files {
+            let path = self.rotated_path(i);
+            if !path.exists() {
+                continue;
+            }
+            let mut entries = Vec::new();
+            for line in BufReader::new(File::open(&path)?).lines() {
+                let line = line?;
+                if !line.trim().is_empty() {
+                    entries.push(serde_json::from_str::<AuditEntry>(&line)?);
+                }
+            }
+            let before = entries.len();
+            entries.retain(|e| keep(e));
+            if entries.len() == before {
+                continue;
+            }
+            deleted += before - entries.len();
+
+            // Write to a temporary file first so a crash can't lose the whole log
+            let mut temp = path.clone().into_os_string();
+            temp.push(".tmp");
+            let mut file = File::create(&temp)?;
+            for entry in entries.iter() {
+                writeln!(file, "{}", serde_json::to_string(entry)?)?;
+            }
+            file.sync_all()?;
+            fs::rename(temp, path)?;
+        }
+        Ok(deleted)
+    }
+
+    /// Return every entry which matches the given filter (oldest first)
+    pub fn search(&self, filter: &AuditFilter) -> io::Result<Vec<AuditEntry>> {
+        Ok(self.read_all()?.into_iter().filter(|e| filter.matches(e)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn entry(command: &str, author_id: u64, day: u32) -> AuditEntry {
+        AuditEntry {
+            timestamp: Utc.with_ymd_and_hms(2022, 2, day, 9, 0, 0).unwrap(),
+            guild_id: 1,
+            author: format!("User{}#0001", author_id),
+            author_id,
+            command: String::from(command),
+            arguments: Vec::new(),
+            mentions: Vec::new(),
+            mention_ids: Vec::new(),
+            result: Outcome::Ok,
+        }
+    }
+
+    /// A log which rotates after every entry (so each file holds one entry)
+    fn tiny_log(name: &str) -> AuditLog {
+        let path = std::env::temp_dir().join(format!("queuebot-audit-{}-{}.jsonl", name, std::process::id()));
+        let log = AuditLog { path, max_bytes: 1, max_files: 2 };
+        log.remove_files();
+        log
+    }
+
+    impl AuditLog {
+        fn remove_files(&self) {
+            for i in 0..=self.max_files + 1 {
+                let _ = fs::remove_file(self.rotated_path(i));
+            }
+        }
+    }
+
+    fn commands(entries: Vec<AuditEntry>) -> Vec<String> {
+        entries.into_iter().map(|e| e.command).collect()
+    }
+
+    #[test]
+    fn rotates_once_the_size_limit_is_reached() {
+        let log = tiny_log("rotate");
+        for command in ["join", "leave", "next", "clear"] {
+            log.append(&entry(command, 1, 1)).unwrap();
+        }
+        // The oldest entry was rotated past `max_files` and deleted
+        assert_eq!(commands(log.read_all().unwrap()), ["leave", "next", "clear"]);
+        assert!(log.rotated_path(2).exists());
+        assert!(!log.rotated_path(3).exists());
+        log.remove_files();
+    }
+
+    #[test]
+    fn retain_deletes_from_every_file() {
+        let log = tiny_log("retain");
+        log.append(&entry("join", 1, 1)).unwrap();
+        log.append(&entry("leave", 2, 2)).unwrap();
+        log.append(&entry("next", 1, 3)).unwrap();
+
+        let cutoff = Utc.with_ymd_and_hms(2022, 2, 2, 0, 0, 0).unwrap();
+        assert_eq!(log.retain(|e| e.timestamp >= cutoff).unwrap(), 1);
+        assert_eq!(commands(log.read_all().unwrap()), ["leave", "next"]);
+        assert_eq!(log.retain(|e| e.author_id != 1).unwrap(), 1);
+        assert_eq!(commands(log.read_all().unwrap()), ["leave"]);
+        // Nothing to delete leaves the files alone
+        assert_eq!(log.retain(|_| true).unwrap(), 0);
+        log.remove_files();
+    }
+
+    #[test]
+    fn filters_are_parsed_from_arguments() {
+        let args = |line: &str| line.split_whitespace().map(String::from).collect::<Vec<String>>();
+        let filter = AuditFilter::from_args(&args("--guild 1 --user Russ --command NEXT --since 2022-02-02 --until 2022-02-03T12:00:00Z")).unwrap();
+        assert_eq!(filter.guild_id, Some(1));
+        assert_eq!(filter.command.as_deref(), Some("next"));
+        assert_eq!(filter.since, Some(Utc.with_ymd_and_hms(2022, 2, 2, 0, 0, 0).unwrap()));
+        assert_eq!(filter.until, Some(Utc.with_ymd_and_hms(2022, 2, 3, 12, 0, 0).unwrap()));
+        assert!(!filter.matches(&entry("next", 1, 1)));
+        assert!(filter.matches(&AuditEntry { mentions: vec![String::from("Russ#0004")], ..entry("next", 1, 3) }));
+
+        assert_eq!(AuditFilter::from_args(&args("--since 2022-02-30")).err().unwrap(), "invalid time `2022-02-30` (expected YYYY-MM-DD or RFC 3339)");
+        assert_eq!(AuditFilter::from_args(&args("--guild cs120")).err().unwrap(), "invalid guild ID `cs120`");
+        assert_eq!(AuditFilter::from_args(&args("--author Russ")).err().unwrap(), "unknown option --author");
+        assert_eq!(AuditFilter::from_args(&args("--user")).err().unwrap(), "missing value for --user");
+    }
+}
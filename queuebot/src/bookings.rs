@@ -0,0 +1,221 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveTime, Utc, Weekday};
+
+use crate::history::parse_duration;
+
+/// A period of time a TA has set aside to help a single student
+/// who booked it in advance (instead of waiting in the walk-in queue)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Slot {
+    /// Number students type to book the slot (`!q book 3`).
+    /// Numbers are never reused within a server
+    pub id: u64,
+    /// ID of the TA holding the slot
+    pub ta: u64,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// ID of the student who booked it
+    pub student: Option<u64>,
+}
+
+impl Slot {
+    fn overlaps(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> bool {
+        self.start < end && start < self.end
+    }
+}
+
+/// Why slots couldn't be added, booked or cancelled
+#[derive(Clone, Debug, PartialEq)]
+pub enum BookingError {
+    /// The new slots overlap one the TA already has
+    Conflict(Slot),
+    /// There isn't an upcoming slot with that number
+    NotFound(u64),
+    /// The student already has this slot booked (one booking per student at a time)
+    AlreadyBooked(Slot),
+    /// Someone else booked the slot first
+    Taken(u64),
+    /// Every upcoming slot is booked
+    NoneFree,
+    /// The student doesn't have a booking to cancel
+    NotBooked,
+    /// The slot starts too soon to cancel it
+    TooLate(Slot),
+}
+
+/// Every upcoming appointment slot within a server.
+/// Slots are removed once they start (see `take_due`)
+#[derive(Default)]
+pub struct Bookings {
+    /// Sorted by when they start
+    slots: Vec<Slot>,
+    /// How many slots have ever been added (used to number them)
+    added: u64,
+}
+
+impl Bookings {
+    /// Split the time from `start` to `end` into slots of `length` for a TA
+    /// (any time left over at the end isn't used). None are added if any of
+    /// them would overlap a slot the TA already has. Returns the new slots
+    pub fn add(&mut self, ta: u64, start: DateTime<Utc>, end: DateTime<Utc>, length: Duration) -> Result<Vec<Slot>, BookingError> {
+        if let Some(existing) = self.slots.iter().find(|s| s.ta == ta && s.overlaps(start, end)) {
+            return Err(BookingError::Conflict(existing.clone()));
+        }
+        let mut added = Vec::new();
+        let mut from = start;
+        while length > Duration::zero() && from + length <= end {
+            self.added += 1;
+            added.push(Slot { id: self.added, ta, start: from, end: from + length, student: None });
+            from += length;
+        }
+        self.slots.extend(added.iter().cloned());
+        self.slots.sort_by_key(|s| (s.start, s.id));
+        Ok(added)
+    }
+
+    /// Delete a slot (even if it was booked) and return it
+    pub fn remove(&mut self, id: u64) -> Result<Slot, BookingError> {
+        let index = self.slots.iter().position(|s| s.id == id).ok_or(BookingError::NotFound(id))?;
+        Ok(self.slots.remove(index))
+    }
+
+    /// Book slot `id` for a student, or the earliest free slot if `id` is `None`.
+    /// Students can only have one booking at a time
+    pub fn book(&mut self, student: u64, id: Option<u64>) -> Result<Slot, BookingError> {
+        if let Some(existing) = self.booking_of(student) {
+            return Err(BookingError::AlreadyBooked(existing.clone()));
+        }
+        let slot = match id {
+            Some(id) => self.slots.iter_mut().find(|s| s.id == id).ok_or(BookingError::NotFound(id))?,
+            None => self.slots.iter_mut().find(|s| s.student.is_none()).ok_or(BookingError::NoneFree)?,
+        };
+        if slot.student.is_some() {
+            return Err(BookingError::Taken(slot.id));
+        }
+        slot.student = Some(student);
+        Ok(slot.clone())
+    }
+
+    /// Cancel a student's booking, unless it starts within `deadline` of `now`.
+    /// Returns the slot, which can now be booked by someone else
+    pub fn cancel(&mut self, student: u64, now: DateTime<Utc>, deadline: Duration) -> Result<Slot, BookingError> {
+        let slot = self.slots.iter_mut().find(|s| s.student == Some(student)).ok_or(BookingError::NotBooked)?;
+        if slot.start - now < deadline {
+            return Err(BookingError::TooLate(slot.clone()));
+        }
+        let cancelled = slot.clone();
+        slot.student = None;
+        Ok(cancelled)
+    }
+
+    /// The slot a student has booked (if they have one)
+    pub fn booking_of(&self, student: u64) -> Option<&Slot> {
+        self.slots.iter().find(|s| s.student == Some(student))
+    }
+
+    /// Every upcoming slot, soonest first
+    pub fn slots(&self) -> &[Slot] {
+        &self.slots
+    }
+
+    /// Remove every slot which has started by `now` and return the booked ones
+    /// (soonest first) so their students can be put into the queue
+    pub fn take_due(&mut self, now: DateTime<Utc>) -> Vec<Slot> {
+        let due = self.slots.iter().take_while(|s| s.start <= now).count();
+        self.slots.drain(..due).filter(|s| s.student.is_some()).collect()
+    }
+}
+
+/// Parse the arguments of `!q slots add` (such as `Tue 14:00-16:00 15m`) into when
+/// the slots start and end and how long each one is. Times are within `offset`
+/// and the day is the next one with that name whose start hasn't passed yet.
+/// Returns `None` if the arguments are invalid
+pub fn parse_slots(args: &[&str], now: DateTime<Utc>, offset: FixedOffset) -> Option<(DateTime<Utc>, DateTime<Utc>, Duration)> {
+    let (day, times, length) = match args {
+        [day, times, length] => (Weekday::from_str(day).ok()?, *times, parse_duration(length).ok()?),
+        _ => return None,
+    };
+    let (start, end) = times.split_once('-')?;
+    let start = NaiveTime::parse_from_str(start, "%H:%M").ok()?;
+    let end = NaiveTime::parse_from_str(end, "%H:%M").ok()?;
+    if end <= start || length <= Duration::zero() || length > end - start {
+        return None;
+    }
+
+    let today = now.with_timezone(&offset).date_naive();
+    let mut date = today + Duration::days(((7 + day.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7) as i64);
+    let at = |date: chrono::NaiveDate, time: NaiveTime| date.and_time(time).and_local_timezone(offset).single().map(|t| t.with_timezone(&Utc));
+    if at(date, start)? <= now {
+        date += Duration::days(7);
+    }
+    Some((at(date, start)?, at(date, end)?, length))
+}
+
+/// Show when a slot starts (such as `Tue 1 Feb 14:15`) within `offset`
+pub fn format_time(time: DateTime<Utc>, offset: FixedOffset) -> String {
+    time.with_timezone(&offset).format("%a %-d %b %H:%M").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// Tuesday 1 February 2022, 14:00 UTC
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2022, 2, 1, 14, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn slot_times_are_parsed() {
+        let utc = FixedOffset::east_opt(0).unwrap();
+        let (start, end, length) = parse_slots(&["wed", "09:30-11:00", "30m"], now(), utc).unwrap();
+        assert_eq!((start, end, length), (Utc.with_ymd_and_hms(2022, 2, 2, 9, 30, 0).unwrap(), Utc.with_ymd_and_hms(2022, 2, 2, 11, 0, 0).unwrap(), Duration::minutes(30)));
+        assert_eq!(format_time(start, utc), "Wed 2 Feb 09:30");
+        // Later today is today but earlier today is next week
+        assert_eq!(parse_slots(&["Tue", "15:00-16:00", "15m"], now(), utc).unwrap().0, Utc.with_ymd_and_hms(2022, 2, 1, 15, 0, 0).unwrap());
+        assert_eq!(parse_slots(&["Tuesday", "13:00-16:00", "15m"], now(), utc).unwrap().0, Utc.with_ymd_and_hms(2022, 2, 8, 13, 0, 0).unwrap());
+        // Times are within the server's timezone
+        let hawaii = FixedOffset::west_opt(10 * 3600).unwrap();
+        assert_eq!(parse_slots(&["Tue", "14:00-16:00", "15m"], now(), hawaii).unwrap().0, Utc.with_ymd_and_hms(2022, 2, 2, 0, 0, 0).unwrap());
+
+        assert_eq!(parse_slots(&["Tue", "16:00-14:00", "15m"], now(), utc), None);
+        assert_eq!(parse_slots(&["Tue", "14:00-14:10", "15m"], now(), utc), None);
+        assert_eq!(parse_slots(&["Someday", "14:00-16:00", "15m"], now(), utc), None);
+        assert_eq!(parse_slots(&["Tue", "2pm-4pm", "15m"], now(), utc), None);
+        assert_eq!(parse_slots(&["Tue", "14:00-16:00"], now(), utc), None);
+        assert_eq!(parse_slots(&["Tue", "14:00-16:00", "9999999999999999m"], now(), utc), None);
+    }
+
+    #[test]
+    fn slots_are_booked_and_cancelled() {
+        let mut bookings = Bookings::default();
+        let start = now() + Duration::hours(2);
+        let slots = bookings.add(1, start, start + Duration::minutes(50), Duration::minutes(15)).unwrap();
+        assert_eq!(slots.iter().map(|s| s.id).collect::<Vec<u64>>(), [1, 2, 3]);
+        assert_eq!(slots[2].end, start + Duration::minutes(45));
+        // The same TA can't have overlapping slots but another TA can
+        assert_eq!(bookings.add(1, start + Duration::minutes(40), start + Duration::hours(1), Duration::minutes(10)), Err(BookingError::Conflict(slots[2].clone())));
+        assert_eq!(bookings.add(2, start, start + Duration::minutes(15), Duration::minutes(15)).unwrap()[0].id, 4);
+
+        assert_eq!(bookings.book(10, None).unwrap().id, 1);
+        assert_eq!(bookings.book(10, Some(2)), Err(BookingError::AlreadyBooked(bookings.slots()[0].clone())));
+        assert_eq!(bookings.book(11, Some(1)), Err(BookingError::Taken(1)));
+        assert_eq!(bookings.book(11, Some(9)), Err(BookingError::NotFound(9)));
+        // Slot 4 starts at the same time as slot 1 so it's the earliest free one
+        assert_eq!(bookings.book(11, None).unwrap().id, 4);
+
+        assert!(matches!(bookings.cancel(10, now(), Duration::hours(3)), Err(BookingError::TooLate(s)) if s.id == 1));
+        assert_eq!(bookings.cancel(10, now(), Duration::hours(1)).unwrap().id, 1);
+        assert_eq!(bookings.cancel(10, now(), Duration::hours(1)), Err(BookingError::NotBooked));
+        assert_eq!(bookings.book(12, None).unwrap().id, 1);
+
+        // Once they start they're taken out, whether or not they were booked
+        let due = bookings.take_due(start + Duration::minutes(15));
+        assert_eq!(due.iter().map(|s| (s.id, s.student)).collect::<Vec<_>>(), [(1, Some(12)), (4, Some(11))]);
+        assert_eq!(bookings.slots().iter().map(|s| s.id).collect::<Vec<u64>>(), [3]);
+        assert_eq!(bookings.remove(3).unwrap().id, 3);
+        assert_eq!(bookings.book(10, None), Err(BookingError::NoneFree));
+    }
+}
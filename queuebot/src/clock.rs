@@ -0,0 +1,42 @@
+use chrono::{DateTime, Utc};
+
+/// Where the bot gets the current time from. Anything which depends on
+/// time (reminders, audit timestamps) goes through this so tests can
+/// control time instead of sleeping
+pub trait Clock: Send {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The real time according to the system
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock which only moves when told to.
+/// Clones share the same time so a test can keep one while the bot owns another
+#[derive(Clone)]
+pub struct ManualClock {
+    now: std::sync::Arc<std::sync::Mutex<DateTime<Utc>>>,
+}
+
+impl ManualClock {
+    /// Create a clock stopped at the given time
+    pub fn new(now: DateTime<Utc>) -> ManualClock {
+        ManualClock { now: std::sync::Arc::new(std::sync::Mutex::new(now)) }
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, by: chrono::Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
@@ -0,0 +1,507 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::audit::Outcome;
+use crate::{Message, QueueBot};
+
+/// Who is allowed to run a command.
+/// Roles are ordered so that a TA can run every student command
+/// (and a head TA can run every TA command)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Student,
+    Ta,
+    /// A TA who runs the course's office hours (see `GuildConfig::head_tas`)
+    HeadTa,
+}
+
+/// A command that can be run by typing `!q <name> <args...>`.
+///
+/// To add a new command, create a struct which implements this trait
+/// and register it with `QueueBot::with_command`.
+/// `Send + Sync` is required so commands can be shared between threads
+pub trait Command: Send + Sync {
+    /// The word typed after `!q` (e.g. `join`). Must be lowercase
+    fn name(&self) -> &'static str;
+
+    /// Other words which also run this command (e.g. `pos` for `position`)
+    fn aliases(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// The minimum role needed to run the command
+    fn required_role(&self) -> Role {
+        Role::Student
+    }
+
+    /// A short description of how to use the command, shown by `!q help`
+    fn usage(&self) -> &'static str;
+
+    /// Run the command. `args` are the words typed after the command name
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, args: &[&str]) -> Outcome;
+}
+
+/// Holds every command the bot knows about and
+/// looks them up by their name or aliases
+#[derive(Clone, Default)]
+pub struct CommandRegistry {
+    /// Commands in the order they were registered (used by `!q help`)
+    commands: Vec<Arc<dyn Command>>,
+    /// Map of every name and alias to an index within `commands`
+    lookup: HashMap<&'static str, usize>,
+}
+
+impl CommandRegistry {
+    /// Create a registry without any commands
+    pub fn new() -> CommandRegistry {
+        CommandRegistry::default()
+    }
+
+    /// Create a registry containing every built-in QueueBot command
+    pub fn with_builtins() -> CommandRegistry {
+        let mut registry = CommandRegistry::new();
+        registry.register(Ping);
+        registry.register(Help);
+        registry.register(Join);
+        registry.register(Leave);
+        registry.register(Position);
+        registry.register(List);
+        registry.register(Slots);
+        registry.register(Book);
+        registry.register(Cancel);
+        registry.register(Feedback);
+        registry.register(Language);
+        registry.register(Notify);
+        registry.register(History);
+        registry.register(Forget);
+        registry.register(Duty);
+        registry.register(Next);
+        registry.register(NoShow);
+        registry.register(Done);
+        registry.register(Clear);
+        registry.register(Add);
+        registry.register(Remove);
+        registry.register(Pin);
+        registry.register(Audit);
+        registry
+    }
+
+    /// Add a command to the registry. If its name or one of its aliases
+    /// is already taken, the new command replaces the old one for that word
+    pub fn register<C: Command + 'static>(&mut self, command: C) {
+        let index = self.commands.len();
+        self.lookup.insert(command.name(), index);
+        for alias in command.aliases() {
+            self.lookup.insert(alias, index);
+        }
+        self.commands.push(Arc::new(command));
+    }
+
+    /// Find the command with the given name or alias
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Command>> {
+        self.lookup.get(name).map(|&i| Arc::clone(&self.commands[i]))
+    }
+
+    /// Every registered command which can still be reached by at least one word
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<dyn Command>> {
+        self.commands.iter().enumerate()
+            .filter(|(i, _)| self.lookup.values().any(|j| j == i))
+            .map(|(_, c)| c)
+    }
+}
+
+/// `!q ping`
+struct Ping;
+
+impl Command for Ping {
+    fn name(&self) -> &'static str { "ping" }
+    fn usage(&self) -> &'static str { "`!q ping` - Check to see if the bot is working" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, _args: &[&str]) -> Outcome {
+        bot.queue_ping(msg.guild_id, msg.author)
+    }
+}
+
+/// `!q help`
+struct Help;
+
+impl Command for Help {
+    fn name(&self) -> &'static str { "help" }
+    fn usage(&self) -> &'static str { "`!q help` - List every command you can run" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, _args: &[&str]) -> Outcome {
+        let role = bot.role_of(msg.guild_id, msg.author);
+        // Use the translated usage if there is one, otherwise fall back to the command's own
+        let lines = bot.commands.iter()
+            .filter(|c| c.required_role() <= role)
+            .map(|c| match format!("usage.{}", c.name()) {
+                key if bot.catalog.contains(&key) => bot.tr(msg.guild_id, msg.author, &key, &[]),
+                _ => c.usage().to_string(),
+            })
+            .collect::<Vec<String>>();
+        bot.send(bot.tr(msg.guild_id, msg.author, "help", &[("commands", lines.join("\n"))]), None)
+    }
+}
+
+/// `!q join [topic]`
+struct Join;
+
+impl Command for Join {
+    fn name(&self) -> &'static str { "join" }
+    fn usage(&self) -> &'static str { "`!q join [topic]` - Join the queue (optionally saying what you need help with)" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, args: &[&str]) -> Outcome {
+        bot.q_join(msg.guild_id, msg.author, &args.join(" "))
+    }
+}
+
+/// `!q leave`
+struct Leave;
+
+impl Command for Leave {
+    fn name(&self) -> &'static str { "leave" }
+    fn usage(&self) -> &'static str { "`!q leave` - Leave the queue" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, _args: &[&str]) -> Outcome {
+        bot.q_leave(msg.guild_id, msg.author)
+    }
+}
+
+/// `!q position`
+struct Position;
+
+impl Command for Position {
+    fn name(&self) -> &'static str { "position" }
+    fn aliases(&self) -> &'static [&'static str] { &["pos"] }
+    fn usage(&self) -> &'static str { "`!q position` - Get your position within the queue" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, _args: &[&str]) -> Outcome {
+        bot.q_position(msg.guild_id, msg.author)
+    }
+}
+
+/// `!q list`
+struct List;
+
+impl Command for List {
+    fn name(&self) -> &'static str { "list" }
+    fn usage(&self) -> &'static str { "`!q list` - List every student within the queue" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, _args: &[&str]) -> Outcome {
+        bot.q_list(msg.guild_id, msg.author)
+    }
+}
+
+/// `!q language [code|default]`
+struct Language;
+
+impl Command for Language {
+    fn name(&self) -> &'static str { "language" }
+    fn aliases(&self) -> &'static [&'static str] { &["lang"] }
+    fn usage(&self) -> &'static str { "`!q language [code|default]` - Show or change the language the bot replies to you in" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, args: &[&str]) -> Outcome {
+        bot.q_language(msg.guild_id, msg.author, args)
+    }
+}
+
+/// `!q notify [on|off]`
+struct Notify;
+
+impl Command for Notify {
+    fn name(&self) -> &'static str { "notify" }
+    fn usage(&self) -> &'static str { "`!q notify [on|off]` - Turn reminders about your place in the queue on or off" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, args: &[&str]) -> Outcome {
+        bot.q_notify(msg.guild_id, msg.author, args)
+    }
+}
+
+/// `!q history [@user] [purge]`
+struct History;
+
+impl Command for History {
+    fn name(&self) -> &'static str { "history" }
+    fn usage(&self) -> &'static str { "`!q history [@user] [purge]` - Show (or delete) your past visits to the queue" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, args: &[&str]) -> Outcome {
+        let targets = bot.targets(msg, args);
+        bot.q_history(msg.guild_id, msg.author, args, targets.as_deref())
+    }
+}
+
+/// `!q forget me`
+struct Forget;
+
+impl Command for Forget {
+    fn name(&self) -> &'static str { "forget" }
+    fn usage(&self) -> &'static str { "`!q forget me` - Delete your history and settings" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, args: &[&str]) -> Outcome {
+        bot.q_forget(msg.guild_id, msg.author, args)
+    }
+}
+
+/// `!q slots [add DAY HH:MM-HH:MM LENGTH | remove ID]`
+struct Slots;
+
+impl Command for Slots {
+    fn name(&self) -> &'static str { "slots" }
+    fn usage(&self) -> &'static str { "`!q slots` - List appointment slots (TAs can `add DAY HH:MM-HH:MM LENGTH` or `remove ID`)" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, args: &[&str]) -> Outcome {
+        bot.q_slots(msg.guild_id, msg.author, args)
+    }
+}
+
+/// `!q book [ID]`
+struct Book;
+
+impl Command for Book {
+    fn name(&self) -> &'static str { "book" }
+    fn usage(&self) -> &'static str { "`!q book [ID]` - Book an appointment slot (the earliest free one if no ID is given)" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, args: &[&str]) -> Outcome {
+        bot.q_book(msg.guild_id, msg.author, args)
+    }
+}
+
+/// `!q cancel`
+struct Cancel;
+
+impl Command for Cancel {
+    fn name(&self) -> &'static str { "cancel" }
+    fn usage(&self) -> &'static str { "`!q cancel` - Cancel your appointment" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, _args: &[&str]) -> Outcome {
+        bot.q_cancel(msg.guild_id, msg.author)
+    }
+}
+
+/// `!q feedback RATING [comment]` or `!q feedback summary`
+struct Feedback;
+
+impl Command for Feedback {
+    fn name(&self) -> &'static str { "feedback" }
+    fn usage(&self) -> &'static str { "`!q feedback RATING [comment]` - Rate the help you just got from 1 to 5 (head TAs can see a `summary`)" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, args: &[&str]) -> Outcome {
+        bot.q_feedback(msg.guild_id, msg.author, msg.id, args)
+    }
+}
+
+/// `!q duty [on|off]`
+struct Duty;
+
+impl Command for Duty {
+    fn name(&self) -> &'static str { "duty" }
+    fn required_role(&self) -> Role { Role::Ta }
+    fn usage(&self) -> &'static str { "`!q duty [on|off]` - Show who is on duty, or go on or off duty" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, args: &[&str]) -> Outcome {
+        bot.q_duty(msg.guild_id, msg.author, args)
+    }
+}
+
+/// `!q next [@ta]`
+struct Next;
+
+impl Command for Next {
+    fn name(&self) -> &'static str { "next" }
+    fn required_role(&self) -> Role { Role::Ta }
+    fn usage(&self) -> &'static str { "`!q next [@ta]` - Pop the next student from the queue (for the given TA, or whoever's turn it is)" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, args: &[&str]) -> Outcome {
+        let targets = bot.targets(msg, args);
+        bot.q_next(msg.guild_id, msg.author, targets.as_deref())
+    }
+}
+
+/// `!q noshow [@user]`
+struct NoShow;
+
+impl Command for NoShow {
+    fn name(&self) -> &'static str { "noshow" }
+    fn required_role(&self) -> Role { Role::Ta }
+    fn usage(&self) -> &'static str { "`!q noshow [@user]` - Move a called student back in the queue because they didn't show up" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, args: &[&str]) -> Outcome {
+        let targets = bot.targets(msg, args);
+        bot.q_noshow(msg.guild_id, msg.author, targets.as_deref())
+    }
+}
+
+/// `!q done`
+struct Done;
+
+impl Command for Done {
+    fn name(&self) -> &'static str { "done" }
+    fn required_role(&self) -> Role { Role::Ta }
+    fn usage(&self) -> &'static str { "`!q done` - Archive the help thread with the student you called" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, _args: &[&str]) -> Outcome {
+        bot.q_done(msg.guild_id, msg.author)
+    }
+}
+
+/// `!q clear`
+struct Clear;
+
+impl Command for Clear {
+    fn name(&self) -> &'static str { "clear" }
+    fn required_role(&self) -> Role { Role::Ta }
+    fn usage(&self) -> &'static str { "`!q clear` - Empty the queue" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, _args: &[&str]) -> Outcome {
+        bot.q_clear(msg.guild_id, msg.author)
+    }
+}
+
+/// `!q add @user`
+struct Add;
+
+impl Command for Add {
+    fn name(&self) -> &'static str { "add" }
+    fn required_role(&self) -> Role { Role::Ta }
+    fn usage(&self) -> &'static str { "`!q add @user` - Add a student to the end of the queue" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, args: &[&str]) -> Outcome {
+        let targets = bot.targets(msg, args);
+        bot.q_add_other(msg.guild_id, msg.author, targets.as_deref())
+    }
+}
+
+/// `!q remove @user`
+struct Remove;
+
+impl Command for Remove {
+    fn name(&self) -> &'static str { "remove" }
+    fn required_role(&self) -> Role { Role::Ta }
+    fn usage(&self) -> &'static str { "`!q remove @user` - Remove a student from the queue" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, args: &[&str]) -> Outcome {
+        let targets = bot.targets(msg, args);
+        bot.q_remove_other(msg.guild_id, msg.author, targets.as_deref())
+    }
+}
+
+/// `!q pin`
+struct Pin;
+
+impl Command for Pin {
+    fn name(&self) -> &'static str { "pin" }
+    fn required_role(&self) -> Role { Role::Ta }
+    fn usage(&self) -> &'static str { "`!q pin` - Post and pin a message showing the queue with Join and Leave buttons" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, _args: &[&str]) -> Outcome {
+        bot.q_pin(msg.guild_id)
+    }
+}
+
+/// `!q audit @user`
+struct Audit;
+
+impl Command for Audit {
+    fn name(&self) -> &'static str { "audit" }
+    fn required_role(&self) -> Role { Role::Ta }
+    fn usage(&self) -> &'static str { "`!q audit @user` - Show recent commands run by or mentioning a user" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, args: &[&str]) -> Outcome {
+        let targets = bot.targets(msg, args);
+        bot.q_audit(msg.guild_id, msg.author, targets.as_deref())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{MockTransport, Sent};
+    use crate::users::UserRegistry;
+    use crate::{DiscordUser, GuildConfig};
+
+    /// A command which replies with its usage (so tests can tell commands apart)
+    struct Echo {
+        name: &'static str,
+        aliases: &'static [&'static str],
+        role: Role,
+        usage: &'static str,
+    }
+
+    impl Command for Echo {
+        fn name(&self) -> &'static str { self.name }
+        fn aliases(&self) -> &'static [&'static str] { self.aliases }
+        fn required_role(&self) -> Role { self.role }
+        fn usage(&self) -> &'static str { self.usage }
+        fn handle(&self, bot: &mut QueueBot, _msg: &Message, args: &[&str]) -> Outcome {
+            bot.send(format!("{} {}", self.usage, args.join(" ")), None)
+        }
+    }
+
+    fn usage(registry: &CommandRegistry, name: &str) -> Option<&'static str> {
+        registry.get(name).map(|c| c.usage())
+    }
+
+    #[test]
+    fn aliases_find_the_same_command() {
+        let registry = CommandRegistry::with_builtins();
+        assert_eq!(registry.get("pos").unwrap().name(), "position");
+        assert_eq!(registry.get("position").unwrap().name(), "position");
+        assert_eq!(registry.get("lang").unwrap().name(), "language");
+        // Names are looked up exactly (the bot lowercases them first)
+        assert!(registry.get("POS").is_none());
+        assert!(registry.get("nope").is_none());
+        // Aliases aren't listed as commands of their own
+        assert_eq!(registry.iter().filter(|c| c.name() == "position").count(), 1);
+        assert!(registry.iter().all(|c| c.name() != "pos"));
+    }
+
+    #[test]
+    fn registering_a_taken_word_replaces_the_command() {
+        let mut registry = CommandRegistry::new();
+        registry.register(Echo { name: "hours", aliases: &["when"], role: Role::Student, usage: "old" });
+        registry.register(Echo { name: "hours", aliases: &[], role: Role::Student, usage: "new" });
+        assert_eq!(usage(&registry, "hours"), Some("new"));
+        // The old command can still be reached through its alias
+        assert_eq!(usage(&registry, "when"), Some("old"));
+        assert_eq!(registry.iter().map(|c| c.usage()).collect::<Vec<&str>>(), ["old", "new"]);
+
+        // Once every word is taken it's no longer listed
+        registry.register(Echo { name: "when", aliases: &[], role: Role::Student, usage: "newer" });
+        assert_eq!(usage(&registry, "when"), Some("newer"));
+        assert_eq!(registry.iter().map(|c| c.usage()).collect::<Vec<&str>>(), ["new", "newer"]);
+    }
+
+    /// A bot with an extra TA command, along with a student and a TA
+    fn setup() -> (QueueBot, MockTransport, DiscordUser, DiscordUser) {
+        let mut users = UserRegistry::new();
+        let student = users.register("Kapua", "0002", false).unwrap();
+        let ta = users.register("Dana", "0005", false).unwrap();
+        let transport = MockTransport::default();
+        let bot = QueueBot::new()
+            .with_guild(1, GuildConfig::new("CS 120").with_ta(ta.uuid))
+            .with_users(users)
+            .with_transport(transport.clone())
+            .with_command(Echo { name: "secret", aliases: &["shh"], role: Role::Ta, usage: "`!q secret` - Only for TAs" });
+        (bot, transport, student, ta)
+    }
+
+    fn say(bot: &mut QueueBot, author: &DiscordUser, message: &str) -> Option<Outcome> {
+        bot.on_message(Message { id: 0, guild_id: 1, author, message: String::from(message), mentions: None })
+    }
+
+    #[test]
+    fn commands_need_the_right_role() {
+        let (mut bot, transport, student, ta) = setup();
+        // Students get the same reply as for a command which doesn't exist
+        assert_eq!(say(&mut bot, &student, "!q secret"), Some(Outcome::Rejected));
+        assert_eq!(say(&mut bot, &student, "!q shh"), Some(Outcome::Rejected));
+        assert_eq!(say(&mut bot, &student, "!q nope"), Some(Outcome::Rejected));
+        let sent = transport.take();
+        assert!(sent.iter().all(|s| *s == sent[2]));
+
+        assert_eq!(say(&mut bot, &ta, "!q SHH a b"), Some(Outcome::Ok));
+        assert_eq!(transport.take(), [Sent::Channel(String::from("`!q secret` - Only for TAs a b"))]);
+    }
+
+    #[test]
+    fn help_only_lists_commands_the_user_can_run() {
+        let (mut bot, transport, student, ta) = setup();
+        say(&mut bot, &student, "!q help");
+        say(&mut bot, &ta, "!q help");
+        let sent = transport.take();
+        let (student_help, ta_help) = match (&sent[0], &sent[1]) {
+            (Sent::Channel(s), Sent::Channel(t)) => (s, t),
+            _ => panic!("help should be sent to the channel"),
+        };
+        assert!(student_help.starts_with("<@Kapua> Commands:\n`!q ping` - Check to see if the bot is working\n`!q help`"));
+        assert!(student_help.contains("`!q join [topic]`"));
+        assert!(!student_help.contains("`!q next`"));
+        assert!(!student_help.contains("`!q secret`"));
+
+        // TAs see everything students do, then their own commands
+        let ta_lines = ta_help.lines().skip(1).collect::<Vec<&str>>();
+        assert!(student_help.lines().skip(1).all(|line| ta_lines.contains(&line)));
+        assert!(ta_help.contains("`!q next"));
+        assert_eq!(ta_lines.last(), Some(&"`!q secret` - Only for TAs"));
+        // Each command is listed once even though it has aliases
+        assert_eq!(ta_help.matches("`!q position`").count(), 1);
+    }
+}
@@ -0,0 +1,219 @@
+//! The scripted office hours session which shows off every command.
+//! The `queuebot` binary runs it (printing everything the bot sends) and
+//! `tests/session.rs` checks what the bot sends after each step, so both
+//! always use the same session.
+//!
+//! Ben runs CS 120 on his own, then Dana (who is only a TA there) takes
+//! the CS 150 queue, which uses help threads, reminders and feedback surveys
+
+use chrono::Duration;
+
+use crate::guild::GuildConfig;
+use crate::hours::OfficeHours;
+use crate::interactions::{Interaction, InteractionKind, JOIN_BUTTON, JOIN_EMOJI, LEAVE_BUTTON};
+use crate::users::{SnowflakeGenerator, UserRegistry};
+use crate::{DiscordUser, Message, QueueBot, User};
+
+/// IDs of the mock servers the bot is running in (one per course)
+pub const CS120_GUILD: u64 = 943_412_115_532_513_290;
+pub const CS150_GUILD: u64 = 943_412_187_238_412_300;
+
+/// Everyone who takes part in the session.
+/// Ben is a TA everywhere (and runs CS 150) and Dana is only a TA in CS 150
+pub struct People {
+    pub ben: DiscordUser,
+    pub kapua: DiscordUser,
+    pub bennett: DiscordUser,
+    pub russ: DiscordUser,
+    pub jordan: DiscordUser,
+    pub dana: DiscordUser,
+}
+
+/// Create a bot which knows about everyone and is set up for both courses.
+/// Anything which is kept between runs (such as history or the audit log)
+/// is left for the caller to add
+pub fn setup() -> (QueueBot, People) {
+    let mut users = UserRegistry::new();
+    let people = People {
+        ben: users.register("Ben", "0001", true).unwrap(),
+        kapua: users.register("Kapua", "0002", false).unwrap(),
+        bennett: users.register("Bennett", "0003", false).unwrap(),
+        russ: users.register("Russ", "0004", false).unwrap(),
+        jordan: users.register("Jordan", "0003", false).unwrap(),
+        dana: users.register("Dana", "0005", false).unwrap(),
+    };
+    let bot = QueueBot::new()
+        // CS 120 holds office hours in person so it doesn't need help threads
+        .with_guild(CS120_GUILD, GuildConfig::new("CS 120")
+            .with_retention(Duration::days(365))
+            .with_help_threads(false))
+        // Ben runs CS 150 so he can see how students rate its TAs
+        .with_guild(CS150_GUILD, GuildConfig::new("CS 150")
+            .with_ta(people.dana.uuid)
+            .with_head_ta(people.ben.uuid)
+            .with_reminders(Some(2), Duration::seconds(30))
+            .with_no_shows(1, 2)
+            .with_reply_cleanup()
+            .with_surveys())
+        .with_users(users)
+        .with_command(OfficeHours::new("Mon/Wed 2-4pm and Fri 10am-12pm in Boyd 307"));
+    (bot, people)
+}
+
+/// Run the whole session. `after` is called once the bot has handled each
+/// message, edit, deletion or click (so a test can check what was sent)
+pub fn run<F: FnMut(&mut QueueBot)>(bot: &mut QueueBot, people: &People, after: F) {
+    let p = people;
+    // Every message sent within the demo gets its own ID like it would on Discord
+    let mut demo = Demo { bot, ids: SnowflakeGenerator::new(1, 1), after };
+    let guild = CS120_GUILD;
+
+    // Ben pings the bot to make sure it's working (responds with "Pong!")
+    demo.say(guild, &p.ben, "!q ping");
+
+    // A student asks when office hours are (a course-specific command)
+    demo.say(guild, &p.kapua, "!q hours");
+
+    // Every student joins the queue and Ben lists it
+    for student in [&p.kapua, &p.bennett, &p.russ, &p.jordan] {
+        demo.say(guild, student, "!q join");
+    }
+    demo.say(guild, &p.ben, "!q list");
+
+    // Ben should not be removed from the queue (since he never joined)
+    demo.say(guild, &p.ben, "!q leave");
+
+    // Russ decides to leave the queue then checks he's not in it anymore
+    demo.say(guild, &p.russ, "!q leave");
+    demo.say(guild, &p.russ, "!q pos");
+
+    // Jordan prefers Spanish so he changes his language and checks his position
+    demo.say(guild, &p.jordan, "!q language es");
+    demo.say(guild, &p.jordan, "!q position");
+
+    // Ben lists the queue again after Russ leaves, calls the next student then clears it
+    demo.say(guild, &p.ben, "!q list");
+    demo.say(guild, &p.ben, "!q next");
+    demo.say(guild, &p.ben, "!q clear");
+
+    // Russ lists the queue in confusion
+    demo.say(guild, &p.russ, "!q list");
+
+    // Ben adds Kapua, Bennett, Russ, and Jordan back into the queue
+    for student in [&p.kapua, &p.bennett, &p.russ, &p.jordan] {
+        demo.mention(guild, &p.ben, "!q add", student);
+    }
+    demo.say(guild, &p.russ, "!q list");
+
+    // Ben removes (still confused) Russ from the queue
+    demo.mention(guild, &p.ben, "!q remove", &p.russ);
+    demo.say(guild, &p.kapua, "!q list");
+
+    // Russ complains he was removed unfairly so Ben checks the audit log
+    demo.mention(guild, &p.ben, "!q audit", &p.russ);
+
+    // Kapua checks how long she waited the last time she was helped
+    demo.say(guild, &p.kapua, "!q history");
+
+    // Jordan asks for his history and settings to be deleted
+    demo.say(guild, &p.jordan, "!q forget me");
+
+    // Kapua changes her name but keeps her spot in the queue
+    let kapua = DiscordUser { name: String::from("Kapua_K"), ..p.kapua.clone() };
+    demo.say(guild, &kapua, "!q position");
+    demo.say(guild, &p.ben, "!q list");
+
+    // CS 150 runs its office hours from the same bot but has its own queue and TAs.
+    // Dana starts her shift
+    let guild = CS150_GUILD;
+    demo.say(guild, &p.dana, "!q duty on");
+
+    // Russ says what he needs help with but then edits his message to fix it
+    let join = demo.say(guild, &p.russ, "!q join lab2");
+    demo.edit(join, guild, &p.russ, "!q join lab3 part b");
+
+    // Kapua looks up a lab handout with a command added by scripts/lab-links.rhai
+    // (without the scripts directory it is an unknown command)
+    demo.say(guild, &p.kapua, "!q labs lab3");
+
+    // Dana lists the queue then deletes her message, which also
+    // deletes the bot's reply (CS 150 has reply cleanup turned on)
+    let list = demo.say(guild, &p.dana, "!q list");
+    demo.delete(guild, list);
+
+    // Dana pins a message showing the queue. Bennett clicks its Join button,
+    // Kapua joins by reacting to it and then Bennett changes his mind
+    demo.say(guild, &p.dana, "!q pin");
+    let pinned = demo.bot.guild(guild).status_message.expect("the queue was just pinned");
+    demo.click(guild, pinned, &p.bennett, InteractionKind::Button(String::from(JOIN_BUTTON)));
+    demo.click(guild, pinned, &p.kapua, InteractionKind::Reaction(String::from(JOIN_EMOJI)));
+    demo.click(guild, pinned, &p.bennett, InteractionKind::Button(String::from(LEAVE_BUTTON)));
+
+    // Dana calls Russ (opening a private thread with him) but he has wandered off,
+    // so she marks him as a no-show which puts him back behind Kapua
+    demo.say(guild, &p.dana, "!q next");
+    demo.say(guild, &p.dana, "!q noshow");
+
+    // Dana helps Kapua within their thread instead and archives it once she's done,
+    // which asks Kapua to rate the help. Kapua answers with a comment (which the
+    // bot deletes from the channel), and Ben checks how his TAs are doing
+    demo.say(guild, &p.dana, "!q next");
+    demo.say(guild, &p.dana, "!q done");
+    demo.say(guild, &p.kapua, "!q feedback 5 Dana explained recursion really well");
+    demo.say(guild, &p.ben, "!q feedback summary");
+
+    // Dana's shift ends while Russ is still waiting, so he is warned that nobody is on duty
+    demo.say(guild, &p.dana, "!q duty off");
+
+    // Dana offers appointments on Friday morning and Bennett books the first one.
+    // When it starts he'll be put at the front of the queue
+    demo.say(guild, &p.dana, "!q slots add Fri 10:00-11:00 20m");
+    demo.say(guild, &p.bennett, "!q book");
+
+    // Dana isn't a TA in CS 120 so she can't pull students off its queue
+    demo.say(CS120_GUILD, &p.dana, "!q next");
+}
+
+/// Sends the session's events to the bot, calling `after` once each is handled
+struct Demo<'a, F> {
+    bot: &'a mut QueueBot,
+    ids: SnowflakeGenerator,
+    after: F,
+}
+
+impl<F: FnMut(&mut QueueBot)> Demo<'_, F> {
+    /// Send a message. Returns its ID (so it can be edited or deleted later)
+    fn say(&mut self, guild: u64, author: &DiscordUser, text: &str) -> u64 {
+        self.send(guild, author, String::from(text), None)
+    }
+
+    /// Send a command which mentions `user`
+    fn mention(&mut self, guild: u64, author: &DiscordUser, command: &str, user: &DiscordUser) -> u64 {
+        self.send(guild, author, format!("{} {}", command, user.get_mention()), Some(vec![user.clone()]))
+    }
+
+    fn send(&mut self, guild: u64, author: &DiscordUser, message: String, mentions: Option<Vec<DiscordUser>>) -> u64 {
+        let id = self.ids.next_id();
+        self.bot.on_message(Message { id, guild_id: guild, author, message, mentions });
+        (self.after)(self.bot);
+        id
+    }
+
+    /// Edit the message with the given ID
+    fn edit(&mut self, id: u64, guild: u64, author: &DiscordUser, text: &str) {
+        self.bot.on_message_edit(Message { id, guild_id: guild, author, message: String::from(text), mentions: None });
+        (self.after)(self.bot);
+    }
+
+    /// Delete the message with the given ID
+    fn delete(&mut self, guild: u64, id: u64) {
+        self.bot.on_message_delete(guild, id);
+        (self.after)(self.bot);
+    }
+
+    /// Click a button on (or react to) a message
+    fn click(&mut self, guild: u64, message_id: u64, user: &DiscordUser, kind: InteractionKind) {
+        self.bot.on_interaction(Interaction { guild_id: guild, message_id, user, kind });
+        (self.after)(self.bot);
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::{HashMap, VecDeque};
+
+/// How many commands are remembered for edits and deletions.
+/// Discord lets messages be edited forever, but students only ever
+/// fix a typo shortly after sending, so older commands are forgotten
+const MAX_TRACKED: usize = 1000;
+
+/// A command which was run, remembered so that editing or deleting the
+/// message which ran it can be handled (see `QueueBot::on_message_edit`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrackedCommand {
+    pub guild_id: u64,
+    pub author_id: u64,
+    /// The name of the command which ran (`join` even if it was run with an alias)
+    pub command: String,
+    /// IDs of the messages the bot sent in reply
+    pub replies: Vec<u64>,
+}
+
+/// The most recent commands, keyed by the ID of the message which ran them.
+/// Once more than `MAX_TRACKED` are remembered the oldest are dropped
+#[derive(Default)]
+pub struct RecentCommands {
+    commands: HashMap<u64, TrackedCommand>,
+    /// Message IDs, oldest first
+    order: VecDeque<u64>,
+}
+
+impl RecentCommands {
+    /// Remember the command run by message `id`
+    pub fn track(&mut self, id: u64, command: TrackedCommand) {
+        if self.commands.insert(id, command).is_none() {
+            self.order.push_back(id);
+        }
+        while self.order.len() > MAX_TRACKED {
+            if let Some(oldest) = self.order.pop_front() {
+                self.commands.remove(&oldest);
+            }
+        }
+    }
+
+    /// The command run by message `id` (if it is still remembered)
+    pub fn get(&self, id: u64) -> Option<&TrackedCommand> {
+        self.commands.get(&id)
+    }
+
+    /// Stop remembering the command run by message `id` and return it
+    pub fn remove(&mut self, id: u64) -> Option<TrackedCommand> {
+        let command = self.commands.remove(&id)?;
+        self.order.retain(|i| *i != id);
+        Some(command)
+    }
+}
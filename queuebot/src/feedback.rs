@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+/// Where survey answers are kept unless told otherwise
+pub const DEFAULT_FEEDBACK_PATH: &str = "queuebot-feedback.jsonl";
+
+/// Reactions students click to rate the help they got, from 1 to 5
+pub const RATING_EMOJI: [&str; 5] = ["1️⃣", "2️⃣", "3️⃣", "4️⃣", "5️⃣"];
+
+/// The longest comment a student can leave with their rating (in characters)
+pub const MAX_COMMENT_LENGTH: usize = 500;
+
+/// The rating a reaction stands for (`None` if it isn't one of `RATING_EMOJI`)
+pub fn rating_of(emoji: &str) -> Option<u8> {
+    RATING_EMOJI.iter().position(|e| *e == emoji).map(|i| i as u8 + 1)
+}
+
+/// A student's answer to the survey sent after they were helped.
+/// Who gave it isn't stored, and only the day is kept (rather than the
+/// exact time) so it can't be matched up with a session in the history
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub guild_id: u64,
+    /// ID of the TA who helped the student
+    pub ta_id: u64,
+    pub date: NaiveDate,
+    /// From 1 (worst) to 5 (best)
+    pub rating: u8,
+    pub comment: Option<String>,
+}
+
+/// A survey which was sent to a student but hasn't been answered yet.
+/// These are only kept in memory, so they're lost if the bot restarts
+#[derive(Clone, Debug, PartialEq)]
+pub struct Survey {
+    pub guild_id: u64,
+    pub ta_id: u64,
+    pub student_id: u64,
+    /// ID of the direct message asking the student (which they can react to)
+    pub message_id: u64,
+}
+
+/// How a single TA has been rated
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TaSummary {
+    /// How many times they were given each rating (index 0 is a rating of 1)
+    pub ratings: [usize; 5],
+    /// Every comment left about them, newest first
+    pub comments: Vec<String>,
+}
+
+impl TaSummary {
+    /// How many ratings they were given
+    pub fn count(&self) -> usize {
+        self.ratings.iter().sum()
+    }
+
+    /// Their mean rating (0 if they haven't been rated)
+    pub fn average(&self) -> f64 {
+        let total = self.ratings.iter().enumerate().map(|(i, n)| (i + 1) * n).sum::<usize>();
+        match self.count() {
+            0 => 0.0,
+            count => total as f64 / count as f64,
+        }
+    }
+}
+
+/// Every survey answer along with the surveys still waiting for one.
+/// Answers are stored one per line as JSON like `History`
+#[derive(Default)]
+pub struct Feedback {
+    path: Option<PathBuf>,
+    responses: Vec<Response>,
+    pending: Vec<Survey>,
+}
+
+impl Feedback {
+    /// Load the answers stored at `path` (which doesn't have to exist yet).
+    /// New answers are written back to the same file
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Feedback> {
+        let path = path.as_ref().to_path_buf();
+        let mut responses = Vec::new();
+        if path.exists() {
+            for line in BufReader::new(File::open(&path)?).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                responses.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(Feedback { path: Some(path), responses, pending: Vec::new() })
+    }
+
+    /// Remember that a student was sent a survey. Any survey they hadn't
+    /// answered from the same server is replaced, since it's about older help
+    pub fn ask(&mut self, survey: Survey) {
+        self.pending.retain(|s| !(s.guild_id == survey.guild_id && s.student_id == survey.student_id));
+        self.pending.push(survey);
+    }
+
+    /// Remove and return the survey a student is answering. With a `message_id`
+    /// (when they reacted to it) it has to be that survey, otherwise it's
+    /// the most recent one they were sent
+    pub fn take_survey(&mut self, student: u64, message_id: Option<u64>) -> Option<Survey> {
+        let index = self.pending.iter().rposition(|s| s.student_id == student && message_id.is_none_or(|id| s.message_id == id))?;
+        Some(self.pending.remove(index))
+    }
+
+    /// Add an answer
+    pub fn record(&mut self, response: Response) -> io::Result<()> {
+        let result = match &self.path {
+            None => Ok(()),
+            Some(path) => OpenOptions::new().create(true).append(true).open(path)
+                .and_then(|mut file| writeln!(file, "{}", serde_json::to_string(&response)?)),
+        };
+        // Keep the answer in memory even if it couldn't be saved
+        self.responses.push(response);
+        result
+    }
+
+    /// Forget every survey a student hasn't answered (for `!q forget me`).
+    /// Answers are kept since they can't be traced back to anyone
+    pub fn forget(&mut self, student: u64) {
+        self.pending.retain(|s| s.student_id != student);
+    }
+
+    /// Delete every answer within a server which was given before `cutoff`.
+    /// Returns how many answers were deleted
+    pub fn prune(&mut self, guild: u64, cutoff: DateTime<Utc>) -> io::Result<usize> {
+        let before = self.responses.len();
+        self.responses.retain(|r| !(r.guild_id == guild && r.date < cutoff.date_naive()));
+        let deleted = before - self.responses.len();
+        if deleted > 0 {
+            self.save()?;
+        }
+        Ok(deleted)
+    }
+
+    /// How every TA within a server has been rated, keyed by their ID
+    pub fn summarise(&self, guild: u64) -> BTreeMap<u64, TaSummary> {
+        let mut summaries = BTreeMap::<u64, TaSummary>::new();
+        for response in self.responses.iter().rev().filter(|r| r.guild_id == guild) {
+            let summary = summaries.entry(response.ta_id).or_default();
+            summary.ratings[(response.rating.clamp(1, 5) - 1) as usize] += 1;
+            summary.comments.extend(response.comment.clone());
+        }
+        summaries
+    }
+
+    /// Every answer that has been recorded (oldest first)
+    pub fn all(&self) -> &[Response] {
+        &self.responses
+    }
+
+    /// Rewrite the whole file (see `History::save`)
+    fn save(&self) -> io::Result<()> {
+        let path = match &self.path {
+            None => return Ok(()),
+            Some(p) => p,
+        };
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        let mut file = File::create(&temp)?;
+        for response in self.responses.iter() {
+            writeln!(file, "{}", serde_json::to_string(response)?)?;
+        }
+        file.sync_all()?;
+        fs::rename(temp, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn response(ta: u64, day: u32, rating: u8, comment: Option<&str>) -> Response {
+        Response { guild_id: 1, ta_id: ta, date: NaiveDate::from_ymd_opt(2022, 2, day).unwrap(), rating, comment: comment.map(String::from) }
+    }
+
+    #[test]
+    fn answers_are_summarised_per_ta() {
+        let path = std::env::temp_dir().join(format!("queuebot-feedback-test-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut feedback = Feedback::open(&path).unwrap();
+        feedback.record(response(10, 1, 5, Some("Really clear"))).unwrap();
+        feedback.record(response(10, 2, 2, None)).unwrap();
+        feedback.record(response(10, 3, 4, Some("Thanks!"))).unwrap();
+        feedback.record(response(11, 3, 3, None)).unwrap();
+
+        let mut feedback = Feedback::open(&path).unwrap();
+        let summaries = feedback.summarise(1);
+        assert_eq!(summaries[&10].ratings, [0, 1, 0, 1, 1]);
+        assert_eq!((summaries[&10].count(), summaries[&10].average()), (3, 11.0 / 3.0));
+        assert_eq!(summaries[&10].comments, ["Thanks!", "Really clear"]);
+        assert_eq!(summaries[&11].average(), 3.0);
+        assert!(feedback.summarise(2).is_empty());
+
+        assert_eq!(feedback.prune(1, Utc.with_ymd_and_hms(2022, 2, 3, 9, 0, 0).unwrap()).unwrap(), 2);
+        assert_eq!(Feedback::open(&path).unwrap().summarise(1)[&10].comments, ["Thanks!"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn surveys_are_answered_once() {
+        let mut feedback = Feedback::default();
+        let survey = |guild_id, student_id, message_id| Survey { guild_id, ta_id: 10, student_id, message_id };
+        feedback.ask(survey(1, 20, 100));
+        feedback.ask(survey(2, 20, 101));
+        feedback.ask(survey(1, 21, 102));
+        // Reactions have to be on the student's own survey
+        assert_eq!(feedback.take_survey(21, Some(100)), None);
+        assert_eq!(feedback.take_survey(20, Some(100)), Some(survey(1, 20, 100)));
+        assert_eq!(feedback.take_survey(20, Some(100)), None);
+        // Typed answers are for the latest survey
+        feedback.ask(survey(1, 20, 103));
+        assert_eq!(feedback.take_survey(20, None), Some(survey(1, 20, 103)));
+        feedback.forget(20);
+        assert_eq!(feedback.take_survey(20, None), None);
+        assert_eq!(rating_of("4️⃣"), Some(4));
+        assert_eq!(rating_of("🙋"), None);
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+
+use crate::bookings::Bookings;
+
+use crate::locale::Locale;
+use crate::queue::IndexedQueue;
+use crate::reminders::Milestone;
+
+/// A private thread opened for a TA to help a student in (see `GuildConfig::help_threads`)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HelpThread {
+    pub id: u64,
+    pub student: u64,
+}
+
+/// The longest topic a student can give when joining (in characters)
+pub const MAX_TOPIC_LENGTH: usize = 100;
+
+/// Settings for a single Discord server (which Discord calls a guild).
+/// One bot can serve several courses, each with its own server, so
+/// every server is set up separately
+pub struct GuildConfig {
+    /// Name of the server (shown on the web dashboard)
+    pub name: String,
+
+    /// The language used for users who haven't picked one
+    pub locale: Locale,
+
+    /// IDs of users who are TAs within this server. This is on top of
+    /// anyone whose roles already mark them as a TA (`User::is_ta`)
+    pub tas: HashSet<u64>,
+
+    /// IDs of TAs who run the course. They can do everything a TA can
+    /// and can also see how every TA has been rated (`!q feedback summary`)
+    pub head_tas: HashSet<u64>,
+
+    /// Students are sent a direct message once they reach this position
+    /// (and again once they reach the front). `None` turns off the first reminder
+    pub reminder_position: Option<usize>,
+
+    /// How long a student has to stay at a position before they are reminded.
+    /// This stops students being messaged while the queue is quickly changing
+    pub reminder_delay: Duration,
+
+    /// How many students are let in ahead of a no-show when they're put back into
+    /// the queue. This counts from the front rather than from where they were, so
+    /// they go back to position `noshow_skip + 1` (or the back if the queue is shorter)
+    pub noshow_skip: usize,
+
+    /// Students are removed from the queue (instead of moved back) once they
+    /// have been a no-show this many times within the same day (0 is treated as 1)
+    pub noshow_strikes: usize,
+
+    /// How long history and audit log entries from this server are kept.
+    /// `None` keeps them forever
+    pub retention: Option<Duration>,
+
+    /// Delete the bot's replies to a command when the message
+    /// which ran the command is deleted
+    pub cleanup_replies: bool,
+
+    /// Open a private thread for the student and TA whenever a student is called
+    pub help_threads: bool,
+
+    /// The timezone appointment slots are added and shown in (`!q slots add`)
+    pub timezone: FixedOffset,
+
+    /// Students can't cancel an appointment once it starts within this long
+    pub cancel_deadline: Duration,
+
+    /// Ask students to rate the help they got after `!q done`
+    pub surveys: bool,
+}
+
+impl GuildConfig {
+    /// Create a config for a server with the given name which uses
+    /// English, doesn't have any extra TAs, and reminds students
+    /// straight away when they are third in line
+    pub fn new(name: &str) -> GuildConfig {
+        GuildConfig {
+            name: String::from(name),
+            locale: Locale::English,
+            tas: HashSet::new(),
+            head_tas: HashSet::new(),
+            reminder_position: Some(3),
+            reminder_delay: Duration::zero(),
+            noshow_skip: 2,
+            noshow_strikes: 3,
+            retention: None,
+            cleanup_replies: false,
+            help_threads: true,
+            timezone: FixedOffset::east_opt(0).expect("UTC is a valid offset"),
+            cancel_deadline: Duration::hours(1),
+            surveys: false,
+        }
+    }
+
+    /// Change when students are reminded that they are almost up
+    pub fn with_reminders(mut self, position: Option<usize>, delay: Duration) -> GuildConfig {
+        self.reminder_position = position;
+        self.reminder_delay = delay;
+        self
+    }
+
+    /// Change how far no-shows are moved back and
+    /// how many times they can miss their turn before being removed
+    pub fn with_no_shows(mut self, skip: usize, strikes: usize) -> GuildConfig {
+        self.noshow_skip = skip;
+        self.noshow_strikes = strikes.max(1);
+        self
+    }
+
+    /// Automatically delete history and audit log entries once they are older than `period`
+    pub fn with_retention(mut self, period: Duration) -> GuildConfig {
+        self.retention = Some(period);
+        self
+    }
+
+    /// Turn private help threads on or off (they are on by default)
+    pub fn with_help_threads(mut self, enabled: bool) -> GuildConfig {
+        self.help_threads = enabled;
+        self
+    }
+
+    /// Change the timezone of appointment slots (UTC by default) and how long
+    /// before an appointment students stop being able to cancel it (an hour by default)
+    pub fn with_appointments(mut self, timezone: FixedOffset, cancel_deadline: Duration) -> GuildConfig {
+        self.timezone = timezone;
+        self.cancel_deadline = cancel_deadline;
+        self
+    }
+
+    /// Privately ask students to rate the help they got once a TA runs `!q done`.
+    /// Answers are anonymous; head TAs see them with `!q feedback summary`
+    pub fn with_surveys(mut self) -> GuildConfig {
+        self.surveys = true;
+        self
+    }
+
+    /// Delete the bot's replies to commands whose message is deleted
+    pub fn with_reply_cleanup(mut self) -> GuildConfig {
+        self.cleanup_replies = true;
+        self
+    }
+
+    /// The milestones a student at `index` within the queue has reached
+    pub fn milestones_at(&self, index: usize) -> Vec<Milestone> {
+        let mut reached = Vec::new();
+        if index == 0 {
+            reached.push(Milestone::Front);
+        }
+        if self.reminder_position.is_some_and(|p| index < p) {
+            reached.push(Milestone::AlmostUp);
+        }
+        reached
+    }
+
+    /// Make the user with the given ID a TA within this server
+    pub fn with_ta(mut self, id: u64) -> GuildConfig {
+        self.tas.insert(id);
+        self
+    }
+
+    /// Make the user with the given ID a head TA within this server
+    pub fn with_head_ta(mut self, id: u64) -> GuildConfig {
+        self.head_tas.insert(id);
+        self
+    }
+}
+
+/// Everything the bot keeps track of for a single server.
+/// Nothing in here is shared with any other server
+pub struct Guild {
+    pub config: GuildConfig,
+
+    /// Users are added to the back of the queue
+    /// and are removed from the front
+    /// (`IndexedQueue` can find anyone's position without searching the whole queue)
+    /// Only user IDs are stored so renaming doesn't affect someone's spot
+    pub queue: IndexedQueue,
+
+    /// The milestones each student in the queue has already been reminded about
+    /// (or had already reached when they joined). Students are removed
+    /// from this when they leave the queue
+    pub reminded: HashMap<u64, HashSet<Milestone>>,
+
+    /// The student each TA most recently called with `!q next`
+    /// (keyed by the TA's ID). Used to find who `!q noshow` is about
+    pub called: HashMap<u64, u64>,
+
+    /// The help thread each TA has open (keyed by the TA's ID)
+    pub threads: HashMap<u64, HelpThread>,
+
+    /// TAs who are on duty (`!q duty on`), in the order they came on
+    pub on_duty: Vec<u64>,
+
+    /// How many students have been handed out to on-duty TAs
+    /// (used to take turns, see `next_on_duty`)
+    handed_out: usize,
+
+    /// When each student in the queue joined it (used for their history)
+    pub joined: HashMap<u64, DateTime<Utc>>,
+
+    /// What each student in the queue needs help with (`!q join <topic>`)
+    pub topics: HashMap<u64, String>,
+
+    /// The ID of the pinned message showing the queue (posted by `!q pin`)
+    pub status_message: Option<u64>,
+
+    /// What the pinned message currently says, so it's only edited when the queue changes
+    pub status_text: String,
+
+    /// Appointment slots which haven't started yet
+    pub bookings: Bookings,
+}
+
+impl Guild {
+    /// Create a server with an empty queue
+    pub fn new(config: GuildConfig) -> Guild {
+        Guild {
+            config,
+            queue: IndexedQueue::new(),
+            reminded: HashMap::new(),
+            called: HashMap::new(),
+            threads: HashMap::new(),
+            on_duty: Vec::new(),
+            handed_out: 0,
+            joined: HashMap::new(),
+            topics: HashMap::new(),
+            status_message: None,
+            status_text: String::new(),
+            bookings: Bookings::default(),
+        }
+    }
+
+    /// Add a student to the back of the queue. They aren't reminded about
+    /// any milestones they already reached by joining (such as joining an empty queue)
+    pub fn push_back(&mut self, user: u64, now: DateTime<Utc>) {
+        let index = self.queue.len();
+        if self.queue.push_back(user) {
+            self.track(user, index, now);
+        }
+    }
+
+    /// Same as `push_back` but the student is put at `index` (or the back
+    /// if the queue is shorter than that)
+    pub fn insert(&mut self, index: usize, user: u64, now: DateTime<Utc>) {
+        let index = index.min(self.queue.len());
+        if self.queue.insert(index, user) {
+            self.track(user, index, now);
+        }
+    }
+
+    /// Move a student who is already in the queue to `index` (or the back
+    /// if the queue is shorter than that). They keep their original join time
+    /// but are reminded about milestones again as if they had just joined there
+    pub fn move_to(&mut self, user: u64, index: usize) {
+        if !self.queue.move_to(user, index) {
+            return;
+        }
+        let index = self.queue.position(user).unwrap_or(index);
+        let reached = self.config.milestones_at(index);
+        self.reminded.insert(user, reached.into_iter().collect());
+    }
+
+    /// Take a student out of the queue (if they are in it).
+    /// Returns when they joined
+    pub fn remove(&mut self, user: u64) -> Option<DateTime<Utc>> {
+        self.queue.remove(user)?;
+        self.forget(user)
+    }
+
+    /// Take the student at the front out of the queue.
+    /// Returns their ID and when they joined
+    pub fn pop_front(&mut self) -> Option<(u64, Option<DateTime<Utc>>)> {
+        let user = self.queue.pop_front()?;
+        Some((user, self.forget(user)))
+    }
+
+    /// Empty the queue. Returns the ID of everyone who was in it
+    /// (front first) and when they joined
+    pub fn clear(&mut self) -> Vec<(u64, Option<DateTime<Utc>>)> {
+        self.queue.clear().into_iter().map(|user| (user, self.forget(user))).collect()
+    }
+
+    /// Start tracking a student who was just put into the queue at `index`
+    fn track(&mut self, user: u64, index: usize, now: DateTime<Utc>) {
+        self.joined.insert(user, now);
+        let reached = self.config.milestones_at(index);
+        self.reminded.insert(user, reached.into_iter().collect());
+    }
+
+    /// Put a TA on duty. Returns false if they already were
+    pub fn go_on_duty(&mut self, ta: u64) -> bool {
+        match self.on_duty.contains(&ta) {
+            true => false,
+            false => {
+                self.on_duty.push(ta);
+                true
+            },
+        }
+    }
+
+    /// Take a TA off duty. Returns false if they weren't on duty
+    pub fn go_off_duty(&mut self, ta: u64) -> bool {
+        let before = self.on_duty.len();
+        self.on_duty.retain(|id| *id != ta);
+        self.on_duty.len() != before
+    }
+
+    /// The on-duty TA whose turn it is to help the next student.
+    /// TAs take turns in the order they came on duty. `None` if nobody is on duty
+    pub fn next_on_duty(&mut self) -> Option<u64> {
+        if self.on_duty.is_empty() {
+            return None;
+        }
+        let ta = self.on_duty[self.handed_out % self.on_duty.len()];
+        self.handed_out += 1;
+        Some(ta)
+    }
+
+    /// Set (or with `None`, clear) what a student in the queue needs help with.
+    /// Topics are cut off at `MAX_TOPIC_LENGTH` characters.
+    /// Returns false if the student isn't in the queue
+    pub fn set_topic(&mut self, user: u64, topic: Option<&str>) -> bool {
+        if !self.queue.contains(user) {
+            return false;
+        }
+        match topic.map(str::trim).filter(|t| !t.is_empty()) {
+            Some(t) => self.topics.insert(user, t.chars().take(MAX_TOPIC_LENGTH).collect()),
+            None => self.topics.remove(&user),
+        };
+        true
+    }
+
+    /// Drop what is tracked about a student who is no longer in the queue
+    /// (so they're reminded again if they rejoin). Returns when they joined
+    fn forget(&mut self, user: u64) -> Option<DateTime<Utc>> {
+        self.reminded.remove(&user);
+        self.topics.remove(&user);
+        self.joined.remove(&user)
+    }
+}
@@ -0,0 +1,237 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+
+/// Where help history is stored if nothing else is specified
+pub const DEFAULT_HISTORY_PATH: &str = "queuebot-history.jsonl";
+
+/// How a student's time in the queue ended
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionOutcome {
+    /// A TA called them with `!q next`
+    Helped,
+    /// A TA called them but they didn't show up (`!q noshow`)
+    NoShow,
+    /// They left with `!q leave`
+    Left,
+    /// A TA removed them with `!q remove` (or they had too many no-shows)
+    Removed,
+    /// They were still waiting when the queue was cleared
+    Cleared,
+}
+
+/// A single stay within a queue, from joining until leaving it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Session {
+    /// ID of the server the queue belongs to
+    pub guild_id: u64,
+    /// ID of the student
+    pub user_id: u64,
+    pub joined: DateTime<Utc>,
+    /// When a TA called them (only set if a TA did)
+    pub called: Option<DateTime<Utc>>,
+    /// ID of the TA who called (or removed) them
+    pub ta_id: Option<u64>,
+    pub ended: DateTime<Utc>,
+    pub outcome: SessionOutcome,
+}
+
+impl Session {
+    /// How long the student waited before being called (or leaving)
+    pub fn waited(&self) -> Duration {
+        self.called.unwrap_or(self.ended) - self.joined
+    }
+}
+
+/// Turn a duration into something short and readable such as `1h 05m` or `4m 30s`
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.num_seconds().max(0);
+    match (seconds / 3600, (seconds % 3600) / 60, seconds % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {:02}s", m, s),
+        (h, m, _) => format!("{}h {:02}m", h, m),
+    }
+}
+
+/// Parse a length of time such as `30s`, `5m` or `2h`.
+/// Lengths too long to be stored (such as `9999999999999999m`) are invalid
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration: {} (expected something like 30s, 5m or 2h)", text);
+    // Split before the last character (which might not be a single byte)
+    let (number, unit) = text.split_at(text.char_indices().last().map_or(0, |(i, _)| i));
+    let number = number.parse::<i64>().map_err(|_| invalid())?;
+    let duration = match unit {
+        "s" => Duration::try_seconds(number),
+        "m" => Duration::try_minutes(number),
+        "h" => Duration::try_hours(number),
+        _ => None,
+    };
+    duration.ok_or_else(invalid)
+}
+
+/// Every finished queue session, for every student in every server.
+///
+/// Sessions are kept in memory so they can be searched quickly and, if
+/// the history was opened from a file, are also appended to that file as
+/// JSON-lines (one JSON object per line) so they survive restarts.
+/// Unlike the audit log, history can be deleted (see `purge`)
+#[derive(Default)]
+pub struct History {
+    path: Option<PathBuf>,
+    sessions: Vec<Session>,
+}
+
+impl History {
+    /// Load the history stored at `path` (which doesn't have to exist yet).
+    /// New sessions are written back to the same file
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<History> {
+        let path = path.as_ref().to_path_buf();
+        let mut sessions = Vec::new();
+        if path.exists() {
+            for line in BufReader::new(File::open(&path)?).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                sessions.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(History { path: Some(path), sessions })
+    }
+
+    /// Add a finished session
+    pub fn record(&mut self, session: Session) -> io::Result<()> {
+        let result = match &self.path {
+            None => Ok(()),
+            Some(path) => OpenOptions::new().create(true).append(true).open(path)
+                .and_then(|mut file| writeln!(file, "{}", serde_json::to_string(&session)?)),
+        };
+        // Keep the session in memory even if it couldn't be saved
+        self.sessions.push(session);
+        result
+    }
+
+    /// Change the outcome of the most recent session within the server in which
+    /// the student with the ID `user` was called by a TA (used when they turn out
+    /// to be a no-show). Visits they have made since, such as rejoining and
+    /// leaving before the TA marked them, are left alone
+    pub fn amend_last(&mut self, guild: u64, user: u64, outcome: SessionOutcome) -> io::Result<()> {
+        let called = |s: &&mut Session| s.guild_id == guild && s.user_id == user && s.called.is_some();
+        if let Some(session) = self.sessions.iter_mut().rev().find(called) {
+            session.outcome = outcome;
+            return self.save();
+        }
+        Ok(())
+    }
+
+    /// Every session of a student within a server (oldest first)
+    pub fn of(&self, guild: u64, user: u64) -> Vec<&Session> {
+        self.sessions.iter().filter(|s| s.guild_id == guild && s.user_id == user).collect()
+    }
+
+    /// How many times a student has been a no-show within a server on the same day as `now`
+    pub fn no_shows_on(&self, guild: u64, user: u64, now: DateTime<Utc>) -> usize {
+        self.of(guild, user).iter()
+            .filter(|s| s.outcome == SessionOutcome::NoShow && s.ended.date_naive() == now.date_naive())
+            .count()
+    }
+
+    /// Delete every session of a student within a server.
+    /// Returns how many sessions were deleted
+    pub fn purge(&mut self, guild: u64, user: u64) -> io::Result<usize> {
+        self.delete_where(|s| s.guild_id == guild && s.user_id == user)
+    }
+
+    /// Delete every session of a student within every server (for `!q forget me`).
+    /// Returns how many sessions were deleted
+    pub fn forget(&mut self, user: u64) -> io::Result<usize> {
+        self.delete_where(|s| s.user_id == user)
+    }
+
+    /// Delete every session within a server which ended before `cutoff`.
+    /// Returns how many sessions were deleted
+    pub fn prune(&mut self, guild: u64, cutoff: DateTime<Utc>) -> io::Result<usize> {
+        self.delete_where(|s| s.guild_id == guild && s.ended < cutoff)
+    }
+
+    /// Every session that has been recorded (oldest first)
+    pub fn all(&self) -> &[Session] {
+        &self.sessions
+    }
+
+    /// Delete every session matching `delete`, saving the file if any were
+    fn delete_where<F: Fn(&Session) -> bool>(&mut self, delete: F) -> io::Result<usize> {
+        let before = self.sessions.len();
+        self.sessions.retain(|s| !delete(s));
+        let deleted = before - self.sessions.len();
+        if deleted > 0 {
+            self.save()?;
+        }
+        Ok(deleted)
+    }
+
+    /// Rewrite the whole file. The new contents are written to a temporary
+    /// file first so a crash part way through can't lose the history
+    fn save(&self) -> io::Result<()> {
+        let path = match &self.path {
+            None => return Ok(()),
+            Some(p) => p,
+        };
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        let mut file = File::create(&temp)?;
+        for session in self.sessions.iter() {
+            writeln!(file, "{}", serde_json::to_string(session)?)?;
+        }
+        file.sync_all()?;
+        fs::rename(temp, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(user: u64, outcome: SessionOutcome) -> Session {
+        let now = Utc::now();
+        let called = Some(now).filter(|_| outcome == SessionOutcome::Helped);
+        Session { guild_id: 1, user_id: user, joined: now, called, ta_id: None, ended: now, outcome }
+    }
+
+    #[test]
+    fn survives_restarts_and_can_be_purged() {
+        let path = std::env::temp_dir().join(format!("queuebot-history-test-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut history = History::open(&path).unwrap();
+        history.record(session(1, SessionOutcome::Helped)).unwrap();
+        history.record(session(1, SessionOutcome::Left)).unwrap();
+        history.record(session(1, SessionOutcome::Helped)).unwrap();
+        history.record(session(2, SessionOutcome::Helped)).unwrap();
+        // Student 1 rejoined after being called then left again
+        history.record(session(1, SessionOutcome::Left)).unwrap();
+        history.amend_last(1, 1, SessionOutcome::NoShow).unwrap();
+
+        let mut history = History::open(&path).unwrap();
+        assert_eq!(history.of(1, 1).iter().map(|s| s.outcome).collect::<Vec<_>>(), [
+            SessionOutcome::Helped, SessionOutcome::Left, SessionOutcome::NoShow, SessionOutcome::Left,
+        ]);
+        assert_eq!(history.of(1, 2)[0].outcome, SessionOutcome::Helped);
+        assert_eq!(history.purge(1, 1).unwrap(), 4);
+
+        let history = History::open(&path).unwrap();
+        assert!(history.of(1, 1).is_empty());
+        assert_eq!(history.of(1, 2).len(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(Duration::seconds(45)), "45s");
+        assert_eq!(format_duration(Duration::seconds(270)), "4m 30s");
+        assert_eq!(format_duration(Duration::minutes(65)), "1h 05m");
+    }
+}
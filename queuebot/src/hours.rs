@@ -0,0 +1,26 @@
+//! An example of a course-specific command which lives outside of the core bot.
+//! It is registered within `main` using `QueueBot::with_command`
+
+use crate::audit::Outcome;
+use crate::commands::Command;
+use crate::{Message, QueueBot};
+
+/// `!q hours` - Respond with when office hours are held
+pub struct OfficeHours {
+    schedule: String,
+}
+
+impl OfficeHours {
+    /// `schedule` is the text sent back to whoever runs `!q hours`
+    pub fn new(schedule: &str) -> OfficeHours {
+        OfficeHours { schedule: String::from(schedule) }
+    }
+}
+
+impl Command for OfficeHours {
+    fn name(&self) -> &'static str { "hours" }
+    fn usage(&self) -> &'static str { "`!q hours` - Show when office hours are held" }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, _args: &[&str]) -> Outcome {
+        bot.send(bot.tr(msg.guild_id, msg.author, "hours", &[("schedule", self.schedule.clone())]), None)
+    }
+}
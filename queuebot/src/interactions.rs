@@ -0,0 +1,45 @@
+use crate::DiscordUser;
+
+/// IDs of the buttons underneath the pinned queue message
+pub const JOIN_BUTTON: &str = "queue_join";
+pub const LEAVE_BUTTON: &str = "queue_leave";
+
+/// Reactions which can be clicked on the pinned queue message instead of the buttons
+pub const JOIN_EMOJI: &str = "🙋";
+pub const LEAVE_EMOJI: &str = "👋";
+
+/// What a user clicked
+#[derive(Clone, Debug, PartialEq)]
+pub enum InteractionKind {
+    /// A button underneath a message (holding the button's ID)
+    Button(String),
+    /// An emoji reaction added to a message
+    Reaction(String),
+}
+
+/// A representation of a Discord interaction, which is sent instead of a
+/// message when a user clicks a button or adds a reaction. Like `Message`,
+/// a library which connects to Discord would have its own version of this.
+///
+/// For more information see <https://discord.com/developers/docs/interactions/message-components>
+pub struct Interaction<'a> {
+    pub guild_id: u64,
+    /// The ID of the message which was clicked on
+    pub message_id: u64,
+    pub user: &'a DiscordUser,
+    pub kind: InteractionKind,
+}
+
+impl Interaction<'_> {
+    /// The name of the command clicking this stands for (such as `join`),
+    /// or `None` if it isn't one of the bot's buttons or reactions
+    pub fn command(&self) -> Option<&'static str> {
+        match &self.kind {
+            InteractionKind::Button(id) if id == JOIN_BUTTON => Some("join"),
+            InteractionKind::Button(id) if id == LEAVE_BUTTON => Some("leave"),
+            InteractionKind::Reaction(emoji) if emoji == JOIN_EMOJI => Some("join"),
+            InteractionKind::Reaction(emoji) if emoji == LEAVE_EMOJI => Some("leave"),
+            _ => None,
+        }
+    }
+}
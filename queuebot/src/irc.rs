@@ -0,0 +1,481 @@
+//! Runs the bot within an IRC channel instead of on Discord.
+//!
+//! IRC is much simpler than Discord so some things are mapped across:
+//!  - Messages (`PRIVMSG`) sent to the channel become `Message`s
+//!  - Nicks become users. Each nick is given an ID the first time it is seen
+//!    and keeps it when the nick changes (like a Discord user changing their name)
+//!  - Channel operators (`@nick`) are TAs
+//!  - Mentions are just nicks, so a message which contains someone's nick mentions
+//!    them and `<@Name>` within the bot's replies is sent as `Name`
+//!  - Direct messages and help threads are sent as private messages
+//!
+//! IRC can't edit, delete, pin or react to messages so those are ignored,
+//! which means `!q pin` only posts the queue once.
+//!
+//! For more information about the protocol see <https://modern.ircdocs.horse/>
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::actor::{BotHandle, IncomingMessage};
+use crate::transport::{Button, Transport};
+use crate::users::SnowflakeGenerator;
+use crate::{DiscordUser, User};
+
+/// The snowflake worker ID used for IRC users, so their IDs never
+/// collide with users created anywhere else
+const IRC_WORKER: u8 = 2;
+
+/// IRC users don't have a discriminator so they all share this one
+const IRC_DISCRIMINATOR: &str = "0000";
+
+/// A line sent by an IRC server, such as
+/// `:Kapua!kapua@host PRIVMSG #cs120 :!q join`
+#[derive(Clone, Debug, PartialEq)]
+pub struct IrcLine {
+    /// Who sent it (`Kapua!kapua@host`), if anyone
+    pub prefix: Option<String>,
+    /// The command (`PRIVMSG`) or numeric reply (`001`)
+    pub command: String,
+    /// Every parameter including the trailing one (`#cs120` and `!q join`)
+    pub params: Vec<String>,
+}
+
+impl IrcLine {
+    /// Parse a line (without its `\r\n`). Returns `None` for an empty line
+    pub fn parse(line: &str) -> Option<IrcLine> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+        let prefix = match rest.strip_prefix(':') {
+            Some(without_colon) => {
+                let (prefix, after) = without_colon.split_once(' ')?;
+                rest = after;
+                Some(String::from(prefix))
+            },
+            None => None,
+        };
+
+        // Everything after ` :` is a single parameter which can contain spaces
+        let (middle, trailing) = match rest.split_once(" :") {
+            Some((middle, trailing)) => (middle, Some(trailing)),
+            None => (rest, None),
+        };
+        let mut words = middle.split_ascii_whitespace();
+        let command = words.next()?.to_ascii_uppercase();
+        let mut params = words.map(String::from).collect::<Vec<String>>();
+        params.extend(trailing.map(String::from));
+        Some(IrcLine { prefix, command, params })
+    }
+
+    /// The nick of whoever sent the line
+    pub fn nick(&self) -> Option<&str> {
+        self.prefix.as_deref().map(|p| p.split('!').next().unwrap_or(p))
+    }
+}
+
+/// The sending half of a connection, shared between the client and its transport
+#[derive(Clone)]
+struct Writer(Arc<Mutex<TcpStream>>);
+
+impl Writer {
+    /// Send a single line to the server
+    fn line(&self, line: &str) -> io::Result<()> {
+        let mut stream = self.0.lock().unwrap();
+        write!(stream, "{}\r\n", line)?;
+        stream.flush()
+    }
+
+    /// Send a message to a channel or nick. IRC messages can't contain line
+    /// breaks so each line is sent separately (skipping empty lines)
+    fn privmsg(&self, target: &str, message: &str) {
+        for line in plain_mentions(message).lines().filter(|l| !l.trim().is_empty()) {
+            if let Err(e) = self.line(&format!("PRIVMSG {} :{}", target, line)) {
+                eprintln!("Unable to send to IRC: {}", e);
+            }
+        }
+    }
+}
+
+/// Replace mentions (`<@Kapua>`) with plain nicks (`Kapua`), which
+/// IRC clients highlight in the same way
+fn plain_mentions(message: &str) -> String {
+    let mut text = String::new();
+    let mut rest = message;
+    while let Some(start) = rest.find("<@") {
+        match rest[start..].find('>') {
+            Some(end) => {
+                text += &rest[..start];
+                text += &rest[start + 2..start + end];
+                rest = &rest[start + end + 1..];
+            },
+            None => break,
+        }
+    }
+    text + rest
+}
+
+/// Sends the bot's messages to an IRC channel (see `IrcClient::transport`)
+#[derive(Clone)]
+pub struct IrcTransport {
+    writer: Writer,
+    channel: String,
+    /// IRC messages don't have IDs so the bot is given made up ones
+    last_id: Arc<AtomicU64>,
+    /// The nicks of everyone within each help thread
+    threads: Arc<Mutex<HashMap<u64, Vec<String>>>>,
+}
+
+impl IrcTransport {
+    fn next_id(&self) -> u64 {
+        self.last_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+impl Transport for IrcTransport {
+    fn send(&self, message: &str) -> u64 {
+        self.writer.privmsg(&self.channel, message);
+        self.next_id()
+    }
+
+    fn send_dm(&self, user: &DiscordUser, message: &str) -> u64 {
+        self.writer.privmsg(&user.get_name(), message);
+        self.next_id()
+    }
+
+    // IRC has no buttons, but students can still type the commands
+    fn send_buttons(&self, message: &str, _buttons: &[Button]) -> u64 {
+        self.send(message)
+    }
+
+    fn edit(&self, _id: u64, _message: &str) {}
+
+    fn delete(&self, _id: u64) {}
+
+    fn pin(&self, _id: u64) {}
+
+    fn react(&self, _id: u64, _emoji: &str) {}
+
+    // A thread is a group of private messages to each of its members
+    fn create_thread(&self, _name: &str, members: &[&DiscordUser]) -> u64 {
+        let id = self.next_id();
+        self.threads.lock().unwrap().insert(id, members.iter().map(|m| m.get_name()).collect());
+        id
+    }
+
+    fn send_to_thread(&self, thread: u64, message: &str) {
+        let members = self.threads.lock().unwrap().get(&thread).cloned().unwrap_or_default();
+        for member in members {
+            self.writer.privmsg(&member, message);
+        }
+    }
+
+    fn archive_thread(&self, thread: u64) {
+        self.threads.lock().unwrap().remove(&thread);
+    }
+
+    fn log(&self, line: &str) {
+        println!("{}", line)
+    }
+}
+
+/// A connection to an IRC server which joins a single channel and turns
+/// what is said there into messages for the bot. Messages are handled
+/// as if they were sent within the server with the ID `guild_id`
+pub struct IrcClient {
+    reader: BufReader<TcpStream>,
+    writer: Writer,
+    nick: String,
+    channel: String,
+    guild_id: u64,
+    /// Everyone seen so far, keyed by their nick in lowercase
+    users: HashMap<String, DiscordUser>,
+    /// Channel operators (lowercase nicks)
+    ops: HashSet<String>,
+    ids: SnowflakeGenerator,
+}
+
+impl IrcClient {
+    /// Connect to the server at `addr` as `nick`. The channel is joined
+    /// once the server accepts the connection (see `next_message`)
+    pub fn connect<A: ToSocketAddrs>(addr: A, nick: &str, channel: &str, guild_id: u64) -> io::Result<IrcClient> {
+        let stream = TcpStream::connect(addr)?;
+        let client = IrcClient {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: Writer(Arc::new(Mutex::new(stream))),
+            nick: String::from(nick),
+            channel: String::from(channel),
+            guild_id,
+            users: HashMap::new(),
+            ops: HashSet::new(),
+            ids: SnowflakeGenerator::new(IRC_WORKER, 0),
+        };
+        client.writer.line(&format!("NICK {}", nick))?;
+        client.writer.line(&format!("USER {} 0 * :QueueBot", nick))?;
+        Ok(client)
+    }
+
+    /// A transport which sends the bot's messages to this client's channel
+    pub fn transport(&self) -> IrcTransport {
+        IrcTransport {
+            writer: self.writer.clone(),
+            channel: self.channel.clone(),
+            last_id: Arc::new(AtomicU64::new(0)),
+            threads: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Wait for the next message sent to the channel. Everything else the server
+    /// sends (pings, nick changes, who is an operator) is handled along the way.
+    /// Returns `None` once the server closes the connection
+    pub fn next_message(&mut self) -> io::Result<Option<IncomingMessage>> {
+        let mut buffer = String::new();
+        loop {
+            buffer.clear();
+            if self.reader.read_line(&mut buffer)? == 0 {
+                return Ok(None);
+            }
+            let line = match IrcLine::parse(&buffer) {
+                Some(line) => line,
+                None => continue,
+            };
+            if let Some(message) = self.handle(&line)? {
+                return Ok(Some(message));
+            }
+        }
+    }
+
+    /// Pass every message sent to the channel to the bot until the server
+    /// closes the connection. This blocks so it must not be called from within an async task
+    pub fn run(mut self, bot: BotHandle) -> io::Result<()> {
+        while let Some(msg) = self.next_message()? {
+            bot.blocking_on_message(msg);
+        }
+        Ok(())
+    }
+
+    /// Handle a line from the server, returning it as a message
+    /// for the bot if it was sent to the channel
+    fn handle(&mut self, line: &IrcLine) -> io::Result<Option<IncomingMessage>> {
+        let param = |i: usize| line.params.get(i).map_or("", |p| p.as_str());
+        match line.command.as_str() {
+            "PING" => self.writer.line(&format!("PONG :{}", param(0)))?,
+            // The server accepted the connection
+            "001" => self.writer.line(&format!("JOIN {}", self.channel))?,
+            "433" => return Err(io::Error::other(format!("the nick {} is already in use", self.nick))),
+            // Who is in the channel. Operators have `@` in front of their nick
+            "353" if param(2).eq_ignore_ascii_case(&self.channel) => {
+                for name in param(3).split_ascii_whitespace() {
+                    let nick = name.trim_start_matches(['~', '&', '%', '+']);
+                    match nick.strip_prefix('@') {
+                        Some(op) => self.set_op(op, true),
+                        None => self.set_op(nick, false),
+                    }
+                }
+            },
+            "MODE" if param(0).eq_ignore_ascii_case(&self.channel) => self.change_modes(param(1), &line.params[2.min(line.params.len())..]),
+            "NICK" => {
+                if let Some(old) = line.nick() {
+                    self.rename(old, param(0));
+                }
+            },
+            "PART" | "KICK" | "QUIT" => {
+                let nick = match line.command.as_str() {
+                    "KICK" => param(1),
+                    _ => line.nick().unwrap_or(""),
+                };
+                self.set_op(nick, false);
+            },
+            "PRIVMSG" if param(0).eq_ignore_ascii_case(&self.channel) => {
+                let author = match line.nick() {
+                    Some(nick) => self.user(nick),
+                    None => return Ok(None),
+                };
+                let text = param(1);
+                let mentions = text.split_ascii_whitespace()
+                    .map(|word| word.trim_start_matches('@').trim_end_matches([',', ':', '.', '!', '?']))
+                    .filter_map(|word| self.users.get(&word.to_ascii_lowercase()))
+                    .filter(|user| user.uuid != author.uuid)
+                    .cloned()
+                    .collect::<Vec<DiscordUser>>();
+                return Ok(Some(IncomingMessage {
+                    id: 0,
+                    guild_id: self.guild_id,
+                    author,
+                    message: String::from(text),
+                    mentions: if mentions.is_empty() { None } else { Some(mentions) },
+                }));
+            },
+            _ => {},
+        }
+        Ok(None)
+    }
+
+    /// Apply a channel mode change such as `+o Kapua` or `-oo Ben Dana`.
+    /// Only operator changes matter, but every mode which takes a
+    /// nick or argument still has to be skipped over
+    fn change_modes(&mut self, modes: &str, args: &[String]) {
+        let mut args = args.iter();
+        let mut adding = true;
+        for mode in modes.chars() {
+            match mode {
+                '+' => adding = true,
+                '-' => adding = false,
+                'o' => {
+                    if let Some(nick) = args.next() {
+                        self.set_op(nick, adding);
+                    }
+                },
+                'v' | 'h' | 'b' | 'k' | 'e' | 'I' => { args.next(); },
+                'l' if adding => { args.next(); },
+                _ => {},
+            }
+        }
+    }
+
+    /// The user with the given nick (creating them if they haven't been seen before)
+    fn user(&mut self, nick: &str) -> DiscordUser {
+        let is_ta = self.ops.contains(&nick.to_ascii_lowercase());
+        let ids = &mut self.ids;
+        let user = self.users.entry(nick.to_ascii_lowercase()).or_insert_with(|| DiscordUser {
+            uuid: ids.next_id(),
+            name: String::from(nick),
+            discriminator: String::from(IRC_DISCRIMINATOR),
+            is_ta,
+        });
+        user.clone()
+    }
+
+    /// Give or take away operator status (and so being a TA)
+    fn set_op(&mut self, nick: &str, op: bool) {
+        let key = nick.to_ascii_lowercase();
+        match op {
+            true => self.ops.insert(key.clone()),
+            false => self.ops.remove(&key),
+        };
+        if let Some(user) = self.users.get_mut(&key) {
+            user.is_ta = op;
+        }
+    }
+
+    /// Someone changed their nick. They keep the same ID (and so their spot in the queue)
+    fn rename(&mut self, old: &str, new: &str) {
+        let old = old.to_ascii_lowercase();
+        if self.ops.remove(&old) {
+            self.ops.insert(new.to_ascii_lowercase());
+        }
+        if let Some(mut user) = self.users.remove(&old) {
+            user.name = String::from(new);
+            self.users.insert(new.to_ascii_lowercase(), user);
+        }
+        if old == self.nick.to_ascii_lowercase() {
+            self.nick = String::from(new);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+    use crate::guild::GuildConfig;
+    use crate::QueueBot;
+
+    #[test]
+    fn lines_are_parsed() {
+        assert_eq!(IrcLine::parse(":Kapua!kapua@host PRIVMSG #cs120 :!q join lab 2\r\n"), Some(IrcLine {
+            prefix: Some(String::from("Kapua!kapua@host")),
+            command: String::from("PRIVMSG"),
+            params: vec![String::from("#cs120"), String::from("!q join lab 2")],
+        }));
+        assert_eq!(IrcLine::parse("PING :irc.example.edu").unwrap().params, ["irc.example.edu"]);
+        assert_eq!(IrcLine::parse(":Ben!b@h MODE #cs120 +o Kapua").unwrap().params, ["#cs120", "+o", "Kapua"]);
+        assert_eq!(IrcLine::parse(":Ben!b@h NICK :Benjamin").unwrap().nick(), Some("Ben"));
+        assert_eq!(IrcLine::parse(""), None);
+        assert_eq!(plain_mentions("<@Kapua> was helped by <@Ben>"), "Kapua was helped by Ben");
+    }
+
+    /// A tiny IRC server which sends `script` once the client has registered,
+    /// then hangs up and returns every line the client sent
+    fn stub_server(script: &'static [&'static str]) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut received = Vec::new();
+            // NICK and USER
+            for _ in 0..2 {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                received.push(String::from(line.trim_end()));
+            }
+            for line in script {
+                write!(writer, "{}\r\n", line).unwrap();
+            }
+            writer.shutdown(std::net::Shutdown::Write).unwrap();
+            received.extend(reader.lines().map(|l| l.unwrap()));
+            received
+        });
+        (addr, server)
+    }
+
+    #[test]
+    fn channel_messages_are_commands_and_ops_are_tas() {
+        let (addr, server) = stub_server(&[
+            ":irc.test 001 queuebot :Welcome",
+            ":irc.test 353 queuebot = #cs120 :queuebot @Ben Kapua +Russ",
+            "PING :irc.test",
+            ":Kapua!k@host PRIVMSG #cs120 :!q join",
+            ":Russ!r@host PRIVMSG #cs120 :!q join",
+            // Private messages to the bot and other channels are ignored
+            ":Russ!r@host PRIVMSG queuebot :!q leave",
+            ":Russ!r@host PRIVMSG #other :!q leave",
+            // Russ isn't an op so he can't remove Kapua, but Ben can
+            ":Russ!r@host PRIVMSG #cs120 :!q remove Kapua",
+            ":Ben!b@host PRIVMSG #cs120 :!q remove Kapua",
+            ":Kapua!k@host PRIVMSG #cs120 :!q join",
+            // Russ keeps his spot after changing his nick, and becomes a TA once he's an op
+            ":Russ!r@host NICK :Russell",
+            ":Ben!b@host MODE #cs120 +o-v Russell Russell",
+            ":Russell!r@host PRIVMSG #cs120 :!q list",
+            ":Russell!r@host PRIVMSG #cs120 :!q remove Kapua",
+            ":Ben!b@host PRIVMSG #cs120 :!q next",
+        ]);
+
+        let mut client = IrcClient::connect(addr, "queuebot", "#cs120", 1).unwrap();
+        let mut bot = QueueBot::new()
+            .with_guild(1, GuildConfig::new("CS 120"))
+            .with_transport(client.transport());
+        while let Some(msg) = client.next_message().unwrap() {
+            bot.on_message(msg.as_message());
+        }
+        assert!(bot.guild(1).queue.is_empty());
+        // Hang up so the server stops reading
+        drop(bot);
+        drop(client);
+
+        assert_eq!(server.join().unwrap(), [
+            "NICK queuebot",
+            "USER queuebot 0 * :QueueBot",
+            "JOIN #cs120",
+            "PONG :irc.test",
+            "PRIVMSG #cs120 :✅ Kapua You have been added to the queue at position 1",
+            "PRIVMSG #cs120 :✅ Russ You have been added to the queue at position 2",
+            "PRIVMSG #cs120 :⚠️ Russ invalid format.",
+            "PRIVMSG #cs120 :✅ Ben The student has been removed from the queue!",
+            "PRIVMSG Russ :Russ You are next in line in the CS 120 queue! A TA will be with you soon.",
+            "PRIVMSG #cs120 :✅ Kapua You have been added to the queue at position 2",
+            "PRIVMSG #cs120 :Queue: [Russell#0000, Kapua#0000]",
+            "PRIVMSG #cs120 :✅ Russell The student has been removed from the queue!",
+            // Help threads are private messages to the student and the TA
+            "PRIVMSG Russell :Russell Ben will help you here. Only the two of you and other TAs can see this thread",
+            "PRIVMSG Ben :Russell Ben will help you here. Only the two of you and other TAs can see this thread",
+            "PRIVMSG #cs120 :The next person in line is Russell",
+        ]);
+    }
+}
@@ -0,0 +1,2471 @@
+//! This project is a simpler rewrite of QueueBot
+//! which is a Discord bot used by CS 120 to keep track
+//! of the queue for office hours.
+//! An old version of the Python source code can be seen here: <https://github.com/benperumala/cs120-queuebot/>
+//!
+//! This version is a recreation of that which does not connect to Discord.
+//! Instead, it is a mockup that tries to roughly simulate
+//! messages in the same way as Discord.
+//!
+//! It is also simpler than the production bot as it
+//!  - Has a single queue (per server) instead of online and in-person queue
+//!  - Doesn't check if users are waiting in a voice channel
+//!  - Doesn't check if messages are from the correct channels
+//!  - Doesn't display fancy Discord-specific embeds
+//!  - Doesn't notify TAs on the join in the queue
+//!
+//! The bot is a library so other tools (such as dashboards or grading
+//! scripts) can run it and read its state. The `queuebot` binary
+//! ([src/main.rs](../src/main.rs)) runs a scripted session (see `demo`) built on top of it.
+//! A bot is created with `QueueBot::new()` and set up with its `with_*`
+//! methods, then Discord's events are passed to `on_message`,
+//! `on_message_edit`, `on_message_delete` and `on_interaction`:
+//!
+//! ```
+//! use queuebot::{Message, QueueBot};
+//! use queuebot::guild::GuildConfig;
+//! use queuebot::transport::{MockTransport, Sent};
+//! use queuebot::users::UserRegistry;
+//!
+//! let mut users = UserRegistry::new();
+//! let kapua = users.register("Kapua", "0002", false).unwrap();
+//! let transport = MockTransport::default();
+//! let mut bot = QueueBot::new()
+//!     .with_guild(1, GuildConfig::new("CS 120"))
+//!     .with_users(users)
+//!     .with_transport(transport.clone());
+//!
+//! bot.on_message(Message {
+//!     id: 10, guild_id: 1, author: &kapua, message: String::from("!q join"), mentions: None
+//! });
+//! assert_eq!(bot.guild(1).queue, [kapua.uuid]);
+//! assert_eq!(transport.take(), [
+//!     Sent::Channel(String::from("✅ <@Kapua> You have been added to the queue at position 1")),
+//! ]);
+//! ```
+
+pub mod actor;
+pub mod audit;
+pub mod bookings;
+pub mod clock;
+pub mod commands;
+pub mod demo;
+mod events;
+pub mod feedback;
+pub mod guild;
+pub mod history;
+pub mod hours;
+pub mod interactions;
+pub mod irc;
+pub mod locale;
+pub mod metrics;
+pub mod privacy;
+pub mod queue;
+mod reminders;
+pub mod scenario;
+pub mod scripting;
+pub mod transport;
+pub mod tui;
+pub mod users;
+pub mod web;
+pub mod webhooks;
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use rhai::{Dynamic, Map};
+
+use audit::{AuditEntry, AuditFilter, AuditLog, Outcome};
+use bookings::{format_time, parse_slots, BookingError, Slot};
+use clock::{Clock, SystemClock};
+use commands::{Command, CommandRegistry, Role};
+use events::{RecentCommands, TrackedCommand};
+use feedback::{Feedback, Response, Survey, RATING_EMOJI};
+use guild::{Guild, GuildConfig, HelpThread};
+use history::{format_duration, History, Session, SessionOutcome};
+use interactions::{Interaction, InteractionKind, JOIN_BUTTON, JOIN_EMOJI, LEAVE_BUTTON, LEAVE_EMOJI};
+use locale::{Args, Catalog, Locale};
+use metrics::Metrics;
+use reminders::{Milestone, Reminder, Scheduler};
+use scripting::{Hook, ScriptAction, ScriptCommand, Scripts};
+use transport::{Button, ConsoleTransport, Transport};
+use users::UserRegistry;
+use webhooks::{WebhookEvent, WebhookPayload, Webhooks};
+
+// Use the Serialize/Deserialize derive macros from serde within every module
+#[macro_use]
+extern crate serde_derive;
+extern crate serde;
+extern crate serde_json;
+
+/// Used to prepend a warning, error, or success
+/// emote to the beginning of a sent message.
+pub enum MessageType {
+    Warning,
+    Success,
+    Error
+}
+
+/// Implementation of a "trait"
+/// Anyone who wants to implement this trait must create
+/// the below methods.
+pub trait User {
+    /// Return a string which represents mentioning the given user
+    /// Discord typically defines this as `<@XXXX>`
+    /// where XXXX is the user's UUID
+    /// However, since this is a mockup, `<@Username>` should be done instead
+    /// to make it easier to read
+    fn get_mention(&self) -> String;
+
+    /// Returns a string which represents the usual
+    /// way users share their account ID so other
+    /// people can add them. Discord defines this as
+    /// USERNAME#DDDD where USERNAME is their username
+    /// and DDDD is four numbers
+    fn get_tag(&self) -> String;
+
+    /// Get the name of the user
+    fn get_name(&self) -> String;
+
+    /// Check if a user is a TA
+    /// (Usually this involves checking all their roles
+    /// but to simplify things, it's a field within the struct)
+    fn is_ta(&self) -> bool;
+}
+
+/// An object which is used to represent a user
+/// on Discord. If using a library to communicate with
+/// Discord, that library would have it's own version of representing
+/// users. This is a mock version of that.
+/// Note for a production version, it wouldn't have an `is_ta` field.
+/// That would instead be a method where you would have to check all of
+/// the user's roles and see if any of them match a "TA Role" which would
+/// be specified in some sort of config (not modeled in this program)
+///
+/// For more information about users see <https://discord.com/developers/docs/resources/user>
+#[derive(Clone)]  // Allows Rust to create deep copies of the object
+pub struct DiscordUser {
+    /// The user's snowflake ID (see `SnowflakeGenerator`)
+    pub uuid: u64,
+    pub name: String,
+    /// The four digits after the `#` within the user's tag
+    pub discriminator: String,
+    pub is_ta: bool,
+}
+
+// Allow for printing the struct to the console
+impl Debug for DiscordUser {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}#{}", self.name, self.discriminator)
+    }
+}
+
+/// A representation of a Discord message. When using a library which
+/// connects to Discord, that object would be used instead. This is a
+/// mockup object which is used to show the bot is working without having
+/// to set up and connect it to Discord.
+///
+/// The fields are based off discord.py's representation which keeps track
+/// of the author, their mention, and any users they have mentioned.
+/// The message will contain mentions (`<@XXX>`) but this can be annoying to
+/// parse. As a result, Discord also returns a list of all users who have
+/// been mentioned within the message (order is not guaranteed)
+///
+/// `guild_id` is the ID of the server (Discord calls these guilds) the
+/// message was sent in. The bot keeps completely separate state for each server
+///
+/// `id` is the message's own ID, which Discord sends again if the message
+/// is edited or deleted. Messages which didn't come from Discord (such as
+/// commands run from the web dashboard) use 0 and can't be edited
+///
+/// The <'a> is used to represent that author is a *reference* to a DiscordUser object
+pub struct Message<'a> {
+    pub id: u64,
+    pub guild_id: u64,
+    pub author: &'a DiscordUser,
+    pub message: String,
+    pub mentions: Option<Vec<DiscordUser>>
+}
+
+// Almost like the __eq__ method within Python.
+// This allows Rust to compare DiscordUsers and
+// see if two structs represent the same object
+impl PartialEq for DiscordUser {
+    fn eq(&self, other: &Self) -> bool {
+        self.uuid == other.uuid
+    }
+}
+
+// Implementation of User contract methods specifically for `DiscordUser`
+impl User for DiscordUser {
+    fn get_mention(&self) -> String {
+        format!("<@{}>", self.name)
+    }
+
+    fn get_tag(&self) -> String {
+        format!("{}#{}", self.name, self.discriminator)
+    }
+
+    fn get_name(&self) -> String {
+        self.name.to_string()
+    }
+
+    fn is_ta(&self) -> bool {
+        self.is_ta
+    }
+}
+
+/// The struct representing the QueueBot object
+/// It holds a queue for every server it is in. In an actual
+/// production implementation, it would also store
+/// references to Discord Text, Voice channels, etc.
+/// which are used to ensure commands are being sent
+/// from the right locations and students are waiting
+/// in the proper voice channel for a TA
+pub struct QueueBot {
+    /// The queue and config of every server, keyed by the server's ID.
+    /// Servers the bot hasn't been set up for are added the first time
+    /// a message is sent within them
+    guilds: HashMap<u64, Guild>,
+
+    /// Every user the bot has seen, looked up by their ID
+    users: UserRegistry,
+
+    /// Where every command (and its outcome) is recorded.
+    /// `None` means commands are not being audited
+    audit: Option<AuditLog>,
+
+    /// Every student's past visits to the queue
+    history: History,
+
+    /// Anonymous ratings of the help students got (see `GuildConfig::surveys`)
+    feedback: Feedback,
+
+    /// Every command the bot responds to. Stored behind an `Arc` so a
+    /// command can be looked up and then handed a mutable reference to the bot
+    commands: Arc<CommandRegistry>,
+
+    /// Every message the bot can send, in every supported language
+    catalog: Catalog,
+
+    /// Languages picked by individual users (keyed by their uuid)
+    user_locales: HashMap<u64, Locale>,
+
+    /// Users who turned off reminders with `!q notify off`
+    notify_opt_out: HashSet<u64>,
+
+    /// Reminders waiting to be sent
+    scheduler: Scheduler,
+
+    /// Custom hooks and commands written by course staff
+    scripts: Scripts,
+
+    /// Counts of commands and wait times (see `metrics::render`)
+    metrics: Metrics,
+
+    /// Other course tools which are told about queue events
+    webhooks: Webhooks,
+
+    /// Recent commands, so edits and deletions of the messages which ran them can be handled
+    recent: RecentCommands,
+
+    /// IDs of the messages sent in reply to the command being handled.
+    /// `send` only borrows the bot so this has to be a `RefCell`
+    replies: RefCell<Vec<u64>>,
+
+    /// When old history and audit entries were last deleted (see `prune_expired`)
+    last_pruned: Option<DateTime<Utc>>,
+
+    /// Where the current time comes from
+    clock: Box<dyn Clock>,
+
+    /// Where messages are sent
+    transport: Box<dyn Transport>,
+}
+
+impl Default for QueueBot {
+    fn default() -> QueueBot {
+        QueueBot::new()
+    }
+}
+
+/// This is an example of implementing a "class" without
+/// a trait. To create a new object, use `QueueBot::new()`
+impl QueueBot {
+    /// Create a new QueueBot object which isn't in any servers yet
+    pub fn new() -> QueueBot {
+        QueueBot {
+            guilds: HashMap::new(),
+            users: UserRegistry::new(),
+            audit: None,
+            history: History::default(),
+            feedback: Feedback::default(),
+            commands: Arc::new(CommandRegistry::with_builtins()),
+            catalog: Catalog::builtin(),
+            user_locales: HashMap::new(),
+            notify_opt_out: HashSet::new(),
+            scheduler: Scheduler::default(),
+            scripts: Scripts::default(),
+            metrics: Metrics::default(),
+            webhooks: Webhooks::default(),
+            recent: RecentCommands::default(),
+            replies: RefCell::new(Vec::new()),
+            last_pruned: None,
+            clock: Box::new(SystemClock),
+            transport: Box::new(ConsoleTransport::default()),
+        }
+    }
+
+    /// Send messages somewhere other than the console
+    pub fn with_transport<T: Transport + 'static>(mut self, transport: T) -> QueueBot {
+        self.transport = Box::new(transport);
+        self
+    }
+
+    /// Get the time from somewhere other than the system clock
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> QueueBot {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Set up the server with the given ID (replacing any existing setup)
+    pub fn with_guild(mut self, id: u64, config: GuildConfig) -> QueueBot {
+        self.guilds.insert(id, Guild::new(config));
+        self
+    }
+
+    /// Register an additional command (such as a course-specific `!q hours`).
+    /// A command with the same name as an existing one replaces it
+    pub fn with_command<C: Command + 'static>(mut self, command: C) -> QueueBot {
+        Arc::make_mut(&mut self.commands).register(command);
+        self
+    }
+
+    /// Use an existing set of users (such as one users were registered with)
+    pub fn with_users(mut self, users: UserRegistry) -> QueueBot {
+        self.users = users;
+        self
+    }
+
+    /// Record every command the bot handles within the given audit log
+    pub fn with_audit_log(mut self, log: AuditLog) -> QueueBot {
+        self.audit = Some(log);
+        self
+    }
+
+    /// Keep the help history of every student (instead of starting empty)
+    pub fn with_history(mut self, history: History) -> QueueBot {
+        self.history = history;
+        self
+    }
+
+    /// Keep students' ratings of the help they got (instead of starting empty)
+    pub fn with_feedback(mut self, feedback: Feedback) -> QueueBot {
+        self.feedback = feedback;
+        self
+    }
+
+    /// Run the given scripts' hooks and add their custom commands.
+    /// Anyone can run a script's command, so a script can't define a command with
+    /// the same name (or alias) as one which is already registered. Otherwise
+    /// `cmd_next` would let students call the next student
+    pub fn with_scripts(mut self, scripts: Scripts) -> Result<QueueBot, String> {
+        let commands = scripts.commands();
+        if let Some((name, file)) = commands.iter().find(|(name, _)| self.commands.get(name).is_some()) {
+            return Err(format!("{}: cmd_{} can't replace the existing `!q {}` command", file, name, name));
+        }
+        for (name, file) in commands {
+            Arc::make_mut(&mut self.commands).register(ScriptCommand::new(&name, &file));
+        }
+        self.scripts = scripts;
+        Ok(self)
+    }
+
+    /// Send queue events to the given webhooks
+    pub fn with_webhooks(mut self, webhooks: Webhooks) -> QueueBot {
+        self.webhooks = webhooks;
+        self
+    }
+
+    /// Event handler for Discord message events. This checks to see if the
+    /// message starts with `!q`. Returns how the command went
+    /// (`None` if the message wasn't a command)
+    pub fn on_message(&mut self, msg: Message) -> Option<Outcome> {
+        let lower_message = msg.message.to_ascii_lowercase();
+        self.transport.log(&format!("[{}]: {}", msg.author.get_name(), msg.message));
+
+        // Servers that haven't been set up start with the default config
+        self.guilds.entry(msg.guild_id)
+            .or_insert_with(|| Guild::new(GuildConfig::new(&msg.guild_id.to_string())));
+
+        // Keep track of the latest name of everyone involved
+        self.users.observe(msg.author);
+        for user in msg.mentions.iter().flatten() {
+            self.users.observe(user);
+        }
+
+        match lower_message.starts_with("!q") {
+            true => Some(self.handle_command(&msg)),
+            false => None,
+        }
+    }
+
+    /// Event handler for Discord interaction events (a button being clicked
+    /// or a reaction being added). Clicking Join or Leave on the pinned queue
+    /// message (see `!q pin`) is handled exactly like typing `!q join` or
+    /// `!q leave`. Reacting to a feedback survey with a rating answers it.
+    /// Clicks on any other message are ignored
+    pub fn on_interaction(&mut self, interaction: Interaction) {
+        let clicked = match &interaction.kind {
+            InteractionKind::Button(id) => id,
+            InteractionKind::Reaction(emoji) => emoji,
+        };
+        self.transport.log(&format!("[{}] (clicked {})", interaction.user.get_name(), clicked));
+
+        // Surveys are sent privately so they aren't in the server the interaction says it's from
+        if let Some(rating) = feedback::rating_of(clicked) {
+            if let Some(survey) = self.feedback.take_survey(interaction.user.uuid, Some(interaction.message_id)) {
+                self.answer_survey(survey, interaction.user, rating, None);
+            }
+            return;
+        }
+
+        let command = match (self.guilds.get(&interaction.guild_id), interaction.command()) {
+            (Some(g), Some(command)) if g.status_message == Some(interaction.message_id) => command,
+            _ => return,
+        };
+        self.users.observe(interaction.user);
+        self.handle_command(&Message {
+            id: 0,
+            guild_id: interaction.guild_id,
+            author: interaction.user,
+            message: format!("!q {}", command),
+            mentions: None,
+        });
+    }
+
+    /// Run the command within a message which starts with `!q`
+    /// and everything which happens afterwards. Returns how the command went
+    fn handle_command(&mut self, msg: &Message) -> Outcome {
+        self.replies.borrow_mut().clear();
+        let result = self.queue_command(msg);
+        self.record(msg, result);
+        self.count_command(msg, result);
+        self.track(msg);
+        self.update_status(msg.guild_id);
+        self.update_reminders(msg.guild_id);
+        self.tick();
+        result
+    }
+
+    /// Event handler for Discord message edit events. `msg` is the message
+    /// as it is now (with the same `id` it was sent with).
+    ///
+    /// Running a command again every time its message is edited would be
+    /// surprising (editing `!q next` shouldn't pop another student) so only
+    /// edits which make sense are handled. Currently that is changing the
+    /// topic of `!q join <topic>` while still in the queue.
+    /// Edits to any other message are ignored
+    pub fn on_message_edit(&mut self, msg: Message) {
+        self.transport.log(&format!("[{}] (edited): {}", msg.author.get_name(), msg.message));
+        let tracked = match self.recent.get(msg.id) {
+            Some(t) if t.guild_id == msg.guild_id && t.author_id == msg.author.uuid => t.clone(),
+            _ => return,
+        };
+
+        // The edited message has to still run the same command
+        let words = msg.message.split_ascii_whitespace().collect::<Vec<&str>>();
+        let command = match words.get(1).and_then(|w| self.commands.get(&w.to_ascii_lowercase())) {
+            Some(c) if words[0].eq_ignore_ascii_case("!q") && c.name() == tracked.command => c.name(),
+            _ => return,
+        };
+
+        self.replies.borrow_mut().clear();
+        match command {
+            "join" => self.q_edit_topic(msg.guild_id, msg.author, &words[2..].join(" ")),
+            _ => return,
+        };
+
+        // Replies to the edit belong to the original message
+        let mut tracked = tracked;
+        tracked.replies.extend(self.replies.take());
+        self.recent.track(msg.id, tracked);
+    }
+
+    /// Event handler for Discord message delete events. If the message ran a
+    /// command and the server has `cleanup_replies` turned on, the bot's replies
+    /// to it are deleted too. Nothing the command did is undone
+    pub fn on_message_delete(&mut self, guild: u64, id: u64) {
+        // The pinned queue message can't be clicked on anymore
+        if let Some(g) = self.guilds.get_mut(&guild).filter(|g| g.status_message == Some(id)) {
+            g.status_message = None;
+        }
+        let tracked = match self.recent.get(id) {
+            Some(t) if t.guild_id == guild => self.recent.remove(id).expect("the command was just found"),
+            _ => return,
+        };
+        if self.guilds.get(&guild).is_some_and(|g| g.config.cleanup_replies) {
+            for reply in tracked.replies {
+                self.transport.delete(reply);
+            }
+        }
+    }
+
+    /// Remember the command within `msg` (and the replies to it)
+    /// in case the message is edited or deleted later
+    fn track(&mut self, msg: &Message) {
+        let replies = self.replies.take();
+        if msg.id == 0 {
+            return;
+        }
+        let name = msg.message.split_ascii_whitespace().nth(1).unwrap_or("").to_ascii_lowercase();
+        let command = match self.commands.get(&name) {
+            Some(c) => String::from(c.name()),
+            None => name,
+        };
+        self.recent.track(msg.id, TrackedCommand { guild_id: msg.guild_id, author_id: msg.author.uuid, command, replies });
+    }
+
+    /// Edit the server's pinned queue message (if it has one) to match the queue
+    fn update_status(&mut self, guild: u64) {
+        let id = match self.guild(guild).status_message {
+            None => return,
+            Some(id) => id,
+        };
+        let text = self.status_text(guild);
+        if text != self.guild(guild).status_text {
+            self.transport.edit(id, &text);
+            self.guild_mut(guild).status_text = text;
+        }
+    }
+
+    /// What the pinned queue message says: everyone in the queue and how to
+    /// join or leave. It's seen by everyone so the server's language is used
+    fn status_text(&self, guild: u64) -> String {
+        let config = &self.guild(guild).config;
+        let students = self.queued_users(guild);
+        let mut lines = vec![self.catalog.get(config.locale, "status", &[
+            ("queue", config.name.clone()),
+            ("count", students.len().to_string()),
+        ])];
+        lines.extend(students.iter().enumerate().map(|(i, s)| format!("{}. {}", i + 1, self.describe(guild, s))));
+        lines.push(self.catalog.get(config.locale, "status.footer", &[
+            ("join", String::from(JOIN_EMOJI)),
+            ("leave", String::from(LEAVE_EMOJI)),
+        ]));
+        lines.join("\n")
+    }
+
+    /// Schedule a reminder for every student in the server's queue who has
+    /// reached a milestone (see `Milestone`) they haven't been reminded about yet
+    fn update_reminders(&mut self, guild: u64) {
+        let now = self.clock.now();
+        let g = self.guilds.get_mut(&guild).expect("messages always create their server");
+
+        // Only the front of the queue can have reached a milestone
+        let reachable = g.config.reminder_position.unwrap_or(0).max(1);
+        for (i, id) in g.queue.iter().enumerate().take(reachable) {
+            let reminded = g.reminded.entry(id).or_default();
+            let new = g.config.milestones_at(i).into_iter().filter(|m| reminded.insert(*m)).collect::<Vec<Milestone>>();
+            // If they skipped straight to the front, only tell them about that
+            let milestone = match new.contains(&Milestone::Front) {
+                true => Milestone::Front,
+                false if new.contains(&Milestone::AlmostUp) => Milestone::AlmostUp,
+                false => continue,
+            };
+            self.scheduler.schedule(Reminder { due: now + g.config.reminder_delay, guild, user: id, milestone });
+        }
+    }
+
+    /// Send every reminder which is due. This happens after every message
+    /// but should also be called regularly so delayed reminders go out on time
+    pub fn tick(&mut self) {
+        self.start_appointments();
+        for reminder in self.scheduler.take_due(self.clock.now()) {
+            // The queue may have changed since the reminder was scheduled
+            let g = match self.guilds.get(&reminder.guild) {
+                Some(g) => g,
+                None => continue,
+            };
+            let index = match g.queue.position(reminder.user) {
+                Some(i) if g.config.milestones_at(i).contains(&reminder.milestone) => i,
+                _ => continue,
+            };
+            let user = match self.users.get(reminder.user) {
+                Some(u) if !self.notify_opt_out.contains(&u.uuid) => u,
+                _ => continue,
+            };
+            let key = match reminder.milestone {
+                Milestone::AlmostUp => "reminder.almost_up",
+                Milestone::Front => "reminder.front",
+            };
+            let message = self.tr(reminder.guild, user, key, &[
+                ("position", (index + 1).to_string()),
+                ("queue", g.config.name.clone()),
+            ]);
+            self.send_dm(user, message, None);
+        }
+        self.prune_expired();
+    }
+
+    /// Delete history, feedback and audit log entries which are older than their
+    /// server's retention period. This rewrites files so it only
+    /// happens once an hour no matter how often `tick` is called
+    fn prune_expired(&mut self) {
+        let now = self.clock.now();
+        if self.last_pruned.is_some_and(|t| now - t < chrono::Duration::hours(1)) {
+            return;
+        }
+        self.last_pruned = Some(now);
+
+        let cutoffs = self.guilds.iter()
+            .filter_map(|(id, g)| g.config.retention.map(|period| (*id, now - period)))
+            .collect::<HashMap<u64, DateTime<Utc>>>();
+        for (guild, cutoff) in cutoffs.iter() {
+            if let Err(e) = self.history.prune(*guild, *cutoff) {
+                eprintln!("Unable to prune history: {}", e);
+            }
+            if let Err(e) = self.feedback.prune(*guild, *cutoff) {
+                eprintln!("Unable to prune feedback: {}", e);
+            }
+        }
+        if let Some(log) = &self.audit {
+            if cutoffs.is_empty() {
+                return;
+            }
+            if let Err(e) = log.retain(|entry| cutoffs.get(&entry.guild_id).is_none_or(|cutoff| entry.timestamp >= *cutoff)) {
+                eprintln!("Unable to prune the audit log: {}", e);
+            }
+        }
+    }
+
+    /// Append the command within `msg` to the audit log (if there is one).
+    /// Only `!q` commands are recorded: other messages can't change the queue,
+    /// and keeping them would store everything students say in the channel
+    fn record(&self, msg: &Message, result: Outcome) {
+        let log = match &self.audit {
+            None => return,
+            Some(log) => log,
+        };
+        let mut words = msg.message.split_ascii_whitespace().skip(1);
+        let command = words.next().unwrap_or("").to_ascii_lowercase();
+        let arguments = match command.as_str() {
+            // Feedback is anonymous so what a student said can't be kept alongside who they are
+            "feedback" => Vec::new(),
+            _ => words.map(String::from).collect(),
+        };
+        let entry = AuditEntry {
+            timestamp: self.clock.now(),
+            guild_id: msg.guild_id,
+            author: msg.author.get_tag(),
+            author_id: msg.author.uuid,
+            command,
+            arguments,
+            mentions: msg.mentions.iter().flatten().map(|u| u.get_tag()).collect(),
+            mention_ids: msg.mentions.iter().flatten().map(|u| u.uuid).collect(),
+            result,
+        };
+        if let Err(e) = log.append(&entry) {
+            eprintln!("Unable to write to the audit log: {}", e);
+        }
+    }
+
+    /// Count the command within `msg` by the name it's registered with
+    /// (so aliases are counted as the command they stand for)
+    fn count_command(&mut self, msg: &Message, result: Outcome) {
+        let name = msg.message.split_ascii_whitespace().nth(1).unwrap_or("").to_ascii_lowercase();
+        let command = self.commands.get(&name).map_or("unknown", |c| c.name());
+        self.metrics.count_command(command, result);
+    }
+
+    /// Add a student's time in the queue (which just ended) to their history.
+    /// `ta` is whoever called or removed them
+    fn end_session(&mut self, guild: u64, user: u64, joined: Option<DateTime<Utc>>, ta: Option<&DiscordUser>, outcome: SessionOutcome) {
+        let now = self.clock.now();
+        let session = Session {
+            guild_id: guild,
+            user_id: user,
+            joined: joined.unwrap_or(now),
+            called: (outcome == SessionOutcome::Helped).then_some(now),
+            ta_id: ta.map(|t| t.uuid),
+            ended: now,
+            outcome,
+        };
+        if outcome == SessionOutcome::Helped {
+            self.metrics.observe_wait(guild, session.waited());
+        }
+        if let Err(e) = self.history.record(session) {
+            eprintln!("Unable to save history: {}", e);
+        }
+    }
+
+    /// What scripts are told about an event involving `user` (see `Scripts`):
+    ///  - `guild` (the server's ID as a string) and `queue` (the server's name)
+    ///  - `user`, `name` and `mention` of the user
+    ///  - `topic` they need help with (empty if they didn't say)
+    ///  - `position` within the queue (0 if they aren't in it) and `queue_length`
+    ///
+    /// along with anything within `extra` (which overrides the above)
+    fn script_event(&self, guild: u64, user: &DiscordUser, extra: Map) -> Map {
+        let g = self.guild(guild);
+        let position = g.queue.position(user.uuid).map_or(0, |i| i as i64 + 1);
+        let mut event = Map::from([
+            ("guild".into(), Dynamic::from(guild.to_string())),
+            ("queue".into(), Dynamic::from(g.config.name.clone())),
+            ("user".into(), Dynamic::from(user.get_tag())),
+            ("name".into(), Dynamic::from(user.get_name())),
+            ("mention".into(), Dynamic::from(user.get_mention())),
+            ("topic".into(), Dynamic::from(g.topics.get(&user.uuid).cloned().unwrap_or_default())),
+            ("position".into(), Dynamic::from(position)),
+            ("queue_length".into(), Dynamic::from(g.queue.len() as i64)),
+        ]);
+        event.extend(extra);
+        event
+    }
+
+    /// Run every script's `hook` for an event involving `user`
+    fn run_hook(&self, hook: Hook, guild: u64, user: &DiscordUser, extra: Map) {
+        let actions = self.scripts.run_hook(hook, &self.script_event(guild, user, extra));
+        self.apply_script_actions(user, actions);
+    }
+
+    /// Run a custom command defined by a script (see `ScriptCommand`)
+    fn run_script_command(&mut self, name: &str, msg: &Message, args: &[&str]) -> Outcome {
+        let arguments = args.iter().map(|a| Dynamic::from(a.to_string())).collect::<rhai::Array>();
+        let event = self.script_event(msg.guild_id, msg.author, Map::from([("args".into(), Dynamic::from(arguments))]));
+        match self.scripts.run_command(name, &event) {
+            Ok(actions) => {
+                self.apply_script_actions(msg.author, actions);
+                Outcome::Ok
+            },
+            Err(e) => {
+                eprintln!("{}", e);
+                Outcome::Error
+            },
+        }
+    }
+
+    /// Do what a script asked for. Direct messages go to `user`
+    fn apply_script_actions(&self, user: &DiscordUser, actions: Vec<ScriptAction>) {
+        for action in actions {
+            match action {
+                ScriptAction::Reply(text) => { self.send(text, None); },
+                ScriptAction::Dm(text) => { self.send_dm(user, text, None); },
+                ScriptAction::Log(text) => self.transport.log(&text),
+            }
+        }
+    }
+
+    /// A webhook payload for `event` within the queue of `guild`.
+    /// The student, TA and topic are left for the caller to fill in
+    fn webhook_payload(&self, event: WebhookEvent, guild: u64) -> WebhookPayload {
+        let g = self.guild(guild);
+        WebhookPayload {
+            event,
+            timestamp: self.clock.now(),
+            guild_id: guild.to_string(),
+            queue: g.config.name.clone(),
+            queue_length: g.queue.len(),
+            student: None,
+            student_id: None,
+            ta: None,
+            ta_id: None,
+            topic: None,
+            cleared: Vec::new(),
+        }
+    }
+
+    /// Tell webhooks that `event` happened to `student`. `ta` is the TA who caused it (if one did)
+    fn notify_webhooks(&mut self, event: WebhookEvent, guild: u64, student: u64, ta: Option<&DiscordUser>, topic: &str) {
+        if self.webhooks.is_empty() {
+            return;
+        }
+        let payload = WebhookPayload {
+            student: Some(self.users.get(student).map_or(student.to_string(), |s| s.get_tag())),
+            student_id: Some(student.to_string()),
+            ta: ta.map(|t| t.get_tag()),
+            ta_id: ta.map(|t| t.uuid.to_string()),
+            topic: (!topic.is_empty()).then(|| String::from(topic)),
+            ..self.webhook_payload(event, guild)
+        };
+        self.webhooks.emit(payload);
+    }
+
+    /// "Send" a message to Discord.
+    /// By default, this just prints the message to stdout (see `Transport`)
+    /// If `cmd_prefix` is specified, a colorful emote
+    /// will be added to the front of the message to help give a
+    /// visual aid of the message status (succeeded, failed, etc.)
+    ///
+    /// The returned `Outcome` matches the emote so command handlers
+    /// can report how they went by returning whatever they sent
+    pub fn send(&self, message: String, cmd_prefix: Option<MessageType>) -> Outcome {
+        let (prefix, outcome) = QueueBot::decorate(cmd_prefix);
+        let id = self.transport.send(&format!("{}{}", prefix, message));
+        self.replies.borrow_mut().push(id);
+        outcome
+    }
+
+    /// Same as `send` but the message is sent privately to `user`
+    pub fn send_dm(&self, user: &DiscordUser, message: String, cmd_prefix: Option<MessageType>) -> Outcome {
+        let (prefix, outcome) = QueueBot::decorate(cmd_prefix);
+        self.transport.send_dm(user, &format!("{}{}", prefix, message));
+        outcome
+    }
+
+    /// The emote to put in front of a message and the matching `Outcome`
+    fn decorate(cmd_prefix: Option<MessageType>) -> (&'static str, Outcome) {
+        match cmd_prefix {
+            None => ("", Outcome::Ok),
+            Some(t) => match t {
+                MessageType::Warning => ("⚠️ ", Outcome::Rejected),
+                MessageType::Success => ("✅ ", Outcome::Ok),
+                MessageType::Error => ("‼️ ", Outcome::Error),
+            },
+        }
+    }
+
+    /// Given a message that starts with `!q`, look up the command
+    /// being run and send it to be handled by that command's handler
+    fn queue_command(&mut self, msg: &Message) -> Outcome {
+        let split_message = msg.message.split_ascii_whitespace().collect::<Vec<&str>>();
+
+        if split_message.len() < 2 {
+            return self.send(
+            self.tr(msg.guild_id, msg.author, "invalid_syntax", &[]),
+            Some(MessageType::Warning))
+        }
+
+        // Figure out which command is being run.
+        // Commands the user doesn't have permission to run are treated
+        // as if they don't exist
+        let name = split_message[1].to_ascii_lowercase();
+        match self.commands.get(&name) {
+            Some(cmd) if cmd.required_role() <= self.role_of(msg.guild_id, msg.author) => {
+                cmd.handle(self, msg, &split_message[2..])
+            },
+            _ => self.send_invalid_syntax(msg.guild_id, msg.author),
+        }
+    }
+
+    /// The state of a server. Every server a message has been
+    /// sent in exists (see `on_message`), and asking for any other panics
+    pub fn guild(&self, id: u64) -> &Guild {
+        &self.guilds[&id]
+    }
+
+    /// The state of a server which can be modified
+    fn guild_mut(&mut self, id: u64) -> &mut Guild {
+        self.guilds.get_mut(&id).expect("messages always create their server")
+    }
+
+    /// Every user within a server's queue (front first), using their latest name
+    fn queued_users(&self, guild: u64) -> Vec<&DiscordUser> {
+        self.guild(guild).queue.iter().filter_map(|id| self.users.get(id)).collect()
+    }
+
+    /// Figure out which users a command is aimed at. Usually these are the
+    /// users mentioned within the message but if there aren't any, each
+    /// argument is looked up as a user ID, tag (`Russ#0004`), or mention
+    fn targets(&self, msg: &Message, args: &[&str]) -> Option<Vec<DiscordUser>> {
+        if msg.mentions.is_some() {
+            return msg.mentions.clone();
+        }
+        let users = args.iter().filter_map(|a| self.users.resolve(a)).cloned().collect::<Vec<DiscordUser>>();
+        match users.is_empty() {
+            true => None,
+            false => Some(users),
+        }
+    }
+
+    /// Get the language a user should be replied to in.
+    /// Their own choice wins, otherwise the server's language is used
+    fn locale_of(&self, guild: u64, user: &DiscordUser) -> Locale {
+        *self.user_locales.get(&user.uuid).unwrap_or(&self.guild(guild).config.locale)
+    }
+
+    /// Translate the message `key` into the language of `user`.
+    /// `{mention}` is always filled in with the user's mention
+    pub fn tr(&self, guild: u64, user: &DiscordUser, key: &str, args: &Args) -> String {
+        let mut all_args = vec![("mention", user.get_mention())];
+        all_args.extend(args.iter().cloned());
+        self.catalog.get(self.locale_of(guild, user), key, &all_args)
+    }
+
+    /// Get the role of a user within a server which decides what commands they can run
+    fn role_of(&self, guild: u64, user: &DiscordUser) -> Role {
+        let config = &self.guild(guild).config;
+        match (config.head_tas.contains(&user.uuid), user.is_ta() || config.tas.contains(&user.uuid)) {
+            (true, _) => Role::HeadTa,
+            (false, true) => Role::Ta,
+            (false, false) => Role::Student,
+        }
+    }
+
+    /// Notify the user the command they typed was invalid
+    fn send_invalid_syntax(&self, guild: u64, user: &DiscordUser) -> Outcome {
+        self.send(
+            self.tr(guild, user, "invalid_format", &[]),
+            Some(MessageType::Warning))
+    }
+
+    /// Respond with a friendly "Pong!"
+    fn queue_ping(&self, guild: u64, user: &DiscordUser) -> Outcome {
+        self.send(self.tr(guild, user, "pong", &[]), None)
+    }
+
+    /// Add the given user to the queue (at the end)
+    /// and send a response message. `topic` is what they need help with
+    /// (an empty string if they didn't say)
+    fn q_join(&mut self, guild: u64, user: &DiscordUser, topic: &str) -> Outcome {
+        if self.guild(guild).queue.contains(user.uuid) {
+            return self.send(self.tr(guild, user, "already_in_queue", &[]), Some(MessageType::Warning));
+        }
+        let now = self.clock.now();
+        let g = self.guild_mut(guild);
+        g.push_back(user.uuid, now);
+        g.set_topic(user.uuid, Some(topic));
+        let outcome = self.send(self.tr(guild, user, "joined", &[("position", self.guild(guild).queue.len().to_string())]), Some(MessageType::Success));
+        self.notify_webhooks(WebhookEvent::Join, guild, user.uuid, None, topic);
+        self.run_hook(Hook::Join, guild, user, Map::new());
+        outcome
+    }
+
+    /// Remove the given user from the queue
+    /// and send a response message
+    fn q_leave(&mut self, guild: u64, user: &DiscordUser) -> Outcome {
+        // Scripts are told what they needed help with (which is forgotten once they leave the queue)
+        let topic = self.guild(guild).topics.get(&user.uuid).cloned().unwrap_or_default();
+        match self.guild_mut(guild).remove(user.uuid) {
+            Some(joined) => {
+                self.end_session(guild, user.uuid, Some(joined), None, SessionOutcome::Left);
+                let outcome = self.send(self.tr(guild, user, "left", &[]), Some(MessageType::Success));
+                self.notify_webhooks(WebhookEvent::Leave, guild, user.uuid, None, &topic);
+                self.run_hook(Hook::Leave, guild, user, Map::from([("topic".into(), Dynamic::from(topic))]));
+                outcome
+            },
+            None => self.send(self.tr(guild, user, "not_in_queue", &[]), Some(MessageType::Warning)),
+        }
+    }
+
+    /// Notify the user what position (index+1)
+    /// they currently are at from within the queue
+    fn q_position(&self, guild: u64, user: &DiscordUser) -> Outcome {
+        match self.guild(guild).queue.position(user.uuid) {
+            Some(i) => {
+                self.send(self.tr(guild, user, "position", &[("position", (i+1).to_string())]), None)
+            },
+            None => self.send(self.tr(guild, user, "not_in_queue", &[]), Some(MessageType::Warning)),
+        }
+    }
+
+    /// Change the topic of a student who is in the queue
+    /// (after they edit the message they joined with)
+    fn q_edit_topic(&mut self, guild: u64, user: &DiscordUser, topic: &str) -> Outcome {
+        if !self.guild_mut(guild).set_topic(user.uuid, Some(topic)) {
+            return self.send(self.tr(guild, user, "not_in_queue", &[]), Some(MessageType::Warning));
+        }
+        match self.guild(guild).topics.get(&user.uuid) {
+            Some(t) => self.send(self.tr(guild, user, "topic_updated", &[("topic", t.clone())]), Some(MessageType::Success)),
+            None => self.send(self.tr(guild, user, "topic_cleared", &[]), Some(MessageType::Success)),
+        }
+    }
+
+    /// A student's tag along with what they need help with (if they said)
+    fn describe(&self, guild: u64, student: &DiscordUser) -> String {
+        match self.guild(guild).topics.get(&student.uuid) {
+            Some(topic) => format!("{:?} ({})", student, topic),
+            None => format!("{:?}", student),
+        }
+    }
+
+    /// List out all students within the queue
+    /// (along with what they need help with, if they said)
+    /// and the TAs who are on duty
+    fn q_list(&self, guild: u64, user: &DiscordUser) -> Outcome {
+        let students = self.queued_users(guild).iter().map(|s| self.describe(guild, s)).collect::<Vec<String>>();
+        let mut list = self.tr(guild, user, "list", &[("queue", format!("[{}]", students.join(", ")))]);
+        if !self.guild(guild).on_duty.is_empty() {
+            list = format!("{}\n{}", list, self.tr(guild, user, "duty.roster", &[("tas", self.roster(guild))]));
+        }
+        self.send(list, None)
+    }
+
+    /// Assumes the user is a TA. Posts and pins a message showing the queue
+    /// which students can click Join or Leave on. It is kept up to date as the
+    /// queue changes. Any older pinned queue message is deleted
+    fn q_pin(&mut self, guild: u64) -> Outcome {
+        let text = self.status_text(guild);
+        let locale = self.guild(guild).config.locale;
+        let buttons = [
+            Button { id: JOIN_BUTTON, label: self.catalog.get(locale, "status.join", &[]) },
+            Button { id: LEAVE_BUTTON, label: self.catalog.get(locale, "status.leave", &[]) },
+        ];
+        let id = self.transport.send_buttons(&text, &buttons);
+        self.transport.pin(id);
+        self.transport.react(id, JOIN_EMOJI);
+        self.transport.react(id, LEAVE_EMOJI);
+
+        let g = self.guild_mut(guild);
+        let old = g.status_message.replace(id);
+        g.status_text = text;
+        if let Some(old) = old {
+            self.transport.delete(old);
+        }
+        Outcome::Ok
+    }
+
+    /// Assumes the user is a TA. It pops the next person off the
+    /// queue and notifies the TA of what student is next
+    ///
+    /// The student is given to the mentioned TA if there is one. Otherwise,
+    /// if any TAs are on duty they take turns (so work is shared evenly),
+    /// and if nobody is on duty the TA who ran the command helps them
+    fn q_next(&mut self, guild: u64, user: &DiscordUser, mentions: Option<&[DiscordUser]>) -> Outcome {
+        let chosen = match mentions {
+            Some([ta]) if self.role_of(guild, ta) >= Role::Ta => Some(ta.clone()),
+            Some([_]) => return self.send(self.tr(guild, user, "next.not_ta", &[]), Some(MessageType::Warning)),
+            Some(_) => return self.send(self.tr(guild, user, "mention_single", &[]), Some(MessageType::Warning)),
+            None => None,
+        };
+        if self.guild(guild).queue.is_empty() {
+            return self.send(self.tr(guild, user, "queue_empty", &[]), None);
+        }
+        let assigned = chosen.is_some() || !self.guild(guild).on_duty.is_empty();
+        let ta = match chosen {
+            Some(ta) => ta,
+            None => match self.guild_mut(guild).next_on_duty().and_then(|id| self.users.get(id)) {
+                Some(ta) => ta.clone(),
+                None => user.clone(),
+            },
+        };
+
+        let g = self.guild_mut(guild);
+        // Scripts are told what the student needs help with (which is forgotten once they leave the queue)
+        let topic = g.queue.get(0).and_then(|id| g.topics.get(&id).cloned()).unwrap_or_default();
+        let (student_id, joined) = g.pop_front().expect("the queue isn't empty");
+        // Remember who was called in case they don't show up
+        g.called.insert(ta.uuid, student_id);
+        self.end_session(guild, student_id, joined, Some(&ta), SessionOutcome::Helped);
+        self.open_thread(guild, &ta, student_id);
+
+        let mention = self.users.get(student_id).map_or(format!("<@{}>", student_id), |s| s.get_mention());
+        let outcome = match assigned {
+            true => self.send(self.tr(guild, user, "next.assigned", &[("student", mention), ("ta", ta.get_mention())]), None),
+            false => self.send(self.tr(guild, user, "next", &[("student", mention)]), None),
+        };
+        self.notify_webhooks(WebhookEvent::Next, guild, student_id, Some(&ta), &topic);
+        if let Some(student) = self.users.get(student_id).cloned() {
+            self.run_hook(Hook::Next, guild, &student, Map::from([
+                ("topic".into(), Dynamic::from(topic)),
+                ("ta".into(), Dynamic::from(ta.get_tag())),
+                ("ta_mention".into(), Dynamic::from(ta.get_mention())),
+            ]));
+        }
+        outcome
+    }
+
+    /// Put every student whose appointment has just started at the front of the
+    /// queue (ahead of walk-ins, in the order their appointments started).
+    /// Slots nobody booked are dropped
+    fn start_appointments(&mut self) {
+        let now = self.clock.now();
+        let mut guilds = self.guilds.keys().copied().collect::<Vec<u64>>();
+        guilds.sort();
+        for guild in guilds {
+            let due = self.guild_mut(guild).bookings.take_due(now);
+            if due.is_empty() {
+                continue;
+            }
+            for (index, slot) in due.iter().enumerate() {
+                let student = match slot.student.and_then(|id| self.users.get(id)) {
+                    Some(s) => s.clone(),
+                    None => continue,
+                };
+                let ta = self.users.get(slot.ta).cloned();
+                let g = self.guild_mut(guild);
+                match g.queue.position(student.uuid) {
+                    // They're already closer to the front than their appointment would put them
+                    Some(position) if position <= index => {},
+                    Some(_) => g.move_to(student.uuid, index),
+                    None => {
+                        g.insert(index, student.uuid, now);
+                        self.notify_webhooks(WebhookEvent::Join, guild, student.uuid, ta.as_ref(), "");
+                    },
+                }
+                let ta = ta.map_or(format!("<@{}>", slot.ta), |t| t.get_mention());
+                self.send(self.tr(guild, &student, "appointment.started", &[("student", student.get_mention()), ("ta", ta)]), None);
+            }
+            self.update_status(guild);
+            self.update_reminders(guild);
+        }
+    }
+
+    /// Arguments for messages about a slot: its `id`, the `time` it starts and its `ta`
+    fn slot_args(&self, guild: u64, slot: &Slot) -> Vec<(&'static str, String)> {
+        vec![
+            ("id", slot.id.to_string()),
+            ("time", format_time(slot.start, self.guild(guild).config.timezone)),
+            ("ta", self.users.get(slot.ta).map_or(slot.ta.to_string(), |t| t.get_tag())),
+        ]
+    }
+
+    /// Tell the user why a slot couldn't be added, booked or cancelled
+    fn send_booking_error(&self, guild: u64, user: &DiscordUser, error: BookingError) -> Outcome {
+        let (key, args) = match error {
+            BookingError::Conflict(slot) => ("slots.conflict", self.slot_args(guild, &slot)),
+            BookingError::NotFound(id) => ("slots.not_found", vec![("id", id.to_string())]),
+            BookingError::AlreadyBooked(slot) => ("book.already", self.slot_args(guild, &slot)),
+            BookingError::Taken(id) => ("book.taken", vec![("id", id.to_string())]),
+            BookingError::NoneFree => ("book.none_free", Vec::new()),
+            BookingError::NotBooked => ("cancel.none", Vec::new()),
+            BookingError::TooLate(slot) => {
+                let mut args = self.slot_args(guild, &slot);
+                args.push(("deadline", format_duration(self.guild(guild).config.cancel_deadline)));
+                ("cancel.too_late", args)
+            },
+        };
+        self.send(self.tr(guild, user, key, &args), Some(MessageType::Warning))
+    }
+
+    /// `!q slots` lists upcoming appointment slots. TAs can also add slots with
+    /// `!q slots add DAY HH:MM-HH:MM LENGTH` and delete one with `!q slots remove ID`
+    /// (which tells the student who booked it)
+    fn q_slots(&mut self, guild: u64, user: &DiscordUser, args: &[&str]) -> Outcome {
+        let is_ta = self.role_of(guild, user) >= Role::Ta;
+        match args {
+            [] => self.list_slots(guild, user, is_ta),
+            [add, times @ ..] if add.eq_ignore_ascii_case("add") && is_ta => self.add_slots(guild, user, times),
+            [remove, id] if remove.eq_ignore_ascii_case("remove") && is_ta => match id.trim_start_matches('#').parse::<u64>() {
+                Ok(id) => self.remove_slot(guild, user, id),
+                Err(_) => self.send_invalid_syntax(guild, user),
+            },
+            _ => self.send_invalid_syntax(guild, user),
+        }
+    }
+
+    /// List every upcoming slot. Students only see whether a slot is booked
+    /// but TAs see who booked it
+    fn list_slots(&self, guild: u64, user: &DiscordUser, is_ta: bool) -> Outcome {
+        let slots = self.guild(guild).bookings.slots();
+        if slots.is_empty() {
+            return self.send(self.tr(guild, user, "slots.none", &[]), None);
+        }
+        let lines = slots.iter().map(|slot| {
+            let mut args = self.slot_args(guild, slot);
+            let key = match slot.student {
+                None => "slots.free",
+                Some(id) if id == user.uuid => "slots.yours",
+                Some(id) if is_ta => {
+                    args.push(("student", self.users.get(id).map_or(id.to_string(), |s| s.get_tag())));
+                    "slots.booked_by"
+                },
+                Some(_) => "slots.booked",
+            };
+            self.tr(guild, user, key, &args)
+        }).collect::<Vec<String>>();
+        self.send(self.tr(guild, user, "slots.list", &[("slots", lines.join("\n"))]), None)
+    }
+
+    /// Assumes the user is a TA. Add slots for them (`args` are everything after `add`)
+    fn add_slots(&mut self, guild: u64, user: &DiscordUser, args: &[&str]) -> Outcome {
+        let timezone = self.guild(guild).config.timezone;
+        let (start, end, length) = match parse_slots(args, self.clock.now(), timezone) {
+            Some(parsed) => parsed,
+            None => return self.send(self.tr(guild, user, "slots.invalid", &[]), Some(MessageType::Warning)),
+        };
+        let slots = match self.guild_mut(guild).bookings.add(user.uuid, start, end, length) {
+            Ok(slots) => slots,
+            Err(e) => return self.send_booking_error(guild, user, e),
+        };
+        let last = slots.last().map_or(end, |s| s.end);
+        self.send(self.tr(guild, user, "slots.added", &[
+            ("count", slots.len().to_string()),
+            ("length", args[2].to_string()),
+            ("time", format_time(start, timezone)),
+            ("end", last.with_timezone(&timezone).format("%H:%M").to_string()),
+            ("first", slots.first().map_or(0, |s| s.id).to_string()),
+            ("last", slots.last().map_or(0, |s| s.id).to_string()),
+        ]), Some(MessageType::Success))
+    }
+
+    /// Assumes the user is a TA. Delete a slot, telling the student who booked it
+    fn remove_slot(&mut self, guild: u64, user: &DiscordUser, id: u64) -> Outcome {
+        let slot = match self.guild_mut(guild).bookings.remove(id) {
+            Ok(slot) => slot,
+            Err(e) => return self.send_booking_error(guild, user, e),
+        };
+        if let Some(student) = slot.student.and_then(|id| self.users.get(id)) {
+            let mut args = self.slot_args(guild, &slot);
+            args.push(("queue", self.guild(guild).config.name.clone()));
+            self.send_dm(student, self.tr(guild, student, "slots.removed_dm", &args), None);
+        }
+        self.send(self.tr(guild, user, "slots.removed", &self.slot_args(guild, &slot)), Some(MessageType::Success))
+    }
+
+    /// Book the slot with the given ID (or the earliest free slot) for the user
+    fn q_book(&mut self, guild: u64, user: &DiscordUser, args: &[&str]) -> Outcome {
+        let id = match args {
+            [] => None,
+            [id] => match id.trim_start_matches('#').parse::<u64>() {
+                Ok(id) => Some(id),
+                Err(_) => return self.send_invalid_syntax(guild, user),
+            },
+            _ => return self.send_invalid_syntax(guild, user),
+        };
+        let slot = match self.guild_mut(guild).bookings.book(user.uuid, id) {
+            Ok(slot) => slot,
+            Err(e) => return self.send_booking_error(guild, user, e),
+        };
+        let config = &self.guild(guild).config;
+        let mut args = self.slot_args(guild, &slot);
+        args.push(("deadline", format_time(slot.start - config.cancel_deadline, config.timezone)));
+        self.send(self.tr(guild, user, "book.booked", &args), Some(MessageType::Success))
+    }
+
+    /// Cancel the user's appointment (as long as it isn't too close to starting)
+    fn q_cancel(&mut self, guild: u64, user: &DiscordUser) -> Outcome {
+        let (now, deadline) = (self.clock.now(), self.guild(guild).config.cancel_deadline);
+        match self.guild_mut(guild).bookings.cancel(user.uuid, now, deadline) {
+            Ok(slot) => self.send(self.tr(guild, user, "cancel.cancelled", &self.slot_args(guild, &slot)), Some(MessageType::Success)),
+            Err(e) => self.send_booking_error(guild, user, e),
+        }
+    }
+
+    /// Assumes the user is a TA. `!q duty` shows who is on duty,
+    /// `!q duty on` puts the user on duty and `!q duty off` takes them off.
+    /// If the last TA goes off duty while students are waiting, they're warned
+    fn q_duty(&mut self, guild: u64, user: &DiscordUser, args: &[&str]) -> Outcome {
+        let arg = args.first().map(|a| a.to_ascii_lowercase());
+        match arg.as_deref() {
+            None => self.send(self.tr(guild, user, "duty.roster", &[("tas", self.roster(guild))]), None),
+            Some("on") => match self.guild_mut(guild).go_on_duty(user.uuid) {
+                true => self.send(self.tr(guild, user, "duty.on", &[("tas", self.roster(guild))]), Some(MessageType::Success)),
+                false => self.send(self.tr(guild, user, "duty.already_on", &[]), Some(MessageType::Warning)),
+            },
+            Some("off") => {
+                if !self.guild_mut(guild).go_off_duty(user.uuid) {
+                    return self.send(self.tr(guild, user, "duty.already_off", &[]), Some(MessageType::Warning));
+                }
+                let outcome = self.send(self.tr(guild, user, "duty.off", &[]), Some(MessageType::Success));
+                let g = self.guild(guild);
+                if g.on_duty.is_empty() && !g.queue.is_empty() {
+                    let students = self.queued_users(guild).iter().map(|s| s.get_mention()).collect::<Vec<String>>();
+                    let warning = self.catalog.get(g.config.locale, "duty.nobody", &[("students", students.join(" "))]);
+                    self.send(warning, Some(MessageType::Warning));
+                }
+                outcome
+            },
+            Some(_) => self.send_invalid_syntax(guild, user),
+        }
+    }
+
+    /// The tags of every on-duty TA (or `-` if there aren't any)
+    fn roster(&self, guild: u64) -> String {
+        let tas = self.guild(guild).on_duty.iter()
+            .map(|id| self.users.get(*id).map_or(format!("<@{}>", id), |ta| ta.get_tag()))
+            .collect::<Vec<String>>();
+        match tas.is_empty() {
+            true => String::from("-"),
+            false => tas.join(", "),
+        }
+    }
+
+    /// Assumes the user is a TA.
+    /// Mark a called student (the mentioned one, or else the one the TA
+    /// called last) as a no-show. They are put back into the queue behind the
+    /// first `noshow_skip` students (see `GuildConfig::noshow_skip`), unless they have reached
+    /// `noshow_strikes` no-shows today in which case they are removed.
+    /// Either way the student is sent a direct message about it
+    fn q_noshow(&mut self, guild: u64, user: &DiscordUser, mentions: Option<&[DiscordUser]>) -> Outcome {
+        let g = self.guild(guild);
+        let student_id = match mentions {
+            Some([student]) if g.called.values().any(|&id| id == student.uuid) => student.uuid,
+            Some([_]) => return self.send(self.tr(guild, user, "noshow.not_called", &[]), Some(MessageType::Warning)),
+            Some(_) => return self.send(self.tr(guild, user, "mention_single", &[]), Some(MessageType::Warning)),
+            None => match g.called.get(&user.uuid) {
+                Some(&id) => id,
+                None => return self.send(self.tr(guild, user, "noshow.nobody_called", &[]), Some(MessageType::Warning)),
+            },
+        };
+        let student = match self.users.get(student_id) {
+            Some(s) => s.clone(),
+            None => return self.send(self.tr(guild, user, "noshow.nobody_called", &[]), Some(MessageType::Warning)),
+        };
+
+        // They may have already rejoined the queue since being called
+        let now = self.clock.now();
+        let g = self.guild_mut(guild);
+        g.called.retain(|_, id| *id != student_id);
+        let rejoined = g.queue.contains(student_id);
+        if let Some(ta) = g.threads.iter().find(|(_, t)| t.student == student_id).map(|(ta, _)| *ta) {
+            self.close_thread(guild, ta);
+        }
+
+        // Their visit was recorded as helped when they were called
+        if let Err(e) = self.history.amend_last(guild, student_id, SessionOutcome::NoShow) {
+            eprintln!("Unable to save history: {}", e);
+        }
+        let strikes = self.history.no_shows_on(guild, student_id, now);
+        // The field is public so it may have been set to 0 without `with_no_shows`
+        let max = self.guild(guild).config.noshow_strikes.max(1);
+
+        let queue = self.guild(guild).config.name.clone();
+        let mention = student.get_mention();
+        if strikes >= max {
+            if rejoined {
+                let joined = self.guild_mut(guild).remove(student_id);
+                self.end_session(guild, student_id, joined, Some(user), SessionOutcome::Removed);
+            }
+            self.send_dm(&student, self.tr(guild, &student, "noshow.removed_dm", &[("queue", queue), ("count", strikes.to_string())]), None);
+            return self.send(self.tr(guild, user, "noshow.removed", &[("student", mention), ("count", strikes.to_string())]), Some(MessageType::Success));
+        }
+
+        let g = self.guild_mut(guild);
+        let index = g.config.noshow_skip;
+        match rejoined {
+            true => g.move_to(student_id, index),
+            false => g.insert(index, student_id, now),
+        }
+        let position = (g.queue.position(student_id).unwrap_or(index) + 1).to_string();
+        self.send_dm(&student, self.tr(guild, &student, "noshow.moved_dm", &[
+            ("queue", queue),
+            ("position", position.clone()),
+            ("count", (max - strikes).to_string()),
+        ]), None);
+        self.send(self.tr(guild, user, "noshow.moved", &[
+            ("student", mention),
+            ("position", position),
+            ("strikes", strikes.to_string()),
+            ("max", max.to_string()),
+        ]), Some(MessageType::Success))
+    }
+
+    /// Assumes the user is a TA. Marks the student they called last as helped
+    /// (so they can't be marked as a no-show anymore) and archives the thread
+    /// they were helping them in, if the server uses help threads
+    fn q_done(&mut self, guild: u64, user: &DiscordUser) -> Outcome {
+        let student = match self.guild_mut(guild).called.remove(&user.uuid) {
+            Some(id) => id,
+            None => return self.send(self.tr(guild, user, "done.none", &[]), Some(MessageType::Warning)),
+        };
+        let thread = self.close_thread(guild, user.uuid);
+        let mention = self.users.get(student).map_or(format!("<@{}>", student), |s| s.get_mention());
+        let key = match thread {
+            Some(_) => "done",
+            None => "done.no_thread",
+        };
+        let outcome = self.send(self.tr(guild, user, key, &[("student", mention)]), Some(MessageType::Success));
+        self.notify_webhooks(WebhookEvent::Done, guild, student, Some(user), "");
+        if self.guild(guild).config.surveys {
+            self.send_survey(guild, user, student);
+        }
+        outcome
+    }
+
+    /// Privately ask a student to rate the help a TA just gave them.
+    /// The message is reacted to with every rating so they can click one
+    fn send_survey(&mut self, guild: u64, ta: &DiscordUser, student: u64) {
+        let student = match self.users.get(student) {
+            Some(s) => s,
+            None => return,
+        };
+        let message = self.tr(guild, student, "feedback.ask", &[("ta", ta.get_tag()), ("queue", self.guild(guild).config.name.clone())]);
+        let id = self.transport.send_dm(student, &message);
+        for emoji in RATING_EMOJI {
+            self.transport.react(id, emoji);
+        }
+        self.feedback.ask(Survey { guild_id: guild, ta_id: ta.uuid, student_id: student.uuid, message_id: id });
+    }
+
+    /// `!q feedback RATING [comment]` answers the latest survey a student was sent.
+    /// Head TAs can use `!q feedback summary` to see how every TA has been rated.
+    ///
+    /// Answers are typed in the channel, so the message (`message_id`) is deleted
+    /// straight away and every reply is sent privately. Otherwise everyone could
+    /// see who gave which rating
+    fn q_feedback(&mut self, guild: u64, user: &DiscordUser, message_id: u64, args: &[&str]) -> Outcome {
+        if let [summary] = args {
+            if summary.eq_ignore_ascii_case("summary") && self.role_of(guild, user) >= Role::HeadTa {
+                return self.feedback_summary(guild, user);
+            }
+        }
+        // Messages which didn't come from Discord (see `Message::id`) can't be deleted
+        if message_id != 0 {
+            self.transport.delete(message_id);
+        }
+        match args {
+            [rating, comment @ ..] => match rating.parse::<u8>() {
+                Ok(rating) if (1..=5).contains(&rating) => {
+                    let comment = comment.join(" ").chars().take(feedback::MAX_COMMENT_LENGTH).collect::<String>();
+                    match self.feedback.take_survey(user.uuid, None) {
+                        Some(survey) => self.answer_survey(survey, user, rating, Some(comment).filter(|c| !c.is_empty())),
+                        None => self.send_dm(user, self.tr(guild, user, "feedback.no_survey", &[]), Some(MessageType::Warning)),
+                    }
+                },
+                _ => self.send_dm(user, self.tr(guild, user, "invalid_format", &[]), Some(MessageType::Warning)),
+            },
+            _ => self.send_dm(user, self.tr(guild, user, "invalid_format", &[]), Some(MessageType::Warning)),
+        }
+    }
+
+    /// Store a student's answer to a survey (without who they are) and thank them.
+    /// Everything is sent privately since the answer is meant to be anonymous
+    fn answer_survey(&mut self, survey: Survey, student: &DiscordUser, rating: u8, comment: Option<String>) -> Outcome {
+        let response = Response {
+            guild_id: survey.guild_id,
+            ta_id: survey.ta_id,
+            date: self.clock.now().date_naive(),
+            rating,
+            comment,
+        };
+        if let Err(e) = self.feedback.record(response) {
+            eprintln!("Unable to save feedback: {}", e);
+        }
+        self.send_dm(student, self.tr(survey.guild_id, student, "feedback.thanks", &[]), Some(MessageType::Success))
+    }
+
+    /// Assumes the user is a head TA. Privately send them how each TA within
+    /// the server has been rated along with the latest comments about them.
+    /// It isn't posted in the channel so TAs don't see each other's feedback
+    fn feedback_summary(&self, guild: u64, user: &DiscordUser) -> Outcome {
+        // Only show the latest few comments so the message doesn't get too long
+        const MAX_COMMENTS: usize = 3;
+
+        let summaries = self.feedback.summarise(guild);
+        if summaries.is_empty() {
+            return self.send_dm(user, self.tr(guild, user, "feedback.none", &[]), None);
+        }
+        let mut tas = summaries.iter()
+            .map(|(id, summary)| (self.users.get(*id).map_or(id.to_string(), |t| t.get_tag()), summary))
+            .collect::<Vec<_>>();
+        tas.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let lines = tas.iter().flat_map(|(ta, summary)| {
+            let ratings = summary.ratings.iter().enumerate()
+                .map(|(i, count)| format!("{}★ {}", i + 1, count))
+                .collect::<Vec<String>>();
+            let line = self.tr(guild, user, "feedback.ta", &[
+                ("ta", ta.clone()),
+                ("average", format!("{:.1}", summary.average())),
+                ("count", summary.count().to_string()),
+                ("ratings", ratings.join(", ")),
+            ]);
+            let comments = summary.comments.iter().take(MAX_COMMENTS)
+                .map(|c| self.tr(guild, user, "feedback.comment", &[("comment", c.clone())]));
+            std::iter::once(line).chain(comments)
+        }).collect::<Vec<String>>();
+        self.send_dm(user, self.tr(guild, user, "feedback.summary", &[
+            ("queue", self.guild(guild).config.name.clone()),
+            ("tas", lines.join("\n")),
+        ]), None)
+    }
+
+    /// Open a private thread for a TA to help the student they just called in
+    /// (if the server uses help threads). Any thread the TA still had open is archived
+    fn open_thread(&mut self, guild: u64, ta: &DiscordUser, student: u64) {
+        self.close_thread(guild, ta.uuid);
+        let student = match self.users.get(student) {
+            Some(s) if self.guild(guild).config.help_threads => s.clone(),
+            _ => return,
+        };
+        // Both of them see the thread's name so the server's language is used
+        let name = self.catalog.get(self.guild(guild).config.locale, "thread.name", &[
+            ("student", student.get_tag()),
+            ("ta", ta.get_tag()),
+        ]);
+        let id = self.transport.create_thread(&name, &[&student, ta]);
+        self.transport.send_to_thread(id, &self.tr(guild, &student, "thread.welcome", &[("ta", ta.get_mention())]));
+        self.guild_mut(guild).threads.insert(ta.uuid, HelpThread { id, student: student.uuid });
+    }
+
+    /// Archive the help thread a TA has open (if they have one) and return it
+    fn close_thread(&mut self, guild: u64, ta: u64) -> Option<HelpThread> {
+        let thread = self.guild_mut(guild).threads.remove(&ta)?;
+        self.transport.archive_thread(thread.id);
+        Some(thread)
+    }
+
+    /// Assumes the user is a TA.
+    /// Completely empty the queue content
+    /// Within actual QueueBot, a confirmation message
+    /// is sent but this is omitted to make things simpler
+    fn q_clear(&mut self, guild: u64, user: &DiscordUser) -> Outcome {
+        let mut cleared = Vec::new();
+        for (id, joined) in self.guild_mut(guild).clear() {
+            self.end_session(guild, id, joined, Some(user), SessionOutcome::Cleared);
+            cleared.push(self.users.get(id).map_or(id.to_string(), |s| s.get_tag()));
+        }
+        let outcome = self.send(self.tr(guild, user, "cleared", &[]), Some(MessageType::Success));
+        if !self.webhooks.is_empty() {
+            let payload = WebhookPayload {
+                ta: Some(user.get_tag()),
+                ta_id: Some(user.uuid.to_string()),
+                cleared,
+                ..self.webhook_payload(WebhookEvent::Clear, guild)
+            };
+            self.webhooks.emit(payload);
+        }
+        outcome
+    }
+
+    /// Assumes the user is a TA.
+    /// The user must mention a single person and that person
+    /// will then be added to the queue
+    fn q_add_other(&mut self, guild: u64, user: &DiscordUser, mentions: Option<&[DiscordUser]>) -> Outcome {
+        match mentions {
+            None => self.send(self.tr(guild, user, "must_mention", &[]), Some(MessageType::Warning)),
+            Some(v) => if v.len() > 1 {
+                self.send(self.tr(guild, user, "mention_single", &[]), Some(MessageType::Warning))
+            } else {
+                let student = v.first().unwrap();
+                if self.guild(guild).queue.contains(student.uuid) {
+                    self.send(self.tr(guild, user, "student_already_in_queue", &[]), Some(MessageType::Warning))
+                } else {
+                    let now = self.clock.now();
+                    self.guild_mut(guild).push_back(student.uuid, now);
+                    let outcome = self.send(self.tr(guild, user, "student_added", &[]), Some(MessageType::Success));
+                    self.notify_webhooks(WebhookEvent::Join, guild, student.uuid, Some(user), "");
+                    outcome
+                }
+            },
+        }
+    }
+
+    /// Assumes the user is a TA.
+    /// The user must mention a single person and that person
+    /// will then be removed from the queue
+    fn q_remove_other(&mut self, guild: u64, user: &DiscordUser, mentions: Option<&[DiscordUser]>) -> Outcome {
+        match mentions {
+            None => self.send(self.tr(guild, user, "must_mention", &[]), Some(MessageType::Warning)),
+            Some(v) => if v.len() > 1 {
+                self.send(self.tr(guild, user, "mention_single", &[]), Some(MessageType::Warning))
+            } else {
+                let student = v.first().unwrap();
+                let topic = self.guild(guild).topics.get(&student.uuid).cloned().unwrap_or_default();
+                let joined = match self.guild_mut(guild).remove(student.uuid) {
+                    None => return self.send(self.tr(guild, user, "student_not_in_queue", &[]), Some(MessageType::Warning)),
+                    Some(joined) => joined,
+                };
+                self.end_session(guild, student.uuid, Some(joined), Some(user), SessionOutcome::Removed);
+                let outcome = self.send(self.tr(guild, user, "student_removed", &[]), Some(MessageType::Success));
+                self.notify_webhooks(WebhookEvent::Leave, guild, student.uuid, Some(user), &topic);
+                outcome
+            },
+        }
+    }
+
+    /// Show or change the language the bot replies to the user in.
+    ///  - `!q language` shows the current language
+    ///  - `!q language es` picks a language for the user
+    ///  - `!q language default` goes back to the server's language
+    ///  - `!q language server es` (TAs only) changes the server's language
+    fn q_language(&mut self, guild: u64, user: &DiscordUser, args: &[&str]) -> Outcome {
+        let available = Locale::ALL.iter().map(|l| l.code()).collect::<Vec<&str>>().join(", ");
+        let name_of = |bot: &QueueBot, locale: Locale| bot.catalog.get(locale, "language.name", &[]);
+
+        match args {
+            [] => self.send(self.tr(guild, user, "language.current", &[
+                ("language", name_of(self, self.locale_of(guild, user))),
+                ("available", available),
+            ]), None),
+            [code] if code.eq_ignore_ascii_case("default") => {
+                self.user_locales.remove(&user.uuid);
+                self.send(self.tr(guild, user, "language.set", &[("language", name_of(self, self.guild(guild).config.locale))]), Some(MessageType::Success))
+            },
+            [server, code] if server.eq_ignore_ascii_case("server") && self.role_of(guild, user) >= Role::Ta => match Locale::from_code(code) {
+                None => self.send(self.tr(guild, user, "language.unknown", &[("code", code.to_string()), ("available", available)]), Some(MessageType::Warning)),
+                Some(locale) => {
+                    self.guild_mut(guild).config.locale = locale;
+                    self.send(self.tr(guild, user, "language.server_set", &[("language", name_of(self, locale))]), Some(MessageType::Success))
+                },
+            },
+            [code] => match Locale::from_code(code) {
+                None => self.send(self.tr(guild, user, "language.unknown", &[("code", code.to_string()), ("available", available)]), Some(MessageType::Warning)),
+                Some(locale) => {
+                    self.user_locales.insert(user.uuid, locale);
+                    self.send(self.tr(guild, user, "language.set", &[("language", name_of(self, locale))]), Some(MessageType::Success))
+                },
+            },
+            _ => self.send_invalid_syntax(guild, user),
+        }
+    }
+
+    /// Turn reminders about the user's place in the queue on or off.
+    /// With no arguments, tell the user whether they're on
+    fn q_notify(&mut self, guild: u64, user: &DiscordUser, args: &[&str]) -> Outcome {
+        match args.first().map(|a| a.to_ascii_lowercase()).as_deref() {
+            None => match self.notify_opt_out.contains(&user.uuid) {
+                true => self.send(self.tr(guild, user, "notify.status_off", &[]), None),
+                false => self.send(self.tr(guild, user, "notify.status_on", &[]), None),
+            },
+            Some("on") => {
+                self.notify_opt_out.remove(&user.uuid);
+                self.send(self.tr(guild, user, "notify.on", &[]), Some(MessageType::Success))
+            },
+            Some("off") => {
+                self.notify_opt_out.insert(user.uuid);
+                self.send(self.tr(guild, user, "notify.off", &[]), Some(MessageType::Success))
+            },
+            Some(_) => self.send_invalid_syntax(guild, user),
+        }
+    }
+
+    /// Show a student's past visits to the queue within this server.
+    ///  - `!q history` shows the user their own history
+    ///  - `!q history @user` (TAs only) shows someone else's
+    ///  - `!q history purge [@user]` deletes it (only TAs can delete someone else's)
+    fn q_history(&mut self, guild: u64, user: &DiscordUser, args: &[&str], mentions: Option<&[DiscordUser]>) -> Outcome {
+        // Only show the last few sessions so the channel doesn't get flooded
+        const MAX_ENTRIES: usize = 10;
+
+        let student = match mentions {
+            None => user.clone(),
+            Some([student]) if student == user || self.role_of(guild, user) >= Role::Ta => student.clone(),
+            Some([_]) => return self.send(self.tr(guild, user, "history.only_own", &[]), Some(MessageType::Warning)),
+            Some(_) => return self.send(self.tr(guild, user, "mention_exactly_one", &[]), Some(MessageType::Warning)),
+        };
+
+        if args.first().is_some_and(|a| a.eq_ignore_ascii_case("purge")) {
+            return match self.history.purge(guild, student.uuid) {
+                Err(e) => self.send(self.tr(guild, user, "history.save_error", &[("error", e.to_string())]), Some(MessageType::Error)),
+                Ok(count) => self.send(self.tr(guild, user, "history.purged", &[
+                    ("user", student.get_tag()),
+                    ("count", count.to_string()),
+                ]), Some(MessageType::Success)),
+            };
+        }
+
+        let sessions = self.history.of(guild, student.uuid);
+        if sessions.is_empty() {
+            return self.send(self.tr(guild, user, "history.none", &[("user", student.get_tag())]), None);
+        }
+        let lines = sessions.iter()
+            .skip(sessions.len().saturating_sub(MAX_ENTRIES))
+            .map(|session| {
+                let key = match session.outcome {
+                    SessionOutcome::Helped => "history.helped",
+                    SessionOutcome::NoShow => "history.no_show",
+                    SessionOutcome::Left => "history.left",
+                    SessionOutcome::Removed => "history.removed",
+                    SessionOutcome::Cleared => "history.cleared",
+                };
+                let ta = session.ta_id.and_then(|id| self.users.get(id)).map_or(String::from("?"), |t| t.get_tag());
+                self.tr(guild, user, key, &[
+                    ("date", session.joined.format("%Y-%m-%d %H:%M").to_string()),
+                    ("waited", format_duration(session.waited())),
+                    ("ta", ta),
+                ])
+            })
+            .collect::<Vec<String>>();
+        self.send(self.tr(guild, user, "history.entries", &[
+            ("user", student.get_tag()),
+            ("count", lines.len().to_string()),
+            ("entries", lines.join("\n")),
+        ]), None)
+    }
+
+    /// Delete everything stored about the user which isn't needed to keep
+    /// the queue running (`!q forget me`): their history in every server
+    /// and their language and reminder settings. The audit log is kept since
+    /// it is the record of who changed the queue, but it is still deleted
+    /// once each server's retention period passes
+    fn q_forget(&mut self, guild: u64, user: &DiscordUser, args: &[&str]) -> Outcome {
+        match args {
+            [me] if me.eq_ignore_ascii_case("me") => {},
+            _ => return self.send_invalid_syntax(guild, user),
+        }
+        let result = self.history.forget(user.uuid);
+        // Reply in their language before it's forgotten
+        let message = match &result {
+            Err(e) => self.tr(guild, user, "history.save_error", &[("error", e.to_string())]),
+            Ok(_) => self.tr(guild, user, "forget.done", &[]),
+        };
+        self.user_locales.remove(&user.uuid);
+        self.notify_opt_out.remove(&user.uuid);
+        self.feedback.forget(user.uuid);
+        match result {
+            Err(_) => self.send(message, Some(MessageType::Error)),
+            Ok(_) => self.send(message, Some(MessageType::Success)),
+        }
+    }
+
+    /// Assumes the user is a TA.
+    /// Show the most recent audit log entries involving the mentioned user
+    /// (either commands they ran or commands which mentioned them)
+    fn q_audit(&self, guild: u64, user: &DiscordUser, mentions: Option<&[DiscordUser]>) -> Outcome {
+        // Only show the last few entries so the channel doesn't get flooded
+        const MAX_ENTRIES: usize = 10;
+
+        let student = match mentions {
+            Some([student]) => student,
+            _ => return self.send(self.tr(guild, user, "mention_exactly_one", &[]), Some(MessageType::Warning)),
+        };
+        let log = match &self.audit {
+            None => return self.send(self.tr(guild, user, "audit.disabled", &[]), Some(MessageType::Warning)),
+            Some(log) => log,
+        };
+        let filter = AuditFilter { user_id: Some(student.uuid), guild_id: Some(guild), ..AuditFilter::default() };
+        match log.search(&filter) {
+            Err(e) => self.send(self.tr(guild, user, "audit.read_error", &[("error", e.to_string())]), Some(MessageType::Error)),
+            Ok(entries) if entries.is_empty() => self.send(self.tr(guild, user, "audit.none", &[("user", student.get_tag())]), None),
+            Ok(entries) => {
+                let lines = entries.iter()
+                    .skip(entries.len().saturating_sub(MAX_ENTRIES))
+                    .map(|e| e.to_string())
+                    .collect::<Vec<String>>();
+                self.send(self.tr(guild, user, "audit.entries", &[
+                    ("user", student.get_tag()),
+                    ("count", lines.len().to_string()),
+                    ("entries", lines.join("\n")),
+                ]), None)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::ManualClock;
+    use scripting::DEFAULT_SCRIPTS_DIR;
+    use transport::{MockTransport, Sent};
+
+    const GUILD_A: u64 = 1;
+    const GUILD_B: u64 = 2;
+
+    /// A bot in two servers, with a student and a TA who is only a TA in `GUILD_A`
+    fn setup() -> (QueueBot, DiscordUser, DiscordUser) {
+        let mut users = UserRegistry::new();
+        let student = users.register("Kapua", "0002", false).unwrap();
+        let ta = users.register("Dana", "0005", false).unwrap();
+        let bot = QueueBot::new()
+            .with_guild(GUILD_A, GuildConfig::new("A").with_ta(ta.uuid))
+            .with_guild(GUILD_B, GuildConfig::new("B"))
+            .with_users(users);
+        (bot, student, ta)
+    }
+
+    fn say(bot: &mut QueueBot, guild: u64, author: &DiscordUser, message: &str) {
+        bot.on_message(Message { id: 0, guild_id: guild, author, message: String::from(message), mentions: None });
+    }
+
+    #[test]
+    fn joining_only_affects_that_guild() {
+        let (mut bot, student, _) = setup();
+        say(&mut bot, GUILD_A, &student, "!q join");
+        assert_eq!(bot.guild(GUILD_A).queue, [student.uuid]);
+        assert!(bot.guild(GUILD_B).queue.is_empty());
+
+        // Joining a second server puts them at the front of that queue too
+        say(&mut bot, GUILD_B, &student, "!q join");
+        say(&mut bot, GUILD_B, &student, "!q leave");
+        assert_eq!(bot.guild(GUILD_A).queue, [student.uuid]);
+        assert!(bot.guild(GUILD_B).queue.is_empty());
+    }
+
+    #[test]
+    fn ta_commands_only_affect_that_guild() {
+        let (mut bot, student, ta) = setup();
+        say(&mut bot, GUILD_A, &student, "!q join");
+        say(&mut bot, GUILD_B, &student, "!q join");
+
+        say(&mut bot, GUILD_A, &ta, "!q clear");
+        assert!(bot.guild(GUILD_A).queue.is_empty());
+        assert_eq!(bot.guild(GUILD_B).queue, [student.uuid]);
+    }
+
+    #[test]
+    fn ta_roles_are_per_guild() {
+        let (mut bot, student, ta) = setup();
+        say(&mut bot, GUILD_B, &student, "!q join");
+
+        // Not a TA within B so the command is ignored
+        say(&mut bot, GUILD_B, &ta, "!q next");
+        assert_eq!(bot.guild(GUILD_B).queue, [student.uuid]);
+        assert_eq!(bot.role_of(GUILD_A, &ta), Role::Ta);
+        assert_eq!(bot.role_of(GUILD_B, &ta), Role::Student);
+    }
+
+    #[test]
+    fn config_is_per_guild() {
+        let (mut bot, student, ta) = setup();
+        say(&mut bot, GUILD_A, &ta, "!q language server es");
+        assert_eq!(bot.locale_of(GUILD_A, &student), Locale::Spanish);
+        assert_eq!(bot.locale_of(GUILD_B, &student), Locale::English);
+    }
+
+    /// A bot whose messages and time are controlled by the test.
+    /// Students are reminded 30 seconds after reaching position 2
+    fn setup_reminders() -> (QueueBot, MockTransport, ManualClock, DiscordUser, Vec<DiscordUser>) {
+        let mut users = UserRegistry::new();
+        let ta = users.register("Ben", "0001", true).unwrap();
+        let students = ["Kapua", "Bennett", "Russ"].iter()
+            .map(|name| users.register(name, "0002", false).unwrap())
+            .collect::<Vec<DiscordUser>>();
+        let transport = MockTransport::default();
+        let clock = ManualClock::new(chrono::Utc::now());
+        let bot = QueueBot::new()
+            .with_guild(GUILD_A, GuildConfig::new("CS 120").with_reminders(Some(2), chrono::Duration::seconds(30)))
+            .with_users(users)
+            .with_transport(transport.clone())
+            .with_clock(clock.clone());
+        (bot, transport, clock, ta, students)
+    }
+
+    /// Every direct message which was sent (ignoring channel messages)
+    fn dms(transport: &MockTransport) -> Vec<(u64, String)> {
+        transport.take().into_iter().filter_map(|s| match s {
+            Sent::Direct(id, message) => Some((id, message)),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn reminds_students_as_they_move_up() {
+        let (mut bot, transport, clock, ta, students) = setup_reminders();
+        for student in students.iter() {
+            say(&mut bot, GUILD_A, student, "!q join");
+        }
+        // Nobody is reminded about where they joined
+        assert!(dms(&transport).is_empty());
+
+        // Bennett moves to the front and Russ moves up to second
+        // but neither are reminded until the delay passes
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        assert!(dms(&transport).is_empty());
+        clock.advance(chrono::Duration::seconds(30));
+        bot.tick();
+        assert_eq!(dms(&transport), [
+            (students[1].uuid, String::from("<@Bennett> You are next in line in the CS 120 queue! A TA will be with you soon.")),
+            (students[2].uuid, String::from("<@Russ> You are now at position 2 in the CS 120 queue. Please get ready!")),
+        ]);
+
+        clock.advance(chrono::Duration::seconds(30));
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        clock.advance(chrono::Duration::seconds(30));
+        bot.tick();
+        assert_eq!(dms(&transport), [(students[2].uuid, String::from("<@Russ> You are next in line in the CS 120 queue! A TA will be with you soon."))]);
+
+        // Each milestone is only sent once
+        clock.advance(chrono::Duration::minutes(5));
+        bot.tick();
+        assert!(dms(&transport).is_empty());
+    }
+
+    #[test]
+    fn reminders_are_cancelled_if_the_queue_changes() {
+        let (mut bot, transport, clock, ta, students) = setup_reminders();
+        for student in students.iter() {
+            say(&mut bot, GUILD_A, student, "!q join");
+        }
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        say(&mut bot, GUILD_A, &students[2], "!q leave");
+        clock.advance(chrono::Duration::seconds(30));
+        bot.tick();
+        assert!(dms(&transport).iter().all(|(id, _)| *id != students[2].uuid));
+    }
+
+    #[test]
+    fn students_can_opt_out_of_reminders() {
+        let (mut bot, transport, clock, ta, students) = setup_reminders();
+        for student in students.iter() {
+            say(&mut bot, GUILD_A, student, "!q join");
+        }
+        say(&mut bot, GUILD_A, &students[2], "!q notify off");
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        clock.advance(chrono::Duration::seconds(30));
+        bot.tick();
+        assert!(dms(&transport).is_empty());
+    }
+
+    #[test]
+    fn no_shows_are_moved_back_then_removed() {
+        let (mut bot, transport, clock, ta, students) = setup_reminders();
+        bot.guild_mut(GUILD_A).config = GuildConfig::new("CS 120").with_reminders(None, chrono::Duration::zero()).with_no_shows(1, 2);
+        for student in students.iter() {
+            say(&mut bot, GUILD_A, student, "!q join");
+        }
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        transport.take();
+
+        // Kapua goes back behind Bennett
+        say(&mut bot, GUILD_A, &ta, "!q noshow");
+        assert_eq!(bot.guild(GUILD_A).queue, [students[1].uuid, students[0].uuid, students[2].uuid]);
+        assert!(dms(&transport).contains(&(students[0].uuid, String::from(
+            "<@Kapua> A TA called you from the CS 120 queue but couldn't find you, so you were moved back to position 2. \
+            If you miss your turn 1 more time you will be removed from the queue"
+        ))));
+
+        // The second time she is removed
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        say(&mut bot, GUILD_A, &ta, "!q noshow");
+        assert_eq!(bot.guild(GUILD_A).queue, [students[2].uuid]);
+        assert_eq!(bot.history.no_shows_on(GUILD_A, students[0].uuid, clock.now()), 2);
+    }
+
+    #[test]
+    fn no_shows_go_to_the_back_of_a_short_queue() {
+        let (mut bot, transport, _, ta, students) = setup_reminders();
+        bot.guild_mut(GUILD_A).config = GuildConfig::new("CS 120").with_reminders(None, chrono::Duration::zero()).with_no_shows(5, 3);
+        say(&mut bot, GUILD_A, &students[0], "!q join");
+        say(&mut bot, GUILD_A, &students[1], "!q join");
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        transport.take();
+
+        say(&mut bot, GUILD_A, &ta, "!q noshow");
+        assert_eq!(bot.guild(GUILD_A).queue, [students[1].uuid, students[0].uuid]);
+        assert_eq!(transport.take().last(), Some(&Sent::Channel(String::from(
+            "✅ <@Kapua> didn't show up and was moved back to position 2 (strike 1 of 3)"
+        ))));
+
+        // Kapua is the only one in the queue this time
+        say(&mut bot, GUILD_A, &students[1], "!q leave");
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        say(&mut bot, GUILD_A, &ta, "!q noshow");
+        assert_eq!(bot.guild(GUILD_A).queue, [students[0].uuid]);
+    }
+
+    #[test]
+    fn no_show_strikes_are_at_least_one() {
+        let (mut bot, transport, _, ta, students) = setup_reminders();
+        // Set directly rather than through `with_no_shows` (which clamps it)
+        bot.guild_mut(GUILD_A).config.noshow_strikes = 0;
+        say(&mut bot, GUILD_A, &students[0], "!q join");
+        say(&mut bot, GUILD_A, &students[1], "!q join");
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        transport.take();
+
+        say(&mut bot, GUILD_A, &ta, "!q noshow");
+        assert_eq!(bot.guild(GUILD_A).queue, [students[1].uuid]);
+        assert!(transport.take().contains(&Sent::Channel(String::from("✅ <@Kapua> didn't show up and was removed from the queue after 1 strike"))));
+    }
+
+    #[test]
+    fn no_show_needs_a_called_student() {
+        let (mut bot, _, _, ta, students) = setup_reminders();
+        say(&mut bot, GUILD_A, &students[0], "!q join");
+        say(&mut bot, GUILD_A, &ta, "!q noshow");
+        bot.on_message(Message {
+            id: 0, guild_id: GUILD_A, author: &ta, message: String::from("!q noshow <@Kapua>"), mentions: Some(vec![students[0].clone()])
+        });
+        assert_eq!(bot.guild(GUILD_A).queue, [students[0].uuid]);
+        assert!(bot.history.of(GUILD_A, students[0].uuid).is_empty());
+    }
+
+    #[test]
+    fn history_records_each_visit() {
+        let (mut bot, transport, clock, ta, students) = setup_reminders();
+        say(&mut bot, GUILD_A, &students[0], "!q join");
+        say(&mut bot, GUILD_A, &students[1], "!q join");
+        clock.advance(chrono::Duration::minutes(5));
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        say(&mut bot, GUILD_A, &students[1], "!q leave");
+
+        let kapua = bot.history.of(GUILD_A, students[0].uuid);
+        assert_eq!(kapua.len(), 1);
+        assert_eq!(kapua[0].outcome, SessionOutcome::Helped);
+        assert_eq!(kapua[0].ta_id, Some(ta.uuid));
+        assert_eq!(kapua[0].waited(), chrono::Duration::minutes(5));
+        assert_eq!(bot.history.of(GUILD_A, students[1].uuid)[0].outcome, SessionOutcome::Left);
+
+        // Students can't look at each other's history but TAs can
+        transport.take();
+        bot.on_message(Message {
+            id: 0, guild_id: GUILD_A, author: &students[1], message: String::from("!q history <@Kapua>"), mentions: Some(vec![students[0].clone()])
+        });
+        bot.on_message(Message {
+            id: 0, guild_id: GUILD_A, author: &ta, message: String::from("!q history <@Kapua>"), mentions: Some(vec![students[0].clone()])
+        });
+        let sent = transport.take();
+        assert_eq!(sent[0], Sent::Channel(String::from("⚠️ <@Bennett> You can only see your own history")));
+        assert!(matches!(&sent[1], Sent::Channel(m) if m.contains("Waited 5m 00s and was helped by Ben#0001")));
+
+        say(&mut bot, GUILD_A, &students[0], "!q history purge");
+        assert!(bot.history.of(GUILD_A, students[0].uuid).is_empty());
+    }
+
+    #[test]
+    fn forget_me_deletes_history_and_settings() {
+        let (mut bot, _, _, ta, students) = setup_reminders();
+        say(&mut bot, GUILD_A, &students[0], "!q join");
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        say(&mut bot, GUILD_A, &students[0], "!q language es");
+        say(&mut bot, GUILD_A, &students[0], "!q notify off");
+        say(&mut bot, GUILD_A, &students[1], "!q join");
+        say(&mut bot, GUILD_A, &students[1], "!q leave");
+
+        say(&mut bot, GUILD_A, &students[0], "!q forget me");
+        assert!(bot.history.of(GUILD_A, students[0].uuid).is_empty());
+        assert_eq!(bot.locale_of(GUILD_A, &students[0]), Locale::English);
+        assert!(!bot.notify_opt_out.contains(&students[0].uuid));
+        // Nobody else is affected
+        assert_eq!(bot.history.of(GUILD_A, students[1].uuid).len(), 1);
+    }
+
+    #[test]
+    fn history_is_pruned_after_the_retention_period() {
+        let (mut bot, _, clock, ta, students) = setup_reminders();
+        bot.guild_mut(GUILD_A).config.retention = Some(chrono::Duration::days(30));
+        say(&mut bot, GUILD_A, &students[0], "!q join");
+        say(&mut bot, GUILD_A, &ta, "!q next");
+
+        clock.advance(chrono::Duration::days(20));
+        say(&mut bot, GUILD_A, &students[1], "!q join");
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        assert_eq!(bot.history.all().len(), 2);
+
+        clock.advance(chrono::Duration::days(11));
+        bot.tick();
+        assert_eq!(bot.history.all().iter().map(|s| s.user_id).collect::<Vec<u64>>(), [students[1].uuid]);
+    }
+
+    #[test]
+    fn tas_can_read_the_audit_log() {
+        let (bot, transport, _, ta, students) = setup_reminders();
+        let path = std::env::temp_dir().join(format!("queuebot-audit-command-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut bot = bot.with_audit_log(AuditLog::new(&path));
+        say(&mut bot, GUILD_A, &students[0], "!q join");
+        // Ordinary chat isn't recorded
+        say(&mut bot, GUILD_A, &students[0], "brb getting coffee");
+        say(&mut bot, GUILD_A, &students[1], "!q leave");
+        bot.on_message(Message {
+            id: 0, guild_id: GUILD_A, author: &ta, message: String::from("!q remove <@Kapua>"), mentions: Some(vec![students[0].clone()])
+        });
+        assert_eq!(AuditLog::new(&path).read_all().unwrap().len(), 3);
+
+        transport.take();
+        for (author, student) in [(&ta, &students[0]), (&ta, &students[2]), (&students[1], &students[0])] {
+            bot.on_message(Message {
+                id: 0, guild_id: GUILD_A, author, message: format!("!q audit <@{}>", student.name), mentions: Some(vec![student.clone()])
+            });
+        }
+        let sent = transport.take();
+        assert!(matches!(&sent[0], Sent::Channel(m)
+            if m.starts_with("<@Ben> Audit log for Kapua#0002 (2 entries):") && m.contains("Kapua#0002 join -> ok") && m.contains("Ben#0001 remove <@Kapua> -> ok")));
+        assert_eq!(sent[1], Sent::Channel(String::from("<@Ben> No audit entries for Russ#0002")));
+        // Students can't read it
+        assert!(matches!(&sent[2], Sent::Channel(m) if m.starts_with("⚠️") && !m.contains("Audit log")));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// A message with its own ID (so it can be edited or deleted)
+    fn message<'a>(id: u64, author: &'a DiscordUser, message: &str) -> Message<'a> {
+        Message { id, guild_id: GUILD_A, author, message: String::from(message), mentions: None }
+    }
+
+    #[test]
+    fn editing_a_join_changes_the_topic() {
+        let (mut bot, transport, _, ta, students) = setup_reminders();
+        bot.on_message(message(10, &students[0], "!q join lab2"));
+        bot.on_message(message(11, &students[1], "!q join"));
+        assert_eq!(bot.guild(GUILD_A).topics[&students[0].uuid], "lab2");
+
+        bot.on_message_edit(message(10, &students[0], "!q join lab3"));
+        assert_eq!(bot.guild(GUILD_A).topics[&students[0].uuid], "lab3");
+        bot.on_message_edit(message(10, &students[0], "!q join"));
+        assert!(bot.guild(GUILD_A).topics.is_empty());
+
+        // Only the author can edit their message and it has to still be a join
+        bot.on_message_edit(message(10, &students[1], "!q join stolen"));
+        bot.on_message_edit(message(11, &students[1], "!q leave"));
+        assert_eq!(bot.guild(GUILD_A).queue, [students[0].uuid, students[1].uuid]);
+        assert!(bot.guild(GUILD_A).topics.is_empty());
+
+        // Editing another command doesn't run it again
+        bot.on_message(message(12, &ta, "!q next"));
+        bot.on_message_edit(message(12, &ta, "!q next"));
+        assert_eq!(bot.guild(GUILD_A).queue, [students[1].uuid]);
+
+        transport.take();
+        bot.on_message(message(13, &ta, "!q list"));
+        bot.on_message_edit(message(11, &students[1], "!q join recursion"));
+        assert_eq!(transport.take(), [
+            Sent::Channel(String::from("Queue: [Bennett#0002]")),
+            Sent::Channel(String::from("✅ <@Bennett> You are now in the queue for: recursion")),
+        ]);
+    }
+
+    #[test]
+    fn deleting_a_command_can_delete_its_replies() {
+        let (mut bot, transport, _, _, students) = setup_reminders();
+        bot.on_message(message(10, &students[0], "!q join"));
+        bot.on_message_delete(GUILD_A, 10);
+        assert!(!transport.take().contains(&Sent::Deleted(1)));
+
+        bot.guild_mut(GUILD_A).config.cleanup_replies = true;
+        bot.on_message(message(11, &students[1], "!q join lab2"));
+        bot.on_message_edit(message(11, &students[1], "!q join lab3"));
+        bot.on_message_delete(GUILD_B, 11);
+        bot.on_message_delete(GUILD_A, 11);
+        // The reply to the join and the reply to the edit are both deleted
+        assert_eq!(transport.take()[2..], [Sent::Deleted(2), Sent::Deleted(3)]);
+        // Deleting the command doesn't undo it
+        assert_eq!(bot.guild(GUILD_A).queue, [students[0].uuid, students[1].uuid]);
+    }
+
+    /// Click a button or add a reaction to a message within `GUILD_A`
+    fn click(bot: &mut QueueBot, message_id: u64, user: &DiscordUser, kind: InteractionKind) {
+        bot.on_interaction(Interaction { guild_id: GUILD_A, message_id, user, kind });
+    }
+
+    #[test]
+    fn pinned_queue_can_be_clicked_and_stays_up_to_date() {
+        let (mut bot, transport, _, ta, students) = setup_reminders();
+        say(&mut bot, GUILD_A, &students[0], "!q join lab2");
+        say(&mut bot, GUILD_A, &ta, "!q pin");
+        let pinned = bot.guild(GUILD_A).status_message.unwrap();
+        let sent = transport.take();
+        assert!(sent.contains(&Sent::Buttons(
+            String::from("**CS 120 queue**: 1 student waiting\n1. Kapua#0002 (lab2)\nClick Join or react with 🙋 to join the queue, or Leave or 👋 to leave it"),
+            vec![JOIN_BUTTON, LEAVE_BUTTON],
+        )));
+        assert!(sent.contains(&Sent::Pinned(pinned)));
+        assert!(sent.contains(&Sent::Reacted(pinned, String::from(JOIN_EMOJI))));
+
+        // Buttons and reactions work the same as typing the command
+        click(&mut bot, pinned, &students[1], InteractionKind::Button(String::from(JOIN_BUTTON)));
+        click(&mut bot, pinned, &students[2], InteractionKind::Reaction(String::from(JOIN_EMOJI)));
+        click(&mut bot, pinned, &students[0], InteractionKind::Reaction(String::from(LEAVE_EMOJI)));
+        assert_eq!(bot.guild(GUILD_A).queue, [students[1].uuid, students[2].uuid]);
+        assert_eq!(bot.history.of(GUILD_A, students[0].uuid)[0].outcome, SessionOutcome::Left);
+
+        // The pinned message is edited after each change
+        let edits = transport.take().into_iter().filter(|s| matches!(s, Sent::Edited(id, _) if *id == pinned)).count();
+        assert_eq!(edits, 3);
+        say(&mut bot, GUILD_A, &ta, "!q list");
+        assert!(!transport.take().iter().any(|s| matches!(s, Sent::Edited(..))));
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        assert!(transport.take().contains(&Sent::Edited(pinned, String::from(
+            "**CS 120 queue**: 1 student waiting\n1. Russ#0002\nClick Join or react with 🙋 to join the queue, or Leave or 👋 to leave it"
+        ))));
+
+        // Clicks on any other message (or unknown reactions) do nothing
+        click(&mut bot, pinned + 100, &students[0], InteractionKind::Button(String::from(JOIN_BUTTON)));
+        click(&mut bot, pinned, &students[0], InteractionKind::Reaction(String::from("🎉")));
+        bot.on_message_delete(GUILD_A, pinned);
+        click(&mut bot, pinned, &students[0], InteractionKind::Button(String::from(JOIN_BUTTON)));
+        assert_eq!(bot.guild(GUILD_A).queue, [students[2].uuid]);
+    }
+
+    /// Every thread event which was sent (ignoring everything else)
+    fn threads(transport: &MockTransport) -> Vec<Sent> {
+        transport.take().into_iter()
+            .filter(|s| matches!(s, Sent::Thread(..) | Sent::InThread(..) | Sent::Archived(_)))
+            .collect()
+    }
+
+    #[test]
+    fn called_students_get_a_private_thread() {
+        let (mut bot, transport, _, ta, students) = setup_reminders();
+        say(&mut bot, GUILD_A, &students[0], "!q join");
+        say(&mut bot, GUILD_A, &students[1], "!q join");
+        say(&mut bot, GUILD_A, &students[2], "!q join");
+        transport.take();
+
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        let thread = bot.guild(GUILD_A).threads[&ta.uuid];
+        assert_eq!(thread.student, students[0].uuid);
+        assert_eq!(threads(&transport), [
+            Sent::Thread(thread.id, String::from("Kapua#0002 and Ben#0001"), vec![students[0].uuid, ta.uuid]),
+            Sent::InThread(thread.id, String::from("<@Kapua> <@Ben> will help you here. Only the two of you and other TAs can see this thread")),
+        ]);
+
+        // Done archives the thread and the student can no longer be marked as a no-show
+        say(&mut bot, GUILD_A, &ta, "!q done");
+        assert_eq!(threads(&transport), [Sent::Archived(thread.id)]);
+        say(&mut bot, GUILD_A, &ta, "!q noshow");
+        assert_eq!(bot.history.of(GUILD_A, students[0].uuid)[0].outcome, SessionOutcome::Helped);
+        say(&mut bot, GUILD_A, &ta, "!q done");
+        assert!(threads(&transport).is_empty());
+
+        // Calling the next student (or marking them as a no-show) archives their thread
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        let first = bot.guild(GUILD_A).threads[&ta.uuid].id;
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        let second = bot.guild(GUILD_A).threads[&ta.uuid].id;
+        assert!(threads(&transport).contains(&Sent::Archived(first)));
+        say(&mut bot, GUILD_A, &ta, "!q noshow");
+        assert_eq!(threads(&transport), [Sent::Archived(second)]);
+        assert!(bot.guild(GUILD_A).threads.is_empty());
+
+        // Servers can turn threads off
+        bot.guild_mut(GUILD_A).config.help_threads = false;
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        assert!(threads(&transport).is_empty());
+    }
+
+    #[test]
+    fn on_duty_tas_take_turns() {
+        let (mut bot, transport, _, ben, students) = setup_reminders();
+        let dana = bot.users.register("Dana", "0005", true).unwrap();
+        for student in students.iter() {
+            say(&mut bot, GUILD_A, student, "!q join");
+        }
+        say(&mut bot, GUILD_A, &ben, "!q duty on");
+        say(&mut bot, GUILD_A, &dana, "!q duty on");
+        say(&mut bot, GUILD_A, &dana, "!q duty on");
+        transport.take();
+        say(&mut bot, GUILD_A, &ben, "!q list");
+        assert_eq!(transport.take()[0], Sent::Channel(String::from(
+            "Queue: [Kapua#0002, Bennett#0002, Russ#0002]\nOn duty: Ben#0001, Dana#0005"
+        )));
+
+        // Whoever runs `!q next`, students are handed out in turn
+        say(&mut bot, GUILD_A, &ben, "!q next");
+        say(&mut bot, GUILD_A, &ben, "!q next");
+        assert_eq!(bot.guild(GUILD_A).called[&ben.uuid], students[0].uuid);
+        assert_eq!(bot.guild(GUILD_A).called[&dana.uuid], students[1].uuid);
+        assert_eq!(bot.history.of(GUILD_A, students[1].uuid)[0].ta_id, Some(dana.uuid));
+        // A TA can also be picked by mentioning them (students can't be picked)
+        bot.on_message(Message {
+            id: 0, guild_id: GUILD_A, author: &ben, message: String::from("!q next <@Kapua>"), mentions: Some(vec![students[0].clone()])
+        });
+        assert_eq!(bot.guild(GUILD_A).queue, [students[2].uuid]);
+
+        // The last TA going off duty warns everyone who is still waiting
+        say(&mut bot, GUILD_A, &ben, "!q duty off");
+        transport.take();
+        say(&mut bot, GUILD_A, &dana, "!q duty off");
+        assert_eq!(transport.take(), [
+            Sent::Channel(String::from("✅ <@Dana> You are now off duty")),
+            Sent::Channel(String::from("⚠️ <@Russ> There are no TAs on duty right now, so the queue won't move until one comes back")),
+        ]);
+
+        // With nobody on duty, the TA who runs `!q next` helps the student
+        say(&mut bot, GUILD_A, &dana, "!q next");
+        assert_eq!(bot.guild(GUILD_A).called[&dana.uuid], students[2].uuid);
+    }
+
+    #[test]
+    fn booked_students_are_put_at_the_front_when_their_slot_starts() {
+        let (mut bot, transport, _, ben, students) = setup_reminders();
+        // Tuesday 1 February 2022, 12:00 UTC
+        let clock = ManualClock::new(chrono::TimeZone::with_ymd_and_hms(&Utc, 2022, 2, 1, 12, 0, 0).unwrap());
+        bot = bot.with_clock(clock.clone());
+        let (kapua, bennett, russ) = (&students[0], &students[1], &students[2]);
+
+        // Students can't add slots and TAs can't add slots which overlap their own
+        say(&mut bot, GUILD_A, kapua, "!q slots add Tue 14:00-16:00 15m");
+        say(&mut bot, GUILD_A, &ben, "!q slots add Tue 2pm");
+        // Lengths too long to be stored are rejected instead of crashing the bot
+        say(&mut bot, GUILD_A, &ben, "!q slots add Tue 14:00-16:00 9999999999999999m");
+        say(&mut bot, GUILD_A, &ben, "!q slots add Tue 14:00-14:30 15m");
+        say(&mut bot, GUILD_A, &ben, "!q slots add tue 14:15-15:00 15m");
+        assert_eq!(transport.take(), [
+            Sent::Channel(String::from("⚠️ <@Kapua> invalid format.")),
+            Sent::Channel(String::from("⚠️ <@Ben> Slots are added like `!q slots add Tue 14:00-16:00 15m` (a day, a time range and how long each slot is)")),
+            Sent::Channel(String::from("⚠️ <@Ben> Slots are added like `!q slots add Tue 14:00-16:00 15m` (a day, a time range and how long each slot is)")),
+            Sent::Channel(String::from("✅ <@Ben> Added 2 15m slots from Tue 1 Feb 14:00 to 14:30 (`#1` to `#2`)")),
+            Sent::Channel(String::from("⚠️ <@Ben> Those slots overlap your slot `#2` at Tue 1 Feb 14:15")),
+        ]);
+
+        say(&mut bot, GUILD_A, kapua, "!q book 2");
+        say(&mut bot, GUILD_A, kapua, "!q book");
+        say(&mut bot, GUILD_A, bennett, "!q book 2");
+        say(&mut bot, GUILD_A, bennett, "!q book");
+        say(&mut bot, GUILD_A, russ, "!q book");
+        say(&mut bot, GUILD_A, russ, "!q slots");
+        say(&mut bot, GUILD_A, &ben, "!q slots");
+        assert_eq!(transport.take(), [
+            Sent::Channel(String::from("✅ <@Kapua> You have booked slot `#2` at Tue 1 Feb 14:15 with Ben#0001. You will be put at the front of the queue when it starts. You can cancel with `!q cancel` until Tue 1 Feb 13:15")),
+            Sent::Channel(String::from("⚠️ <@Kapua> You already have slot `#2` at Tue 1 Feb 14:15 booked. Use `!q cancel` first to book a different one")),
+            Sent::Channel(String::from("⚠️ <@Bennett> Slot `#2` has already been booked")),
+            Sent::Channel(String::from("✅ <@Bennett> You have booked slot `#1` at Tue 1 Feb 14:00 with Ben#0001. You will be put at the front of the queue when it starts. You can cancel with `!q cancel` until Tue 1 Feb 13:00")),
+            Sent::Channel(String::from("⚠️ <@Russ> There are no free appointment slots")),
+            Sent::Channel(String::from("Appointment slots:\n`#1` Tue 1 Feb 14:00 with Ben#0001 - booked\n`#2` Tue 1 Feb 14:15 with Ben#0001 - booked")),
+            Sent::Channel(String::from("Appointment slots:\n`#1` Tue 1 Feb 14:00 with Ben#0001 - booked by Bennett#0002\n`#2` Tue 1 Feb 14:15 with Ben#0001 - booked by Kapua#0002")),
+        ]);
+
+        // Cancelling is only allowed until an hour before
+        clock.advance(chrono::Duration::minutes(70));
+        say(&mut bot, GUILD_A, bennett, "!q cancel");
+        say(&mut bot, GUILD_A, kapua, "!q cancel");
+        say(&mut bot, GUILD_A, kapua, "!q cancel");
+        assert_eq!(transport.take(), [
+            Sent::Channel(String::from("⚠️ <@Bennett> Appointments can't be cancelled within 1h 00m of starting. Please let Ben#0001 know if you can't make it")),
+            Sent::Channel(String::from("✅ <@Kapua> Your appointment at Tue 1 Feb 14:15 has been cancelled")),
+            Sent::Channel(String::from("⚠️ <@Kapua> You don't have an appointment booked")),
+        ]);
+        say(&mut bot, GUILD_A, russ, "!q book 2");
+        say(&mut bot, GUILD_A, russ, "!q join");
+        say(&mut bot, GUILD_A, kapua, "!q join");
+        transport.take();
+
+        // Bennett goes in front of everyone who walked in, then Russ (who had
+        // already joined) is moved to the front once their own appointment starts
+        clock.advance(chrono::Duration::minutes(50));
+        bot.tick();
+        assert_eq!(bot.guild(GUILD_A).queue, [bennett.uuid, russ.uuid, kapua.uuid]);
+        clock.advance(chrono::Duration::minutes(15));
+        bot.tick();
+        assert_eq!(bot.guild(GUILD_A).queue, [russ.uuid, bennett.uuid, kapua.uuid]);
+        assert!(transport.take().contains(&Sent::Channel(String::from(
+            "<@Russ> Your appointment with <@Ben> has started, so you have been moved to the front of the queue"
+        ))));
+        assert!(bot.guild(GUILD_A).bookings.slots().is_empty());
+
+        // Removing a booked slot tells the student
+        say(&mut bot, GUILD_A, &ben, "!q slots add Wed 10:00-10:15 15m");
+        say(&mut bot, GUILD_A, kapua, "!q book");
+        transport.take();
+        say(&mut bot, GUILD_A, &ben, "!q slots remove #3");
+        say(&mut bot, GUILD_A, &ben, "!q slots remove 3");
+        assert_eq!(transport.take(), [
+            Sent::Direct(kapua.uuid, String::from("<@Kapua> Your appointment at Wed 2 Feb 10:00 with Ben#0001 in CS 120 was cancelled by a TA. Use `!q slots` to find another one")),
+            Sent::Channel(String::from("✅ <@Ben> Slot `#3` at Wed 2 Feb 10:00 has been removed")),
+            Sent::Channel(String::from("⚠️ <@Ben> There is no upcoming slot `#3`")),
+        ]);
+    }
+
+    #[test]
+    fn students_can_rate_the_help_they_got_anonymously() {
+        let (mut bot, transport, _, ben, students) = setup_reminders();
+        let (kapua, bennett) = (&students[0], &students[1]);
+        let dana = bot.users.register("Dana", "0005", false).unwrap();
+        let config = &mut bot.guild_mut(GUILD_A).config;
+        config.tas.insert(dana.uuid);
+        config.head_tas.insert(ben.uuid);
+        config.surveys = true;
+        say(&mut bot, GUILD_A, kapua, "!q join");
+        say(&mut bot, GUILD_A, bennett, "!q join");
+
+        // Once Dana is done Kapua is privately asked to rate her
+        say(&mut bot, GUILD_A, &dana, "!q next");
+        transport.take();
+        say(&mut bot, GUILD_A, &dana, "!q done");
+        let sent = transport.take();
+        assert!(sent.contains(&Sent::Direct(kapua.uuid, String::from(
+            "<@Kapua> How was the help you got from Dana#0005 in CS 120? React with 1️⃣ to 5️⃣ (5 is best), or reply with `!q feedback 1-5 [comment]` to leave a comment too. Your answer is anonymous"
+        ))));
+        let survey = sent.iter().find_map(|s| match s {
+            Sent::Reacted(id, emoji) if emoji == "1️⃣" => Some(*id),
+            _ => None,
+        }).unwrap();
+        assert_eq!(sent.iter().filter(|s| matches!(s, Sent::Reacted(id, _) if *id == survey)).count(), 5);
+
+        // Only Kapua can answer it, and only once
+        click(&mut bot, survey, bennett, InteractionKind::Reaction(String::from("1️⃣")));
+        click(&mut bot, survey, kapua, InteractionKind::Reaction(String::from(JOIN_EMOJI)));
+        click(&mut bot, survey, kapua, InteractionKind::Reaction(String::from("4️⃣")));
+        click(&mut bot, survey, kapua, InteractionKind::Reaction(String::from("5️⃣")));
+        bot.on_message(message(50, kapua, "!q feedback 3"));
+        assert_eq!(transport.take(), [
+            Sent::Direct(kapua.uuid, String::from("✅ <@Kapua> Thanks for your feedback!")),
+            Sent::Deleted(50),
+            Sent::Direct(kapua.uuid, String::from("⚠️ <@Kapua> You don't have any help to rate right now")),
+        ]);
+
+        // Bennett answers by typing instead so they can leave a comment
+        say(&mut bot, GUILD_A, &ben, "!q next");
+        say(&mut bot, GUILD_A, &ben, "!q done");
+        transport.take();
+        bot.on_message(message(51, bennett, "!q feedback 6 Felt a bit rushed"));
+        bot.on_message(message(52, bennett, "!q feedback 2 Felt a bit rushed"));
+        // What they typed is deleted and nothing about it is posted in the channel
+        assert_eq!(transport.take(), [
+            Sent::Deleted(51),
+            Sent::Direct(bennett.uuid, String::from("⚠️ <@Bennett> invalid format.")),
+            Sent::Deleted(52),
+            Sent::Direct(bennett.uuid, String::from("✅ <@Bennett> Thanks for your feedback!")),
+        ]);
+
+        // Only head TAs can see the summary, which is sent privately
+        bot.on_message(message(53, &dana, "!q feedback summary"));
+        say(&mut bot, GUILD_A, &ben, "!q feedback summary");
+        assert_eq!(transport.take(), [
+            Sent::Deleted(53),
+            Sent::Direct(dana.uuid, String::from("⚠️ <@Dana> invalid format.")),
+            Sent::Direct(ben.uuid, String::from(
+                "<@Ben> Feedback for CS 120:\n\
+                **Ben#0001**: 2.0 from 1 rating (1★ 0, 2★ 1, 3★ 0, 4★ 0, 5★ 0)\n\
+                > Felt a bit rushed\n\
+                **Dana#0005**: 4.0 from 1 rating (1★ 0, 2★ 0, 3★ 0, 4★ 1, 5★ 0)"
+            )),
+        ]);
+
+        // Nothing stored says who gave which answer
+        let stored = serde_json::to_string(bot.feedback.all()).unwrap();
+        assert!(!stored.contains(&kapua.uuid.to_string()) && !stored.contains(&bennett.uuid.to_string()));
+    }
+
+    #[test]
+    fn surveys_are_sent_without_help_threads() {
+        let (mut bot, transport, _, ta, students) = setup_reminders();
+        let config = &mut bot.guild_mut(GUILD_A).config;
+        config.help_threads = false;
+        config.surveys = true;
+        say(&mut bot, GUILD_A, &students[0], "!q join");
+        say(&mut bot, GUILD_A, &ta, "!q next");
+        transport.take();
+
+        say(&mut bot, GUILD_A, &ta, "!q done");
+        let sent = transport.take();
+        assert_eq!(sent[..2], [
+            Sent::Channel(String::from("✅ <@Ben> You are done helping <@Kapua>")),
+            Sent::Direct(students[0].uuid, String::from(
+                "<@Kapua> How was the help you got from Ben#0001 in CS 120? React with 1️⃣ to 5️⃣ (5 is best), or reply with `!q feedback 1-5 [comment]` to leave a comment too. Your answer is anonymous"
+            )),
+        ]);
+        assert!(!sent.iter().any(|s| matches!(s, Sent::Archived(_))));
+
+        // The answer didn't come from Discord so there's no message to delete
+        say(&mut bot, GUILD_A, &students[0], "!q feedback 4");
+        assert_eq!(transport.take(), [Sent::Direct(students[0].uuid, String::from("✅ <@Kapua> Thanks for your feedback!"))]);
+
+        // Kapua was already marked as helped
+        say(&mut bot, GUILD_A, &ta, "!q done");
+        assert_eq!(transport.take(), [Sent::Channel(String::from("⚠️ <@Ben> You haven't called anyone with `!q next`"))]);
+    }
+
+    #[test]
+    fn scripts_can_reply_to_hooks_and_add_commands() {
+        let (bot, transport, _, ta, students) = setup_reminders();
+        let scripts = Scripts::load(DEFAULT_SCRIPTS_DIR).unwrap()
+            .with_script("leave.rhai", "fn on_leave(event) { dm(`You left while waiting for help with ${event.topic}`); }").unwrap();
+        let mut bot = bot.with_scripts(scripts).unwrap();
+
+        say(&mut bot, GUILD_A, &students[0], "!q join Lab4 part a");
+        assert_eq!(transport.take(), [
+            Sent::Channel(String::from("✅ <@Kapua> You have been added to the queue at position 1")),
+            Sent::Channel(String::from("Kapua, while you wait here's the lab4 handout: https://example.edu/cs/labs/lab4")),
+        ]);
+        say(&mut bot, GUILD_A, &students[0], "!q leave");
+        assert_eq!(dms(&transport), [(students[0].uuid, String::from("You left while waiting for help with Lab4 part a"))]);
+
+        // Commands from scripts work like any other (and show up in `!q help`)
+        say(&mut bot, GUILD_A, &students[1], "!q LABS lab2");
+        assert_eq!(transport.take(), [Sent::Channel(String::from("https://example.edu/cs/labs/lab2"))]);
+        say(&mut bot, GUILD_A, &ta, "!q help");
+        assert!(matches!(&transport.take()[0], Sent::Channel(help) if help.contains("`!q labs`")));
+    }
+
+    #[test]
+    fn scripts_cannot_replace_existing_commands() {
+        // Otherwise students could run TA commands through the script
+        for (function, command) in [("cmd_next", "next"), ("cmd_CLEAR", "clear"), ("cmd_pos", "pos")] {
+            let scripts = Scripts::new().with_script("sneaky.rhai", &format!("fn {}(event) {{ reply(`hi`); }}", function)).unwrap();
+            let error = setup_reminders().0.with_scripts(scripts).err().unwrap();
+            assert_eq!(error, format!("sneaky.rhai: cmd_{} can't replace the existing `!q {}` command", command, command));
+        }
+
+        // Commands registered with `with_command` are protected too
+        let scripts = Scripts::new().with_script("hours.rhai", "fn cmd_hours(event) { reply(`Never`); }").unwrap();
+        let bot = setup_reminders().0.with_command(crate::hours::OfficeHours::new("Mon 2-4pm"));
+        assert!(bot.with_scripts(scripts).is_err());
+    }
+
+    #[test]
+    fn unknown_guilds_get_their_own_state() {
+        let (mut bot, student, _) = setup();
+        say(&mut bot, 3, &student, "!q join");
+        assert_eq!(bot.guild(3).queue, [student.uuid]);
+        assert!(bot.guild(GUILD_A).queue.is_empty());
+        assert!(bot.guild(GUILD_B).queue.is_empty());
+    }
+}
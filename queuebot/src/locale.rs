@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+/// A language the bot can reply in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Locale {
+    English,
+    Spanish,
+}
+
+impl Locale {
+    /// Every supported locale
+    pub const ALL: [Locale; 2] = [Locale::English, Locale::Spanish];
+
+    /// The short code users type to pick a locale (e.g. `!q language es`)
+    pub fn code(&self) -> &'static str {
+        match self {
+            Locale::English => "en",
+            Locale::Spanish => "es",
+        }
+    }
+
+    /// Look up a locale from its code (case-insensitive)
+    pub fn from_code(code: &str) -> Option<Locale> {
+        Locale::ALL.iter().copied().find(|l| l.code().eq_ignore_ascii_case(code))
+    }
+
+    /// The message catalog shipped with the bot for this locale
+    fn source(&self) -> &'static str {
+        match self {
+            Locale::English => include_str!("../locales/en.txt"),
+            Locale::Spanish => include_str!("../locales/es.txt"),
+        }
+    }
+}
+
+/// Arguments which get interpolated into a message.
+/// Each pair is the name of the `{placeholder}` and what to replace it with
+pub type Args<'a> = [(&'a str, String)];
+
+/// Holds every message the bot can send, in every language.
+///
+/// Messages are looked up by a key (such as `joined`). If a message
+/// is missing from a locale, the English version is used instead and
+/// if it's missing from English too, the key itself is returned
+pub struct Catalog {
+    messages: HashMap<Locale, HashMap<String, String>>,
+}
+
+impl Catalog {
+    /// Load the catalogs that ship with the bot (`locales/*.txt`)
+    pub fn builtin() -> Catalog {
+        let messages = Locale::ALL.iter()
+            .map(|&l| (l, parse(l.source()).unwrap_or_else(|e| panic!("locales/{}.txt: {}", l.code(), e))))
+            .collect();
+        Catalog { messages }
+    }
+
+    /// Get the message for `key` within the given locale and fill in its arguments.
+    /// If one of the arguments is named `count`, it is used to pick the plural form
+    pub fn get(&self, locale: Locale, key: &str, args: &Args) -> String {
+        let count = args.iter()
+            .find(|(name, _)| *name == "count")
+            .and_then(|(_, v)| v.parse::<i64>().ok());
+
+        let template = [locale, Locale::English].iter()
+            .filter_map(|l| self.lookup(*l, key, count))
+            .next()
+            .unwrap_or(key);
+
+        interpolate(template, args)
+    }
+
+    /// Check if `key` is a known message (every key exists in English)
+    pub fn contains(&self, key: &str) -> bool {
+        self.messages[&Locale::English].contains_key(key)
+    }
+
+    /// Find the template for `key`, preferring the plural form matching `count`
+    fn lookup(&self, locale: Locale, key: &str, count: Option<i64>) -> Option<&str> {
+        let messages = self.messages.get(&locale)?;
+        count
+            .and_then(|n| messages.get(&format!("{}[{}]", key, plural_category(locale, n))))
+            .or_else(|| messages.get(key))
+            .map(|s| s.as_str())
+    }
+}
+
+/// Replace every `{placeholder}` within `template` with its argument.
+/// This is done in a single pass so an argument containing braces (such as
+/// a topic of `{ta}`) is left as it is instead of being filled in too.
+/// Placeholders without an argument are kept
+fn interpolate(template: &str, args: &Args) -> String {
+    let mut message = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        message.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after.find('}').and_then(|end| {
+            let name = &after[..end];
+            args.iter().find(|(n, _)| *n == name).map(|(_, v)| (v, end))
+        });
+        match value {
+            Some((value, end)) => {
+                message.push_str(value);
+                rest = &after[end + 1..];
+            },
+            None => {
+                message.push('{');
+                rest = after;
+            },
+        }
+    }
+    message.push_str(rest);
+    message
+}
+
+/// The plural category (as named by the Unicode CLDR) of a number.
+/// English and Spanish only distinguish between one and everything else
+fn plural_category(locale: Locale, n: i64) -> &'static str {
+    match locale {
+        Locale::English | Locale::Spanish => if n == 1 { "one" } else { "other" },
+    }
+}
+
+/// Parse a catalog file. Each line is either blank, a `#` comment,
+/// or `key = message` where `\n` within the message becomes a new line
+fn parse(source: &str) -> Result<HashMap<String, String>, String> {
+    let mut messages = HashMap::new();
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or(format!("line {}: expected `key = message`", i + 1))?;
+        if messages.insert(key.trim().to_string(), value.trim().replace("\\n", "\n")).is_some() {
+            return Err(format!("line {}: duplicate key `{}`", i + 1, key.trim()));
+        }
+    }
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_key_exists_in_every_locale() {
+        let catalog = Catalog::builtin();
+        let english = &catalog.messages[&Locale::English];
+        for locale in Locale::ALL.iter() {
+            let messages = &catalog.messages[locale];
+            for key in english.keys() {
+                assert!(messages.contains_key(key), "`{}` is missing from {}", key, locale.code());
+            }
+            for key in messages.keys() {
+                assert!(english.contains_key(key), "`{}` in {} is missing from en", key, locale.code());
+            }
+        }
+    }
+
+    #[test]
+    fn interpolates_and_pluralises() {
+        let catalog = Catalog::builtin();
+        let args = |count: &str| vec![
+            ("mention", String::from("<@Ben>")),
+            ("user", String::from("Russ#0004")),
+            ("count", String::from(count)),
+            ("entries", String::from("...")),
+        ];
+        assert_eq!(catalog.get(Locale::English, "audit.entries", &args("1")), "<@Ben> Audit log for Russ#0004 (1 entry):\n...");
+        assert_eq!(catalog.get(Locale::English, "audit.entries", &args("3")), "<@Ben> Audit log for Russ#0004 (3 entries):\n...");
+        assert_eq!(catalog.get(Locale::Spanish, "audit.entries", &args("3")), "<@Ben> Registro de auditoría de Russ#0004 (3 entradas):\n...");
+    }
+
+    #[test]
+    fn arguments_are_only_filled_in_once() {
+        let catalog = Catalog::builtin();
+        let args = [
+            ("mention", String::from("<@Kapua>")),
+            ("topic", String::from("why does {mention} print {ta}? {")),
+            ("ta", String::from("Ben")),
+        ];
+        assert_eq!(catalog.get(Locale::English, "topic_updated", &args), "<@Kapua> You are now in the queue for: why does {mention} print {ta}? {");
+        // Braces which aren't a known placeholder are left alone
+        assert_eq!(interpolate("{ {unknown} {ta}} {ta", &args), "{ {unknown} Ben} {ta");
+    }
+
+    #[test]
+    fn missing_keys_fall_back() {
+        let catalog = Catalog::builtin();
+        assert_eq!(catalog.get(Locale::Spanish, "no.such.key", &[]), "no.such.key");
+    }
+}
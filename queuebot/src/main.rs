@@ -1,450 +1,269 @@
-use std::collections::{VecDeque, hash_map::DefaultHasher};
-use std::hash::{Hash, Hasher};
-use std::fmt::Debug;
-
-
-/// This project is a simpler rewrite of QueueBot
-/// which is a Discord bot used by CS 120 to keep track
-/// of the queue for office hours.
-/// An old version of the Python source code can be seen here: <https://github.com/benperumala/cs120-queuebot/>
-///
-/// This version is a recreation of that which does not connect to Discord.
-/// Instead, it is a mockup that tries to roughly simulate
-/// messages in the same way as Discord.
-///
-/// It is also simpler than the production bot as it
-///  - Has a single queue instead of online and in-person queue
-///  - Doesn't check if users are waiting in a voice channel
-///  - Doesn't check if messages are from the correct channels
-///  - Doesn't keep track of a config which allows for customization
-///  - Doesn't display fancy Discord-specific embeds
-///  - Doesn't notify TAs on the join in the queue
-
-/// Used to prepend a warning, error, or success
-/// emote to the beginning of a sent message.
-enum MessageType {
-    Warning,
-    Success,
-    Error
-}
-
-/// Implementation of a "trait"
-/// Anyone who wants to implement this trait must create
-/// the below methods.
-trait User {
-    /// Return a string which represents mentioning the given user
-    /// Discord typically defines this as `<@XXXX>`
-    /// where XXXX is the user's UUID
-    /// However, since this is a mockup, `<@Username>` should be done instead
-    /// to make it easier to read
-    fn get_mention(&self) -> String;
-
-    /// Returns a string which represents the usual
-    /// way users share their account ID so other
-    /// people can add them. Discord defines this as
-    /// USERNAME#DDDD where USERNAME is their username
-    /// and DDDD is four numbers
-    fn get_tag(&self) -> String;
-
-    /// Get the name of the user
-    fn get_name(&self) -> String;
-
-    /// Check if a user is a TA
-    /// (Usually this involves checking all their roles
-    /// but to simplify things, it's a field within the struct)
-    fn is_ta(&self) -> bool;
-}
-
-/// An object which is used to represent a user
-/// on Discord. If using a library to communicate with
-/// Discord, that library would have it's own version of representing
-/// users. This is a mock version of that.
-/// Note for a production version, it wouldn't have an `is_ta` field.
-/// That would instead be a method where you would have to check all of
-/// the user's roles and see if any of them match a "TA Role" which would
-/// be specified in some sort of config (not modeled in this program)
-///
-/// For more information about users see <https://discord.com/developers/docs/resources/user>
-#[derive(Clone)]  // Allows Rust to create deep copies of the object
-struct DiscordUser {
-    uuid: u64,
-    name: String,
-    discriminator: String,
-    is_ta: bool,
-}
-
-// Allow for printing the struct to the console
-impl Debug for DiscordUser {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}#{}", self.name, self.discriminator)
+//! Runs the scripted office hours session from `queuebot::demo`, which shows
+//! off every command of the `queuebot` library (see [src/lib.rs](lib.rs)), along
+//! with command line tools for searching the audit log and exporting records.
+//!
+//!  - `queuebot` runs the session, printing every message the bot sends
+//!  - `queuebot serve [ADDRESS]` runs the session then serves the queue over HTTP
+//!  - `queuebot tui` runs the session then shows a live view of the queue in the terminal
+//!  - `queuebot irc ADDRESS CHANNEL [NICK]` runs the session then keeps running within an IRC channel
+//!  - `queuebot audit ...`, `queuebot export ...` and `queuebot scenario ...`
+//!    (see `audit_cli`, `export_cli` and `scenario_cli`)
+
+use std::path::Path;
+
+use queuebot::actor::BotHandle;
+use queuebot::audit::{AuditFilter, AuditLog, DEFAULT_AUDIT_PATH};
+use queuebot::demo::{self, CS120_GUILD};
+use queuebot::feedback::{Feedback, DEFAULT_FEEDBACK_PATH};
+use queuebot::history::{History, DEFAULT_HISTORY_PATH};
+use queuebot::irc::IrcClient;
+use queuebot::privacy::{export, Pseudonymiser};
+use queuebot::scenario::{scenario_files, Scenario};
+use queuebot::scripting::{Scripts, DEFAULT_SCRIPTS_DIR};
+use queuebot::tui::{self, LogTransport};
+use queuebot::web::StatusServer;
+use queuebot::webhooks::{Webhook, WebhookEvent, Webhooks};
+use queuebot::QueueBot;
+
+/// Search the audit log from the command line. Usage:
+/// `queuebot audit [--file PATH] [--guild ID] [--user NAME] [--command CMD] [--since TIME] [--until TIME]`
+/// where `TIME` is either `YYYY-MM-DD` or an RFC 3339 timestamp
+fn audit_cli(args: &[String]) -> Result<(), String> {
+    let filter = AuditFilter::from_args(args)?;
+    let path = args.iter()
+        .position(|a| a == "--file")
+        .and_then(|i| args.get(i + 1))
+        .map_or(DEFAULT_AUDIT_PATH, |p| p.as_str());
+
+    let entries = AuditLog::new(path).search(&filter).map_err(|e| format!("{}: {}", path, e))?;
+    for entry in entries.iter() {
+        println!("{}", entry);
     }
+    Ok(())
 }
 
-/// A representation of a Discord message. When using a library which
-/// connects to Discord, that object would be used instead. This is a
-/// mockup object which is used to show the bot is working without having
-/// to set up and connect it to Discord.
-///
-/// The fields are based off discord.py's representation which keeps track
-/// of the author, their mention, and any users they have mentioned.
-/// The message will contain mentions (`<@XXX>`) but this can be annoying to
-/// parse. As a result, Discord also returns a list of all users who have
-/// been mentioned within the message (order is not guaranteed)
+/// Export history, the audit log and feedback as JSON-lines. Usage:
+/// `queuebot export [--history PATH] [--audit PATH] [--feedback PATH] [--anonymise [--secret SECRET]]`
 ///
-/// The <'a> is used to represent that author is a *reference* to a DiscordUser object
-struct Message<'a> {
-    author: &'a DiscordUser,
-    message: String,
-    mentions: Option<Vec<DiscordUser>>
-}
-
-// Almost like the __eq__ method within Python.
-// This allows Rust to compare DiscordUsers and
-// see if two structs represent the same object
-impl PartialEq for DiscordUser {
-    fn eq(&self, other: &Self) -> bool {
-        self.uuid == other.uuid
-    }
-}
-
-// Implementation of User contract methods specifically for `DiscordUser`
-impl User for DiscordUser {
-    fn get_mention(&self) -> String {
-        format!("<@{}>", self.name)
-    }
-
-    fn get_tag(&self) -> String {
-        format!("{}#{}", self.name, self.discriminator)
-    }
-
-    fn get_name(&self) -> String {
-        self.name.to_string()
-    }
-
-    fn is_ta(&self) -> bool {
-        self.is_ta
+/// With `--anonymise`, every user is replaced by a pseudonym. The same secret
+/// (`--secret` or the `QUEUEBOT_EXPORT_SECRET` environment variable) always
+/// gives the same pseudonyms so separate exports can be compared
+fn export_cli(args: &[String]) -> Result<(), String> {
+    let value_of = |flag: &str| args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1)).map(|v| v.as_str());
+    let history_path = value_of("--history").unwrap_or(DEFAULT_HISTORY_PATH);
+    let audit_path = value_of("--audit").unwrap_or(DEFAULT_AUDIT_PATH);
+    let feedback_path = value_of("--feedback").unwrap_or(DEFAULT_FEEDBACK_PATH);
+
+    let pseudonymiser = match args.iter().any(|a| a == "--anonymise") {
+        false => None,
+        true => {
+            let secret = value_of("--secret").map(String::from)
+                .or_else(|| std::env::var("QUEUEBOT_EXPORT_SECRET").ok())
+                .ok_or("--anonymise needs a secret (pass --secret or set QUEUEBOT_EXPORT_SECRET)")?;
+            Some(Pseudonymiser::new(&secret))
+        },
+    };
+
+    let history = History::open(history_path).map_err(|e| format!("{}: {}", history_path, e))?;
+    let audit = AuditLog::new(audit_path).read_all().map_err(|e| format!("{}: {}", audit_path, e))?;
+    let feedback = Feedback::open(feedback_path).map_err(|e| format!("{}: {}", feedback_path, e))?;
+    for record in export(history.all(), &audit, feedback.all(), pseudonymiser.as_ref()) {
+        println!("{}", record);
     }
+    Ok(())
 }
 
-/// Convert a string to a unsigned number
-/// It's primarily used to generate uuids for users
-/// Example taken from <https://doc.rust-lang.org/std/hash/index.html#examples>
-fn calculate_hash<T: Hash>(t: &T) -> u64 {
-    let mut s = DefaultHasher::new();
-    t.hash(&mut s);
-    s.finish()
-}
-
-/// Use the Factory Design Pattern to create DiscordUsers.
-/// The UUID of the user is a random u64 based derived from the name
-/// Arguments
-/// * `name` - The name of the user
-/// * `discriminator` - A string containing four digits
-/// * `is_ta` - Whether or not the user is a TA
+/// Replay scenario files and report any replies which changed. Usage:
+/// `queuebot scenario [--bless] PATH...` where each `PATH` is a scenario file
+/// or a directory of them (see `queuebot::scenario` for the format)
 ///
-fn get_user(name: &str, discriminator: &str, is_ta: bool) -> DiscordUser {
-    DiscordUser {
-        uuid: calculate_hash(&name),
-        name: String::from(name),
-        discriminator: String::from(discriminator),
-        is_ta,
-    }
-}
-
-/// The struct representing the QueueBot object
-/// Currently it only holds a queue. In an actual
-/// production implementation, it would also store
-/// references to Discord Text, Voice channels, etc.
-/// which are used to ensure commands are being sent
-/// from the right locations and students are waiting
-/// in the proper voice channel for a TA
-struct QueueBot {
-    /// Users are added to the back of the queue
-    /// and are removed from the front
-    /// (VecDeque is a double-ended queue that is implemented using a Vector)
-    queue: VecDeque<DiscordUser>
-}
-
-/// This is an example of implementing a "class" without
-/// a trait. To create a new object, use `QueueBot::new()`
-impl QueueBot {
-    /// Create a new QueueBot object with an empty queue
-    pub fn new() -> QueueBot {
-        QueueBot { queue: VecDeque::new() }
-    }
-
-    /// Event handler for Discord message events. This checks to see if the
-    /// message starts with `!q`
-    pub fn on_message(&mut self, msg: Message) {
-        let lower_message = msg.message.to_ascii_lowercase();
-        println!("[{}]: {}", msg.author.name, msg.message);
-        if lower_message.starts_with("!q") {
-            self.queue_command(msg);
-        }
-    }
-
-    /// "Send" a message to Discord.
-    /// In reality, this just prints the message to stdout
-    /// If `cmd_prefix` is specified, a colorful emote
-    /// will be added to the front of the message to help give a
-    /// visual aid of the message status (succeeded, failed, etc.)
-    fn send(&self, message: String, cmd_prefix: Option<MessageType>) {
-        let prefix = match cmd_prefix {
-            None => "",
-            Some(t) => match t {
-                MessageType::Warning => "⚠️ ",
-                MessageType::Success => "✅ ",
-                MessageType::Error => "‼️ ",
-            },
-        };
-        println!("SEND> {}{}\n", prefix, message)
-    }
-
-    /// Given a message that starts with `!q`, send it to be handled by the appropriate command handler
-    fn queue_command(&mut self, msg: Message) {
-        let lower_message = msg.message.to_ascii_lowercase();
-        let split_message = lower_message.split_ascii_whitespace().collect::<Vec<&str>>();
-
-        if split_message.len() < 2 {
-            self.send(
-            format!("{} invalid syntax.", msg.author.get_mention()),
-            Some(MessageType::Warning))
-        }
-
-        // Figure out which command is being run
-        match *split_message.get(1).unwrap() {
-            // Check all student commands
-            "ping" => self.queue_ping(),
-            "join" => self.q_join(msg.author),
-            "leave" => self.q_leave(msg.author),
-            "pos" => self.q_position(msg.author),
-            "position" => self.q_position(msg.author),
-            "list" => self.q_list(),
-
-            // None of the above matched
-            // Check if they are a TA. If they are, compare against TA commands
-            _ => match msg.author.is_ta() {
-                false => self.send_invalid_syntax(msg.author),
-                true => match *split_message.get(1).unwrap() {
-                    "next" => self.q_next(msg.author),
-                    "clear" => self.q_clear(msg.author),
-                    "add" => self.q_add_other(msg.author, msg.mentions),
-                    "remove" => self.q_remove_other(msg.author, msg.mentions),
-                    _ => self.send_invalid_syntax(msg.author)
+/// With `--bless`, files are rewritten with what the bot sends now instead
+fn scenario_cli(args: &[String]) -> Result<(), String> {
+    let bless = args.iter().any(|a| a == "--bless");
+    let mut failed = 0;
+    for path in args.iter().filter(|a| *a != "--bless") {
+        for file in scenario_files(path)? {
+            let text = std::fs::read_to_string(&file).map_err(|e| format!("{}: {}", file.display(), e))?;
+            let scenario = Scenario::parse(&text).map_err(|e| format!("{}: {}", file.display(), e))?;
+            match bless {
+                true => {
+                    let blessed = scenario.bless().map_err(|e| format!("{}: {}", file.display(), e))?;
+                    if blessed != text {
+                        std::fs::write(&file, blessed).map_err(|e| format!("{}: {}", file.display(), e))?;
+                        println!("blessed {}", file.display());
+                    }
+                },
+                false => match scenario.check() {
+                    Ok(()) => println!("ok {}", file.display()),
+                    Err(e) => {
+                        println!("FAILED {}\n{}", file.display(), e);
+                        failed += 1;
+                    },
                 },
             }
         }
-
     }
-
-    /// Notify the user the command they typed was invalid
-    fn send_invalid_syntax(&self, user: &DiscordUser) {
-        self.send(
-            format!("{} invalid format.", user.get_mention()),
-            Some(MessageType::Warning));
-    }
-
-    /// Respond with a friendly "Pong!"
-    fn queue_ping(&self) {
-        self.send(String::from("Pong!"), None);
-    }
-
-    /// Add the given user to the queue (at the end)
-    /// and send a response message
-    fn q_join(&mut self, user: &DiscordUser) {
-        if self.queue.contains(&user) {
-            self.send(String::from("You are already in the queue!"), Some(MessageType::Warning));
-            return;
-        }
-        self.queue.push_back(user.clone());
-        self.send(format!("{} You have been added to the queue at position {}", user.get_mention(), self.queue.len()), Some(MessageType::Success));
+    match failed {
+        0 => Ok(()),
+        n => Err(format!("{} scenario(s) failed (run with --bless to accept the new replies)", n)),
     }
+}
 
-    /// Remove the given user from the queue
-    /// and send a response message
-    fn q_leave(&mut self, user: &DiscordUser) {
-        // Get position of user within Queue via brute-force search
-        match self.queue.iter().position(|r| r.uuid == user.uuid) {
-            Some(i) => {
-                self.queue.remove(i);
-                self.send(format!("{} You have been removed from the queue", user.get_mention()), Some(MessageType::Success));
-            },
-            None => self.send(format!("{} You are not in the queue!", user.get_mention()), Some(MessageType::Warning)),
+fn main() {
+    let args = std::env::args().collect::<Vec<String>>();
+    let result = match args.get(1).map(|a| a.as_str()) {
+        Some("audit") => Some(audit_cli(&args[2..])),
+        Some("export") => Some(export_cli(&args[2..])),
+        Some("scenario") => Some(scenario_cli(&args[2..])),
+        _ => None,
+    };
+    if let Some(result) = result {
+        if let Err(e) = result {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
         }
+        return;
     }
 
-    /// Notify the user what position (index+1)
-    /// they currently are at from within the queue
-    fn q_position(&self, user: &DiscordUser) {
-        match self.queue.iter().position(|r| r.uuid == user.uuid) {
-            Some(i) => {
-                self.send(format!("{} You are at position {}", user.get_mention(), i+1), None);
+    // History and feedback are kept between runs
+    let history = match History::open(DEFAULT_HISTORY_PATH) {
+        Ok(h) => h,
+        Err(e) => {
+            eprintln!("error: unable to load {}: {}", DEFAULT_HISTORY_PATH, e);
+            std::process::exit(1);
+        },
+    };
+    let feedback = match Feedback::open(DEFAULT_FEEDBACK_PATH) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("error: unable to load {}: {}", DEFAULT_FEEDBACK_PATH, e);
+            std::process::exit(1);
+        },
+    };
+
+    // Staff can change how the bot behaves without recompiling by adding scripts
+    let scripts = match Path::new(DEFAULT_SCRIPTS_DIR).is_dir() {
+        true => match Scripts::load(DEFAULT_SCRIPTS_DIR) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("error: unable to load scripts: {}", e);
+                std::process::exit(1);
             },
-            None => self.send(format!("{} You are not in the queue!", user.get_mention()), Some(MessageType::Warning)),
-        }
-    }
-
-    /// List out all students within the queue
-    fn q_list(&self) {
-        self.send(format!("Queue: {:?}", self.queue), None)
-    }
-
-    /// Assumes the user is a TA. It pops the next person off the
-    /// queue and notifies the TA of what student is next
-    fn q_next(&mut self, user: &DiscordUser) {
-        if self.queue.len() == 0 {
-            self.send(format!("{} There is no one in the queue", user.get_mention()), None);
-            return;
-        }
-
-        let student = self.queue.pop_front().unwrap();
-        self.send(format!("The next person in line is {}", student.get_mention()), None)
-    }
-
-    /// Assumes the user is a TA.
-    /// Completely empty the queue content
-    /// Within actual QueueBot, a confirmation message
-    /// is sent but this is omitted to make things simpler
-    fn q_clear(&mut self, user: &DiscordUser) {
-        self.queue.clear();
-        self.send(format!("{} The queue has been cleared", user.get_mention()), Some(MessageType::Success));
-    }
-
-    /// Assumes the user is a TA.
-    /// The user must mention a single person and that person
-    /// will then be added to the queue
-    fn q_add_other(&mut self, user: &DiscordUser, mentions: Option<Vec<DiscordUser>>) {
-        match mentions {
-            None => self.send(format!("{} You must `@mention` a user!", user.get_mention()), Some(MessageType::Warning)),
-            Some(v) => if v.len() > 1 {
-                self.send(format!("{} you must mention a single user!", user.get_mention()), Some(MessageType::Warning))
-            } else {
-                let student = v.get(0).unwrap();
-                if self.queue.contains(student) {
-                    self.send(format!("{} That student is already in the queue!", user.get_mention()), Some(MessageType::Warning));
-                } else {
-                    self.queue.push_back(student.clone());
-                    self.send(format!("{} The student has been added to the queue!", user.get_mention()), Some(MessageType::Success));
-                }
-            },
-        }
-    }
-
-    /// Assumes the user is a TA.
-    /// The user must mention a single person and that person
-    /// will then be removed from the queue
-    fn q_remove_other(&mut self, user: &DiscordUser, mentions: Option<Vec<DiscordUser>>) {
-        match mentions {
-            None => self.send(format!("{} You must `@mention` a user!", user.get_mention()), Some(MessageType::Warning)),
-            Some(v) => if v.len() > 1 {
-                self.send(format!("{} you must mention a single user!", user.get_mention()), Some(MessageType::Warning))
-            } else {
-                let student = v.get(0).unwrap();
-                if !self.queue.contains(student) {
-                    self.send(format!("{} The specified student is not in the queue!", user.get_mention()), Some(MessageType::Warning));
-                    return;
-                }
-                let pos = self.queue.iter().position(|r| r.uuid == student.uuid).unwrap();
-                self.queue.remove(pos);
-                self.send(format!("{} The student has been removed from the queue!", user.get_mention()), Some(MessageType::Success));
+        },
+        false => Scripts::new(),
+    };
+
+    // Other course tools (such as a grading sheet) can be told about queue events
+    let webhooks = match webhooks_from_env() {
+        Ok(w) => w,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        },
+    };
+
+    let (bot, people) = demo::setup();
+    let bot = bot
+        .with_audit_log(AuditLog::new(DEFAULT_AUDIT_PATH))
+        .with_history(history)
+        .with_feedback(feedback)
+        .with_webhooks(webhooks);
+    let mut bot = match bot.with_scripts(scripts) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("error: unable to load scripts: {}", e);
+            std::process::exit(1);
+        },
+    };
+
+    // Everything the bot sends is printed as it happens
+    demo::run(&mut bot, &people, |_| {});
+    let ta = people.ben;
+
+    // `cargo run -- serve [ADDRESS]` keeps the bot running so the queue
+    // can be viewed at http://127.0.0.1:8080 (or the given address).
+    // Setting `QUEUEBOT_TA_TOKEN` allows that token to run TA commands as Ben.
+    //
+    // `cargo run -- tui` keeps the bot running and shows Ben a live view of
+    // the CS 120 queue within the terminal, which he can run commands from
+    //
+    // `cargo run -- irc ADDRESS CHANNEL [NICK]` keeps the bot running within an
+    // IRC channel, which holds office hours for CS 120
+    let result = match args.get(1).map(|a| a.as_str()) {
+        Some("serve") => {
+            let addr = args.get(2).map_or("127.0.0.1:8080", |a| a.as_str());
+            let (_runtime, bot) = spawn_bot(bot);
+            let mut server = StatusServer::new(bot);
+            if let Ok(token) = std::env::var("QUEUEBOT_TA_TOKEN") {
+                server = server.with_ta_token(&token, ta.clone());
+            }
+            println!("Serving the queue on http://{}", addr);
+            server.serve(addr)
+        },
+        Some("tui") => {
+            // Printing would draw over the screen so everything goes to its log instead
+            let log = LogTransport::default();
+            let (_runtime, bot) = spawn_bot(bot.with_transport(log.clone()));
+            tui::run(bot, log, CS120_GUILD, ta.clone())
+        },
+        Some("irc") => match (args.get(2), args.get(3)) {
+            (Some(addr), Some(channel)) => {
+                let nick = args.get(4).map_or("queuebot", |n| n.as_str());
+                IrcClient::connect(addr.as_str(), nick, channel, CS120_GUILD).and_then(|client| {
+                    let (_runtime, bot) = spawn_bot(bot.with_transport(client.transport()));
+                    println!("Connected to {} as {}", addr, nick);
+                    client.run(bot)
+                })
             },
-        }
+            _ => Err(std::io::Error::other("usage: queuebot irc ADDRESS CHANNEL [NICK]")),
+        },
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
     }
 }
 
-
-fn main() {
-    // Create mock users
-    let ta = get_user("Ben", "0001", true);
-    let students = vec![
-        get_user("Kapua", "0002", false),
-        get_user("Bennett", "0003", false),
-        get_user("Russ", "0004", false),
-        get_user("Jordan", "0003", false),
-    ];
-
-    let mut bot = QueueBot::new();
-
-    // TA pings the bot to make sure it's working (responds with "Pong!")
-    bot.on_message(Message {
-        author: &ta, message: String::from("!q ping"), mentions: None
-    });
-
-    // Every student joins the queue
-    for student in students.iter() {
-        bot.on_message(Message {
-            author: &student, message: String::from("!q join"), mentions: None
-        });
+/// Set up webhooks from the environment:
+///  - `QUEUEBOT_WEBHOOKS` - URLs to send events to, separated by commas
+///  - `QUEUEBOT_WEBHOOK_SECRET` - signs every request (required if there are any URLs)
+///  - `QUEUEBOT_WEBHOOK_EVENTS` - only send these events, such as `join,leave` (every event by default)
+fn webhooks_from_env() -> Result<Webhooks, String> {
+    let urls = std::env::var("QUEUEBOT_WEBHOOKS").unwrap_or_default();
+    let urls = urls.split(',').map(|u| u.trim()).filter(|u| !u.is_empty()).collect::<Vec<&str>>();
+    if urls.is_empty() {
+        return Ok(Webhooks::new());
     }
-
-    // A list of all the students in the queue
-    bot.on_message(Message {
-        author: &ta, message: String::from("!q list"), mentions: None
-    });
-
-    // TA should not be removed from the queue (since he never joined)
-    bot.on_message(Message {
-        author: &ta, message: String::from("!q leave"), mentions: None
-    });
-
-    // Russ decides to leave the queue
-    bot.on_message(Message {
-        author: &students.get(2).unwrap(), message: String::from("!q leave"), mentions: None
-    });
-
-    // Russ checks to make sure he's not in the queue
-    bot.on_message(Message {
-        author: &students.get(2).unwrap(), message: String::from("!q pos"), mentions: None
-    });
-
-    // Jordan checks his position in the queue
-    bot.on_message(Message {
-        author: &students.get(3).unwrap(), message: String::from("!q position"), mentions: None
-    });
-
-    // TA lists the queue again after Russ leaves
-    bot.on_message(Message {
-        author: &ta, message: String::from("!q list"), mentions: None
-    });
-
-    // TA grabs pops next person off the queue
-    bot.on_message(Message {
-        author: &ta, message: String::from("!q next"), mentions: None
-    });
-
-    // TA clears the queue
-    bot.on_message(Message {
-        author: &ta, message: String::from("!q clear"), mentions: None
-    });
-
-    // Russ lists the queue in confusion
-    bot.on_message(Message {
-        author: &students.get(2).unwrap(), message: String::from("!q list"), mentions: None
-    });
-
-    // TA adds Kapua, Bennett, Russ, and Jordan back into the queue
-    for i in 0..students.len() {
-        let student = students.get(i).unwrap();
-        bot.on_message(Message {
-            author: &ta, message: format!("!q add {}", student.get_mention()), mentions: Some(vec![student.clone()])
-        });
+    let secret = std::env::var("QUEUEBOT_WEBHOOK_SECRET").map_err(|_| "QUEUEBOT_WEBHOOK_SECRET must be set to send webhooks")?;
+    let events = match std::env::var("QUEUEBOT_WEBHOOK_EVENTS") {
+        Ok(names) => names.split(',')
+            .map(|name| WebhookEvent::parse(name).ok_or_else(|| format!("unknown webhook event: {}", name.trim())))
+            .collect::<Result<Vec<WebhookEvent>, String>>()?,
+        Err(_) => Vec::new(),
+    };
+    let mut webhooks = Webhooks::new();
+    for url in urls {
+        webhooks = webhooks.with_webhook(Webhook::new(url, &secret)?.with_events(&events));
     }
+    Ok(webhooks)
+}
 
-    // Russ lists queue again
-    bot.on_message(Message {
-        author: &students.get(2).unwrap(), message: String::from("!q list"), mentions: None
-    });
-
-
-    // TA removes (still confused) Russ from the queue
-    let russ = students.get(2).unwrap();
-    bot.on_message(Message {
-        author: &ta, message: format!("!q remove {}", russ.get_mention()), mentions: Some(vec![russ.clone()])
-    });
-
-    // Kapua lists queue
-    bot.on_message(Message {
-        author: &students.get(0).unwrap(), message: String::from("!q list"), mentions: None
+/// Move the bot onto its own task within a new runtime. From then on
+/// everything else (the web server, the terminal UI and the reminder
+/// timer) sends it requests. The runtime stops once it is dropped
+fn spawn_bot(bot: QueueBot) -> (tokio::runtime::Runtime, BotHandle) {
+    let runtime = tokio::runtime::Runtime::new().expect("unable to start the async runtime");
+    let bot = runtime.block_on(async { BotHandle::spawn(bot) });
+
+    // Send any delayed reminders once they are due
+    let ticker = bot.clone();
+    runtime.spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            ticker.tick().await;
+        }
     });
-
+    (runtime, bot)
 }
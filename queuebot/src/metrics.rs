@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use chrono::Duration;
+
+use crate::audit::Outcome;
+use crate::QueueBot;
+
+/// Upper bounds (in seconds) of the buckets of the wait-time histogram,
+/// from 30 seconds up to 2 hours
+pub const WAIT_BUCKETS: [i64; 9] = [30, 60, 120, 300, 600, 900, 1800, 3600, 7200];
+
+/// How many observations fell into each bucket of `WAIT_BUCKETS`
+#[derive(Clone, Debug, Default)]
+struct Histogram {
+    /// Observations at most each bound (but more than the one before it)
+    buckets: [u64; WAIT_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(i) = WAIT_BUCKETS.iter().position(|bound| seconds <= *bound as f64) {
+            self.buckets[i] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// Counts of what the bot has done since it started, served in the
+/// Prometheus text format (see `render`) so they can be graphed over the semester.
+/// Queue lengths aren't stored here since they're read straight from the queues
+#[derive(Default)]
+pub struct Metrics {
+    /// How many times each command was run, keyed by the command's name and its outcome
+    commands: BTreeMap<(String, String), u64>,
+    /// How long students waited before a TA called them, keyed by server ID
+    waits: BTreeMap<u64, Histogram>,
+}
+
+impl Metrics {
+    /// Count a command being run. `command` is its name (not an alias)
+    pub fn count_command(&mut self, command: &str, result: Outcome) {
+        *self.commands.entry((String::from(command), result.to_string())).or_default() += 1;
+    }
+
+    /// Record how long a student within `guild` waited before being called
+    pub fn observe_wait(&mut self, guild: u64, waited: Duration) {
+        let seconds = waited.num_milliseconds().max(0) as f64 / 1000.0;
+        self.waits.entry(guild).or_default().observe(seconds);
+    }
+
+    /// How many times `command` was run with the given result
+    pub fn commands(&self, command: &str, result: Outcome) -> u64 {
+        self.commands.get(&(String::from(command), result.to_string())).copied().unwrap_or(0)
+    }
+}
+
+/// Every metric of the bot in the Prometheus text format:
+///  - `queuebot_commands_total{command, result}` - commands run, by name and outcome
+///    (`unknown` for anything which isn't a command)
+///  - `queuebot_queue_length{guild, queue}` - students waiting in each queue right now
+///  - `queuebot_wait_seconds{guild, queue}` - a histogram of how long students
+///    waited before being called by a TA
+pub fn render(bot: &QueueBot) -> String {
+    let mut out = String::new();
+    let mut guilds = bot.guilds.iter().collect::<Vec<_>>();
+    guilds.sort_by(|(_, a), (_, b)| a.config.name.cmp(&b.config.name));
+
+    out += "# HELP queuebot_commands_total Commands run, by command and result\n";
+    out += "# TYPE queuebot_commands_total counter\n";
+    for ((command, result), count) in bot.metrics.commands.iter() {
+        let _ = writeln!(out, "queuebot_commands_total{{command=\"{}\",result=\"{}\"}} {}", escape(command), result, count);
+    }
+
+    out += "# HELP queuebot_queue_length Students waiting in the queue\n";
+    out += "# TYPE queuebot_queue_length gauge\n";
+    for (id, g) in guilds.iter() {
+        let _ = writeln!(out, "queuebot_queue_length{{guild=\"{}\",queue=\"{}\"}} {}", id, escape(&g.config.name), g.queue.len());
+    }
+
+    out += "# HELP queuebot_wait_seconds How long students waited before a TA called them\n";
+    out += "# TYPE queuebot_wait_seconds histogram\n";
+    let empty = Histogram::default();
+    for (id, g) in guilds.iter() {
+        let histogram = bot.metrics.waits.get(id).unwrap_or(&empty);
+        let labels = format!("guild=\"{}\",queue=\"{}\"", id, escape(&g.config.name));
+        // Prometheus buckets count everything at most their bound so they add up as they go
+        let mut cumulative = 0;
+        for (bound, count) in WAIT_BUCKETS.iter().zip(histogram.buckets.iter()) {
+            cumulative += count;
+            let _ = writeln!(out, "queuebot_wait_seconds_bucket{{{},le=\"{}\"}} {}", labels, bound, cumulative);
+        }
+        let _ = writeln!(out, "queuebot_wait_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, histogram.count);
+        let _ = writeln!(out, "queuebot_wait_seconds_sum{{{}}} {}", labels, histogram.sum);
+        let _ = writeln!(out, "queuebot_wait_seconds_count{{{}}} {}", labels, histogram.count);
+    }
+    out
+}
+
+/// Escape a label value (backslashes, quotes and newlines have to be escaped)
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    use crate::clock::ManualClock;
+    use crate::guild::GuildConfig;
+    use crate::transport::MockTransport;
+    use crate::users::UserRegistry;
+    use crate::{DiscordUser, Message};
+
+    fn say(bot: &mut QueueBot, author: &DiscordUser, message: &str) {
+        bot.on_message(Message { id: 0, guild_id: 1, author, message: String::from(message), mentions: None });
+    }
+
+    #[test]
+    fn commands_queues_and_waits_are_measured() {
+        let mut users = UserRegistry::new();
+        let ta = users.register("Ben", "0001", true).unwrap();
+        let kapua = users.register("Kapua", "0002", false).unwrap();
+        let russ = users.register("Russ", "0004", false).unwrap();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2022, 2, 1, 14, 0, 0).unwrap());
+        let mut bot = QueueBot::new()
+            .with_guild(1, GuildConfig::new("CS 120").with_help_threads(false))
+            .with_guild(2, GuildConfig::new("CS \"150\""))
+            .with_users(users)
+            .with_transport(MockTransport::default())
+            .with_clock(clock.clone());
+        say(&mut bot, &kapua, "!q join");
+        say(&mut bot, &kapua, "!q join");
+        say(&mut bot, &russ, "!q pos");
+        say(&mut bot, &russ, "!q join");
+        clock.advance(Duration::seconds(90));
+        say(&mut bot, &ta, "!q next");
+        clock.advance(Duration::minutes(50));
+        say(&mut bot, &ta, "!q next");
+        say(&mut bot, &kapua, "!q next");
+        say(&mut bot, &kapua, "!q dance");
+        say(&mut bot, &kapua, "!q join");
+
+        assert_eq!(bot.metrics.commands("join", Outcome::Ok), 3);
+        assert_eq!(bot.metrics.commands("join", Outcome::Rejected), 1);
+        let text = render(&bot);
+        for line in [
+            "queuebot_commands_total{command=\"join\",result=\"ok\"} 3",
+            "queuebot_commands_total{command=\"join\",result=\"rejected\"} 1",
+            // Aliases are counted as the command they stand for
+            "queuebot_commands_total{command=\"position\",result=\"rejected\"} 1",
+            "queuebot_commands_total{command=\"next\",result=\"ok\"} 2",
+            // Students aren't allowed to run `!q next`
+            "queuebot_commands_total{command=\"next\",result=\"rejected\"} 1",
+            "queuebot_commands_total{command=\"unknown\",result=\"rejected\"} 1",
+            "queuebot_queue_length{guild=\"1\",queue=\"CS 120\"} 1",
+            "queuebot_queue_length{guild=\"2\",queue=\"CS \\\"150\\\"\"} 0",
+            "queuebot_wait_seconds_bucket{guild=\"1\",queue=\"CS 120\",le=\"60\"} 0",
+            "queuebot_wait_seconds_bucket{guild=\"1\",queue=\"CS 120\",le=\"120\"} 1",
+            "queuebot_wait_seconds_bucket{guild=\"1\",queue=\"CS 120\",le=\"3600\"} 2",
+            "queuebot_wait_seconds_bucket{guild=\"1\",queue=\"CS 120\",le=\"+Inf\"} 2",
+            "queuebot_wait_seconds_sum{guild=\"1\",queue=\"CS 120\"} 3180",
+            "queuebot_wait_seconds_count{guild=\"2\",queue=\"CS \\\"150\\\"\"} 0",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {}\n{}", line, text);
+        }
+    }
+}
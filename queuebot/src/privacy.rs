@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use crate::audit::AuditEntry;
+use crate::feedback::Response;
+use crate::history::Session;
+
+/// Replaces user IDs and names with pseudonyms such as `user-3fa9c1d20b7e`.
+///
+/// A pseudonym is made from a keyed hash (HMAC-SHA256) of the user's ID, so
+/// the same user always gets the same pseudonym as long as the same secret
+/// is used. This lets exports be compared against each other without
+/// revealing who anyone is. Without the secret, pseudonyms can't be turned
+/// back into IDs (even though IDs themselves are easy to guess)
+pub struct Pseudonymiser {
+    secret: Vec<u8>,
+}
+
+impl Pseudonymiser {
+    /// Create a pseudonymiser. Exports made with the same secret use the same pseudonyms
+    pub fn new(secret: &str) -> Pseudonymiser {
+        Pseudonymiser { secret: secret.as_bytes().to_vec() }
+    }
+
+    /// The pseudonym of the user with the given ID
+    pub fn pseudonym(&self, id: u64) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(id.to_string().as_bytes());
+        let hash = mac.finalize().into_bytes();
+        let hex = hash.iter().take(6).map(|b| format!("{:02x}", b)).collect::<String>();
+        format!("user-{}", hex)
+    }
+
+    /// A history session with the student and TA replaced by pseudonyms
+    fn session(&self, session: &Session) -> Value {
+        let mut value = json!(session);
+        value["user_id"] = json!(self.pseudonym(session.user_id));
+        value["ta_id"] = json!(session.ta_id.map(|id| self.pseudonym(id)));
+        value
+    }
+
+    /// A typed word with any user replaced by their pseudonym.
+    /// `names` maps lowercase usernames and tags to IDs so names and tags
+    /// typed as words can be replaced too. Punctuation around the word
+    /// (such as `Ben,` or `(Kapua)`) is kept but doesn't stop it matching.
+    /// Mentions and tags of users who aren't known are redacted, and
+    /// anything else (including plain numbers such as slot IDs) is left alone
+    fn word(&self, word: &str, names: &HashMap<String, u64>) -> String {
+        let core = word.trim_matches(|c: char| !c.is_alphanumeric() && !"<@>#".contains(c));
+        if core.is_empty() {
+            return String::from(word);
+        }
+        let replaced = match core.strip_prefix("<@").and_then(|m| m.strip_suffix('>')) {
+            Some(mention) => {
+                let inner = mention.trim_start_matches('!');
+                match inner.parse::<u64>().ok().or_else(|| names.get(&inner.to_ascii_lowercase()).copied()) {
+                    Some(id) => self.pseudonym(id),
+                    None => String::from("[redacted]"),
+                }
+            },
+            None => match names.get(&core.to_ascii_lowercase()) {
+                Some(id) => self.pseudonym(*id),
+                None if is_tag(core) => String::from("[redacted]"),
+                None => return String::from(word),
+            },
+        };
+        let start = word.find(core).expect("the core is part of the word");
+        format!("{}{}{}", &word[..start], replaced, &word[start + core.len()..])
+    }
+
+    /// An audit entry with every user replaced by a pseudonym (see `word`)
+    fn audit_entry(&self, entry: &AuditEntry, names: &HashMap<String, u64>) -> Value {
+        let arguments = entry.arguments.iter().map(|arg| self.word(arg, names)).collect::<Vec<String>>();
+
+        let mut value = json!(entry);
+        value["author"] = json!(self.pseudonym(entry.author_id));
+        value["author_id"] = json!(self.pseudonym(entry.author_id));
+        value["mentions"] = json!(entry.mention_ids.iter().map(|id| self.pseudonym(*id)).collect::<Vec<String>>());
+        value["mention_ids"] = value["mentions"].clone();
+        value["arguments"] = json!(arguments);
+        value
+    }
+
+    /// A survey answer with the TA replaced by a pseudonym, along with
+    /// anyone the student named within their comment (see `word`)
+    fn response(&self, response: &Response, names: &HashMap<String, u64>) -> Value {
+        let comment = response.comment.as_ref()
+            .map(|c| c.split_whitespace().map(|w| self.word(w, names)).collect::<Vec<String>>().join(" "));
+        let mut value = json!(response);
+        value["ta_id"] = json!(self.pseudonym(response.ta_id));
+        value["comment"] = json!(comment);
+        value
+    }
+}
+
+/// Whether a word looks like a user's tag (such as `Russ#0004`)
+fn is_tag(word: &str) -> bool {
+    match word.split_once('#') {
+        Some((name, number)) => !name.is_empty() && !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()),
+        None => false,
+    }
+}
+
+/// Turn stored history, audit entries and feedback into JSON objects (one per record)
+/// tagged with `"kind": "session"`, `"kind": "audit"` or `"kind": "feedback"`.
+/// If a pseudonymiser is given, every user is replaced by their pseudonym
+pub fn export(sessions: &[Session], audit: &[AuditEntry], feedback: &[Response], pseudonymiser: Option<&Pseudonymiser>) -> Vec<Value> {
+    // Every name that appears within the audit log, so mentions typed as arguments can be matched up
+    let mut names = HashMap::new();
+    for entry in audit.iter() {
+        let users = std::iter::once((&entry.author, entry.author_id)).chain(entry.mentions.iter().zip(entry.mention_ids.iter().copied()));
+        for (tag, id) in users {
+            names.insert(tag.to_ascii_lowercase(), id);
+            names.insert(tag.split('#').next().unwrap_or("").to_ascii_lowercase(), id);
+        }
+    }
+
+    let sessions = sessions.iter().map(|s| match pseudonymiser {
+        Some(p) => p.session(s),
+        None => json!(s),
+    });
+    let entries = audit.iter().map(|e| match pseudonymiser {
+        Some(p) => p.audit_entry(e, &names),
+        None => json!(e),
+    });
+    let responses = feedback.iter().map(|r| match pseudonymiser {
+        Some(p) => p.response(r, &names),
+        None => json!(r),
+    });
+
+    sessions.map(|mut v| { v["kind"] = json!("session"); v })
+        .chain(entries.map(|mut v| { v["kind"] = json!("audit"); v }))
+        .chain(responses.map(|mut v| { v["kind"] = json!("feedback"); v }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::Outcome;
+    use chrono::Utc;
+
+    #[test]
+    fn pseudonyms_are_stable_per_secret() {
+        let a = Pseudonymiser::new("secret");
+        assert_eq!(a.pseudonym(42), Pseudonymiser::new("secret").pseudonym(42));
+        assert_ne!(a.pseudonym(42), a.pseudonym(43));
+        assert_ne!(a.pseudonym(42), Pseudonymiser::new("other").pseudonym(42));
+    }
+
+    #[test]
+    fn export_hides_every_name() {
+        let entry = AuditEntry {
+            timestamp: Utc::now(),
+            guild_id: 1,
+            author: String::from("Ben#0001"),
+            author_id: 1,
+            command: String::from("remove"),
+            arguments: vec![String::from("<@Russ>"), String::from("Jordan#0003")],
+            mentions: vec![String::from("Russ#0004")],
+            mention_ids: vec![4],
+            result: Outcome::Ok,
+        };
+        let p = Pseudonymiser::new("secret");
+        let response = Response {
+            guild_id: 1,
+            ta_id: 1,
+            date: Utc::now().date_naive(),
+            rating: 5,
+            comment: Some(String::from("Ben was great, thanks <@Russ>")),
+        };
+        let exported = export(&[], &[entry], &[response], Some(&p));
+        assert_eq!(exported[1]["kind"], "feedback");
+        assert_eq!(exported[1]["comment"], format!("{} was great, thanks {}", p.pseudonym(1), p.pseudonym(4)));
+        let exported = exported.iter().map(|v| v.to_string()).collect::<String>();
+        assert!(!exported.contains("Ben") && !exported.contains("Russ") && !exported.contains("Jordan"));
+        assert!(exported.contains(&p.pseudonym(4)));
+        assert!(exported.contains("[redacted]"));
+    }
+
+    #[test]
+    fn only_users_are_replaced() {
+        let p = Pseudonymiser::new("secret");
+        let names = HashMap::from([
+            (String::from("ben"), 1),
+            (String::from("ben#0001"), 1),
+            (String::from("kapua"), 2),
+        ]);
+        let words = |text: &str| text.split_whitespace().map(|w| p.word(w, &names)).collect::<Vec<String>>().join(" ");
+
+        // Numbers such as slot IDs aren't users
+        assert_eq!(words("book 2"), "book 2");
+        assert_eq!(words("slots remove #3"), "slots remove #3");
+        assert_eq!(words("feedback 4 great"), "feedback 4 great");
+        // Names are still found next to punctuation
+        assert_eq!(words("Ben, thanks (Kapua) and BEN#0001."), format!("{}, thanks ({}) and {}.", p.pseudonym(1), p.pseudonym(2), p.pseudonym(1)));
+        assert_eq!(words("<@2>, <@!1> <@Kapua>"), format!("{}, {} {}", p.pseudonym(2), p.pseudonym(1), p.pseudonym(2)));
+        // Unknown users are redacted
+        assert_eq!(words("<@Jordan> (Jordan#0003)"), "[redacted] ([redacted])");
+        assert_eq!(words("(...) Jordan"), "(...) Jordan");
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// A single student within the tree (see `IndexedQueue`)
+struct Node {
+    user: u64,
+    /// Random number which keeps the tree balanced (parents are always higher)
+    priority: u64,
+    left: Option<usize>,
+    right: Option<usize>,
+    parent: Option<usize>,
+    /// How many nodes are in the subtree starting at this node (including itself)
+    size: usize,
+}
+
+/// An ordered queue of user IDs where each user can only appear once.
+///
+/// A `VecDeque` has to be scanned from the front to find out if someone
+/// is in it or where they are, which gets slow once the queue is long.
+/// This keeps the queue in a tree instead (an implicit treap: a binary tree
+/// where an in-order walk gives the queue from front to back) along with a
+/// map of each user to their node. That gives
+///  - `contains` in O(1)
+///  - `position`, `get`, `insert`, `remove` and `move_to` in O(log n)
+///  - `push_back` and `pop_front` in O(log n)
+///
+/// Each node knows how many nodes are below it, so the position of a user
+/// is found by walking from their node up to the root and adding up the
+/// size of everything to the left along the way.
+/// See `benches/queue.rs` for how it compares to a `VecDeque`
+pub struct IndexedQueue {
+    /// Every node. Removed nodes are reused (see `free`)
+    nodes: Vec<Node>,
+    /// Indexes within `nodes` which aren't in use
+    free: Vec<usize>,
+    root: Option<usize>,
+    /// Map of each user to their node
+    index: HashMap<u64, usize>,
+    /// State of the random number generator used for priorities
+    seed: u64,
+}
+
+impl IndexedQueue {
+    /// Create an empty queue
+    pub fn new() -> IndexedQueue {
+        IndexedQueue { nodes: Vec::new(), free: Vec::new(), root: None, index: HashMap::new(), seed: 0x9E37_79B9_7F4A_7C15 }
+    }
+
+    /// How many users are in the queue
+    pub fn len(&self) -> usize {
+        self.size(self.root)
+    }
+
+    /// Check if no one is in the queue
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Check if a user is in the queue
+    pub fn contains(&self, user: u64) -> bool {
+        self.index.contains_key(&user)
+    }
+
+    /// Where a user is within the queue (0 is the front)
+    pub fn position(&self, user: u64) -> Option<usize> {
+        let mut node = *self.index.get(&user)?;
+        let mut rank = self.size(self.nodes[node].left);
+        while let Some(parent) = self.nodes[node].parent {
+            if self.nodes[parent].right == Some(node) {
+                rank += self.size(self.nodes[parent].left) + 1;
+            }
+            node = parent;
+        }
+        Some(rank)
+    }
+
+    /// The user at `index` (0 is the front)
+    pub fn get(&self, mut index: usize) -> Option<u64> {
+        let mut node = self.root?;
+        loop {
+            let left = self.size(self.nodes[node].left);
+            match index {
+                i if i < left => node = self.nodes[node].left?,
+                i if i == left => return Some(self.nodes[node].user),
+                _ => {
+                    index -= left + 1;
+                    node = self.nodes[node].right?;
+                },
+            }
+        }
+    }
+
+    /// Add a user to the back of the queue.
+    /// Returns false (and does nothing) if they are already in it
+    pub fn push_back(&mut self, user: u64) -> bool {
+        self.insert(self.len(), user)
+    }
+
+    /// Put a user at `index` (or the back if the queue is shorter than that).
+    /// Returns false (and does nothing) if they are already in the queue
+    pub fn insert(&mut self, index: usize, user: u64) -> bool {
+        if self.contains(user) {
+            return false;
+        }
+        let node = self.allocate(user);
+        self.index.insert(user, node);
+        let (before, after) = self.split(self.root, index);
+        let joined = self.merge(before, Some(node));
+        let root = self.merge(joined, after);
+        self.set_root(root);
+        true
+    }
+
+    /// Take a user out of the queue. Returns where they were
+    pub fn remove(&mut self, user: u64) -> Option<usize> {
+        let position = self.position(user)?;
+        let (before, rest) = self.split(self.root, position);
+        let (removed, after) = self.split(rest, 1);
+        let root = self.merge(before, after);
+        self.set_root(root);
+
+        let node = removed.expect("the user's node is at their position");
+        self.index.remove(&user);
+        self.free.push(node);
+        Some(position)
+    }
+
+    /// Take the user at the front out of the queue
+    pub fn pop_front(&mut self) -> Option<u64> {
+        let user = self.get(0)?;
+        self.remove(user);
+        Some(user)
+    }
+
+    /// Move a user who is already in the queue to `index`.
+    /// Returns false if they aren't in the queue
+    pub fn move_to(&mut self, user: u64, index: usize) -> bool {
+        self.remove(user).is_some() && self.insert(index, user)
+    }
+
+    /// Empty the queue. Returns everyone who was in it (front first)
+    pub fn clear(&mut self) -> Vec<u64> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let users = self.iter().collect();
+        *self = IndexedQueue { seed: self.seed, ..IndexedQueue::new() };
+        users
+    }
+
+    /// Every user in the queue, front first
+    pub fn iter(&self) -> Iter<'_> {
+        let mut iter = Iter { queue: self, stack: Vec::new() };
+        iter.push_left(self.root);
+        iter
+    }
+
+    fn size(&self, node: Option<usize>) -> usize {
+        node.map_or(0, |n| self.nodes[n].size)
+    }
+
+    /// Create a node for a user (reusing a free one if there is one)
+    fn allocate(&mut self, user: u64) -> usize {
+        // xorshift64 is plenty random enough to keep the tree balanced
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        let node = Node { user, priority: self.seed, left: None, right: None, parent: None, size: 1 };
+        match self.free.pop() {
+            Some(i) => {
+                self.nodes[i] = node;
+                i
+            },
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            },
+        }
+    }
+
+    fn set_root(&mut self, root: Option<usize>) {
+        self.root = root;
+        if let Some(r) = root {
+            self.nodes[r].parent = None;
+        }
+    }
+
+    /// Recalculate a node's size after its children changed and point them back at it
+    fn update(&mut self, node: usize) {
+        let (left, right) = (self.nodes[node].left, self.nodes[node].right);
+        self.nodes[node].size = 1 + self.size(left) + self.size(right);
+        for child in [left, right].into_iter().flatten() {
+            self.nodes[child].parent = Some(node);
+        }
+    }
+
+    /// Join two trees so everything in `a` comes before everything in `b`
+    fn merge(&mut self, a: Option<usize>, b: Option<usize>) -> Option<usize> {
+        match (a, b) {
+            (None, tree) | (tree, None) => tree,
+            (Some(a), Some(b)) if self.nodes[a].priority > self.nodes[b].priority => {
+                let right = self.merge(self.nodes[a].right, Some(b));
+                self.nodes[a].right = right;
+                self.update(a);
+                Some(a)
+            },
+            (Some(a), Some(b)) => {
+                let left = self.merge(Some(a), self.nodes[b].left);
+                self.nodes[b].left = left;
+                self.update(b);
+                Some(b)
+            },
+        }
+    }
+
+    /// Split a tree into its first `count` users and everyone after them
+    fn split(&mut self, tree: Option<usize>, count: usize) -> (Option<usize>, Option<usize>) {
+        let node = match tree {
+            None => return (None, None),
+            Some(n) => n,
+        };
+        let left_size = self.size(self.nodes[node].left);
+        if count <= left_size {
+            let (before, after) = self.split(self.nodes[node].left, count);
+            self.nodes[node].left = after;
+            self.update(node);
+            (before, Some(node))
+        } else {
+            let (before, after) = self.split(self.nodes[node].right, count - left_size - 1);
+            self.nodes[node].right = before;
+            self.update(node);
+            (Some(node), after)
+        }
+    }
+}
+
+impl Default for IndexedQueue {
+    fn default() -> IndexedQueue {
+        IndexedQueue::new()
+    }
+}
+
+impl fmt::Debug for IndexedQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// Allows comparing against an array such as `[russ.uuid, kapua.uuid]`
+impl<const N: usize> PartialEq<[u64; N]> for IndexedQueue {
+    fn eq(&self, other: &[u64; N]) -> bool {
+        self.len() == N && self.iter().eq(other.iter().copied())
+    }
+}
+
+/// Walks through the queue from front to back (see `IndexedQueue::iter`)
+pub struct Iter<'a> {
+    queue: &'a IndexedQueue,
+    /// Nodes still to visit, with the next one on top
+    stack: Vec<usize>,
+}
+
+impl Iter<'_> {
+    /// Push a node and every node down its left side
+    fn push_left(&mut self, mut node: Option<usize>) {
+        while let Some(n) = node {
+            self.stack.push(n);
+            node = self.queue.nodes[n].left;
+        }
+    }
+}
+
+impl Iterator for Iter<'_> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        let node = self.stack.pop()?;
+        self.push_left(self.queue.nodes[node].right);
+        Some(self.queue.nodes[node].user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    /// Check every query against the same operations on a `VecDeque`
+    fn assert_matches(queue: &IndexedQueue, expected: &VecDeque<u64>) {
+        assert_eq!(queue.len(), expected.len());
+        assert_eq!(queue.iter().collect::<Vec<u64>>(), expected.iter().copied().collect::<Vec<u64>>());
+        for (i, user) in expected.iter().enumerate() {
+            assert!(queue.contains(*user));
+            assert_eq!(queue.position(*user), Some(i));
+            assert_eq!(queue.get(i), Some(*user));
+        }
+    }
+
+    #[test]
+    fn behaves_like_a_vecdeque() {
+        let mut queue = IndexedQueue::new();
+        let mut expected = VecDeque::new();
+        // A fixed sequence of pseudo-random operations
+        let mut seed = 12345u64;
+        for _ in 0..2000 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let user = (seed >> 33) % 300;
+            match (seed >> 20) % 5 {
+                0 | 1 => if !expected.contains(&user) {
+                    assert!(queue.push_back(user));
+                    expected.push_back(user);
+                } else {
+                    assert!(!queue.push_back(user));
+                },
+                2 => {
+                    let index = expected.iter().position(|&u| u == user);
+                    assert_eq!(queue.remove(user), index);
+                    if let Some(i) = index {
+                        expected.remove(i);
+                    }
+                },
+                3 => assert_eq!(queue.pop_front(), expected.pop_front()),
+                _ => if let Some(i) = expected.iter().position(|&u| u == user) {
+                    let to = (seed as usize >> 8) % expected.len();
+                    expected.remove(i);
+                    expected.insert(to.min(expected.len()), user);
+                    assert!(queue.move_to(user, to));
+                },
+            }
+            assert_matches(&queue, &expected);
+        }
+        assert_eq!(queue.clear(), expected.drain(..).collect::<Vec<u64>>());
+        assert!(queue.is_empty());
+    }
+}
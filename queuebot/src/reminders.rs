@@ -0,0 +1,44 @@
+use chrono::{DateTime, Utc};
+
+/// A point in the queue a student is told about when they reach it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Milestone {
+    /// They reached the position set by `GuildConfig::reminder_position`
+    AlmostUp,
+    /// They are at the very front of the queue
+    Front,
+}
+
+/// A direct message which should be sent to a student once `due` has passed
+#[derive(Clone, Debug)]
+pub struct Reminder {
+    pub due: DateTime<Utc>,
+    pub guild: u64,
+    pub user: u64,
+    pub milestone: Milestone,
+}
+
+/// Holds reminders until they are due.
+///
+/// The scheduler never looks at the time itself. Instead, whoever owns it
+/// asks for everything that is due as of some time (see `QueueBot::tick`)
+/// which allows the time to come from a `Clock` that tests can control
+#[derive(Default)]
+pub struct Scheduler {
+    pending: Vec<Reminder>,
+}
+
+impl Scheduler {
+    /// Add a reminder to be sent once it is due
+    pub fn schedule(&mut self, reminder: Reminder) {
+        self.pending.push(reminder);
+    }
+
+    /// Remove and return every reminder which is due as of `now` (earliest first)
+    pub fn take_due(&mut self, now: DateTime<Utc>) -> Vec<Reminder> {
+        let (mut due, pending) = std::mem::take(&mut self.pending).into_iter().partition::<Vec<Reminder>, _>(|r| r.due <= now);
+        self.pending = pending;
+        due.sort_by_key(|r| r.due);
+        due
+    }
+}
@@ -0,0 +1,281 @@
+//! Scenarios are conversations with the bot written as plain text files, along
+//! with everything the bot is expected to send back. They are replayed through
+//! `QueueBot::on_message` so a change to the wording or order of replies shows
+//! up as a difference from the file.
+//!
+//! ```text
+//! # Lines starting with # are comments
+//! @ta Ben
+//!
+//! Kapua: !q join
+//! SEND> ✅ <@Kapua> You have been added to the queue at position 1
+//!
+//! Ben: !q list
+//! SEND> Queue: [Kapua#0001]
+//! ```
+//!
+//! Each `Author: message` line is sent by a mock user with that name (users are
+//! given the tags `#0001`, `#0002`, ... in the order they first appear). The
+//! lines after it are what the bot sent, in the same format as the console.
+//! Messages which span several lines continue on lines indented by four spaces.
+//!
+//! Directives:
+//!  - `@ta Name` makes `Name` a TA
+//!  - `@wait 5m` moves the clock forward (by `s`econds, `m`inutes or `h`ours)
+//!    and sends any reminders which are now due
+
+use std::path::{Path, PathBuf};
+
+use chrono::{Duration, TimeZone, Utc};
+
+use crate::clock::ManualClock;
+use crate::guild::GuildConfig;
+use crate::history::parse_duration;
+use crate::transport::{MockTransport, Sent};
+use crate::users::UserRegistry;
+use crate::{Message, QueueBot, User};
+
+/// The server every scenario runs in
+const GUILD: u64 = 1;
+
+/// Extension of scenario files
+pub const SCENARIO_EXTENSION: &str = "txt";
+
+/// What a line of a scenario does
+#[derive(Clone, Debug, PartialEq)]
+enum Action {
+    /// A comment, blank line or `@ta` (which is applied before the scenario starts)
+    Nothing,
+    /// `Author: message`
+    Say { author: String, message: String },
+    /// `@wait`
+    Wait(Duration),
+}
+
+/// A line of a scenario and the output expected after it
+#[derive(Clone, Debug)]
+struct Step {
+    /// The line exactly as it was written
+    line: String,
+    action: Action,
+    expected: Vec<String>,
+}
+
+/// A parsed scenario file (see the module documentation for the format)
+#[derive(Clone, Debug)]
+pub struct Scenario {
+    steps: Vec<Step>,
+    tas: Vec<String>,
+}
+
+impl Scenario {
+    /// Parse the text of a scenario file
+    pub fn parse(text: &str) -> Result<Scenario, String> {
+        let mut steps: Vec<Step> = Vec::new();
+        let mut tas = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let action = match Scenario::parse_line(line).map_err(|e| format!("line {}: {}", number + 1, e))? {
+                Some(action) => action,
+                // Anything else is output from the bot
+                None => match steps.iter_mut().rev().find(|s| s.action != Action::Nothing) {
+                    Some(step) => {
+                        step.expected.push(String::from(line));
+                        continue;
+                    },
+                    None => return Err(format!("line {}: output before any message: {}", number + 1, line)),
+                },
+            };
+            if let Some(name) = line.strip_prefix("@ta ") {
+                tas.push(String::from(name.trim()));
+            }
+            steps.push(Step { line: String::from(line), action, expected: Vec::new() });
+        }
+        Ok(Scenario { steps, tas })
+    }
+
+    /// What `line` does, or `None` if it's output from the bot
+    fn parse_line(line: &str) -> Result<Option<Action>, String> {
+        // Lines of whitespace are empty lines within a message the bot sent, not blank lines
+        if line.is_empty() || line.starts_with('#') || line.starts_with("@ta ") {
+            return Ok(Some(Action::Nothing));
+        }
+        if let Some(duration) = line.strip_prefix("@wait ") {
+            return parse_duration(duration.trim()).map(|d| Some(Action::Wait(d)));
+        }
+        if line.starts_with('@') {
+            return Err(format!("unknown directive: {}", line));
+        }
+        // Output lines start with something like `SEND>` or `DM Kapua#0001>`
+        // so a name followed straight away by a colon is a message
+        match line.split_once(": ") {
+            Some((author, message)) if !author.is_empty() && author.chars().all(|c| c.is_alphanumeric() || c == '_') => {
+                Ok(Some(Action::Say { author: String::from(author), message: String::from(message) }))
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Replay the scenario through a new bot and return what was sent after each step
+    fn run(&self) -> Result<Vec<Vec<String>>, String> {
+        let mut users = UserRegistry::new();
+        let mut registered = 0;
+        for step in self.steps.iter() {
+            if let Action::Say { author, .. } = &step.action {
+                if users.find_by_mention(&format!("<@{}>", author)).is_none() {
+                    registered += 1;
+                    users.register(author, &format!("{:04}", registered), self.tas.contains(author))?;
+                }
+            }
+        }
+        for ta in self.tas.iter() {
+            if users.find_by_mention(&format!("<@{}>", ta)).is_none() {
+                return Err(format!("@ta {} never sends a message", ta));
+            }
+        }
+
+        let transport = MockTransport::default();
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(2022, 2, 1, 14, 0, 0).unwrap());
+        let mut bot = QueueBot::new()
+            .with_guild(GUILD, GuildConfig::new("CS 120"))
+            .with_users(users)
+            .with_transport(transport.clone())
+            .with_clock(clock.clone());
+
+        let mut output = Vec::new();
+        for step in self.steps.iter() {
+            match &step.action {
+                Action::Nothing => {},
+                Action::Say { author, message } => {
+                    let author = bot.users.find_by_mention(&format!("<@{}>", author)).cloned().expect("every author was registered");
+                    let mentions = message.split_ascii_whitespace()
+                        .filter_map(|word| bot.users.find_by_mention(word.trim_end_matches([',', '.', '!', '?'])))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    bot.on_message(Message {
+                        id: 0,
+                        guild_id: GUILD,
+                        author: &author,
+                        message: message.clone(),
+                        mentions: if mentions.is_empty() { None } else { Some(mentions) },
+                    });
+                },
+                Action::Wait(duration) => {
+                    clock.advance(*duration);
+                    bot.tick();
+                },
+            }
+            output.push(transport.take().iter().flat_map(|sent| render(&bot.users, sent)).collect());
+        }
+        Ok(output)
+    }
+
+    /// Replay the scenario and describe every step where the bot
+    /// didn't send what was expected
+    pub fn check(&self) -> Result<(), String> {
+        let output = self.run()?;
+        let differences = self.steps.iter().zip(output.iter())
+            .filter(|(step, actual)| step.expected != **actual)
+            .map(|(step, actual)| {
+                let mut text = format!("{}\n  expected:\n", step.line);
+                for line in step.expected.iter() {
+                    text += &format!("  - {}\n", line);
+                }
+                text += "  got:\n";
+                for line in actual.iter() {
+                    text += &format!("  + {}\n", line);
+                }
+                text
+            })
+            .collect::<Vec<String>>();
+        match differences.is_empty() {
+            true => Ok(()),
+            false => Err(differences.join("\n")),
+        }
+    }
+
+    /// Replay the scenario and return its text with the expected output
+    /// replaced by what the bot actually sent
+    pub fn bless(&self) -> Result<String, String> {
+        let output = self.run()?;
+        let mut text = String::new();
+        for (step, actual) in self.steps.iter().zip(output.iter()) {
+            text += &step.line;
+            text.push('\n');
+            for line in actual.iter() {
+                text += line;
+                text.push('\n');
+            }
+        }
+        Ok(text)
+    }
+}
+
+/// Write something the bot sent the same way `ConsoleTransport` prints it,
+/// with every line after the first indented
+fn render(users: &UserRegistry, sent: &Sent) -> Vec<String> {
+    let tag = |id: &u64| users.get(*id).map_or(id.to_string(), |u| u.get_tag());
+    let text = match sent {
+        Sent::Channel(message) => format!("SEND> {}", message),
+        Sent::Direct(user, message) => format!("DM {}> {}", tag(user), message),
+        Sent::Buttons(message, buttons) => {
+            let buttons = buttons.iter().map(|b| format!("[{}]", b)).collect::<Vec<String>>();
+            format!("SEND> {}\n{}", message, buttons.join(" "))
+        },
+        Sent::Edited(id, message) => format!("EDIT {}> {}", id, message),
+        Sent::Deleted(id) => format!("DELETE> message {}", id),
+        Sent::Pinned(id) => format!("PIN> message {}", id),
+        Sent::Reacted(id, emoji) => format!("REACT {}> {}", id, emoji),
+        Sent::Thread(id, name, members) => {
+            let members = members.iter().map(tag).collect::<Vec<String>>();
+            format!("THREAD {}> \"{}\" with {}", id, name, members.join(", "))
+        },
+        Sent::InThread(id, message) => format!("THREAD {} SEND> {}", id, message),
+        Sent::Archived(id) => format!("ARCHIVE> thread {}", id),
+    };
+    text.lines().enumerate()
+        .map(|(i, line)| if i == 0 { String::from(line) } else { format!("    {}", line) })
+        .collect()
+}
+
+/// Every scenario file within `path` (or `path` itself if it's a file), sorted
+pub fn scenario_files<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>, String> {
+    let path = path.as_ref();
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let entries = std::fs::read_dir(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut files = entries.filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == SCENARIO_EXTENSION))
+        .collect::<Vec<PathBuf>>();
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn differences_are_reported_and_can_be_blessed() {
+        let text = "# Ben helps Kapua\n@ta Ben\n\nKapua: !q join\nSEND> wrong\n\nBen: !q remove <@Kapua>\n";
+        let scenario = Scenario::parse(text).unwrap();
+        let error = scenario.check().unwrap_err();
+        assert!(error.starts_with("Kapua: !q join\n  expected:\n  - SEND> wrong\n  got:\n  + SEND> ✅ <@Kapua>"), "{}", error);
+        assert!(error.contains("Ben: !q remove <@Kapua>\n  expected:\n  got:\n  + SEND> ✅ <@Ben> The student has been removed from the queue!"), "{}", error);
+
+        let blessed = scenario.bless().unwrap();
+        assert!(blessed.starts_with("# Ben helps Kapua\n@ta Ben\n\nKapua: !q join\nSEND> ✅"), "{}", blessed);
+        assert!(Scenario::parse(&blessed).unwrap().check().is_ok());
+        // Blessing twice changes nothing
+        assert_eq!(Scenario::parse(&blessed).unwrap().bless().unwrap(), blessed);
+    }
+
+    #[test]
+    fn invalid_scenarios_are_rejected() {
+        assert_eq!(Scenario::parse("SEND> Pong!").unwrap_err(), "line 1: output before any message: SEND> Pong!");
+        assert!(Scenario::parse("Ben: !q ping\n@wait soon").unwrap_err().starts_with("line 2: invalid duration"));
+        assert!(Scenario::parse("Ben: !q ping\n@wait 9999999999999999m").unwrap_err().starts_with("line 2: invalid duration"));
+        assert_eq!(Scenario::parse("@nap 5m").unwrap_err(), "line 1: unknown directive: @nap 5m");
+        assert_eq!(Scenario::parse("@ta Ben").unwrap().check().unwrap_err(), "@ta Ben never sends a message");
+    }
+}
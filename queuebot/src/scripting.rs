@@ -0,0 +1,283 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+
+use crate::audit::Outcome;
+use crate::commands::Command;
+use crate::{Message, QueueBot};
+
+/// Where scripts are loaded from by default
+pub const DEFAULT_SCRIPTS_DIR: &str = "scripts";
+
+/// How long a single hook or command can run before it is stopped
+const DEFAULT_TIME_LIMIT: Duration = Duration::from_millis(100);
+
+/// Custom commands are script functions whose name starts with this (`cmd_labs` is `!q labs`)
+const COMMAND_PREFIX: &str = "cmd_";
+
+/// Something which happened that scripts can react to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hook {
+    /// A student joined the queue
+    Join,
+    /// A student left the queue
+    Leave,
+    /// A TA called a student with `!q next`
+    Next,
+}
+
+impl Hook {
+    /// The name of the script function which is called
+    fn function(&self) -> &'static str {
+        match self {
+            Hook::Join => "on_join",
+            Hook::Leave => "on_leave",
+            Hook::Next => "on_next",
+        }
+    }
+}
+
+/// Something a script asked the bot to do. Scripts can't touch the bot
+/// directly, they can only ask for these which the bot carries out afterwards
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScriptAction {
+    /// Post a message in the channel (`reply("...")`)
+    Reply(String),
+    /// Send a direct message to the student the event is about (`dm("...")`)
+    Dm(String),
+    /// Write a line to the bot's log (`print("...")`)
+    Log(String),
+}
+
+/// A compiled script and the file it came from
+struct Script {
+    file: String,
+    ast: AST,
+}
+
+/// Custom behaviour written in [Rhai](https://rhai.rs) which can be changed without recompiling.
+///
+/// Every `.rhai` file within the scripts directory can define
+///  - `on_join(event)`, `on_leave(event)` and `on_next(event)` which are called
+///    after a student joins, leaves or is called
+///  - `cmd_<name>(event)` which runs when someone types `!q <name>`
+///
+/// `event` is a map describing what happened (see `QueueBot::script_event`).
+/// Scripts respond by calling `reply(text)` or `dm(text)`.
+///
+/// Scripts are sandboxed: they can't read files, open connections, import
+/// other scripts or use `eval`, and each call is stopped once it has run for
+/// longer than the time limit (so `loop {}` can't freeze the bot)
+pub struct Scripts {
+    engine: Engine,
+    scripts: Vec<Script>,
+    /// What the script being run has asked for so far
+    actions: Arc<Mutex<Vec<ScriptAction>>>,
+    /// When the script being run has to stop by
+    deadline: Arc<Mutex<Option<Instant>>>,
+    time_limit: Duration,
+}
+
+impl Scripts {
+    /// Create a sandboxed engine without any scripts
+    pub fn new() -> Scripts {
+        let actions = Arc::new(Mutex::new(Vec::new()));
+        let deadline = Arc::new(Mutex::new(None::<Instant>));
+
+        let mut engine = Engine::new();
+        engine.set_module_resolver(DummyModuleResolver::new());
+        engine.disable_symbol("eval");
+        engine.set_max_call_levels(32);
+        engine.set_max_expr_depths(64, 32);
+        engine.set_max_string_size(10_000);
+        engine.set_max_array_size(10_000);
+        engine.set_max_map_size(10_000);
+
+        let a = Arc::clone(&actions);
+        engine.register_fn("reply", move |text: &str| a.lock().unwrap().push(ScriptAction::Reply(String::from(text))));
+        let a = Arc::clone(&actions);
+        engine.register_fn("dm", move |text: &str| a.lock().unwrap().push(ScriptAction::Dm(String::from(text))));
+        let a = Arc::clone(&actions);
+        engine.on_print(move |text| a.lock().unwrap().push(ScriptAction::Log(String::from(text))));
+
+        // Checking the time is slow compared to running a single operation so only check every so often
+        let d = Arc::clone(&deadline);
+        engine.on_progress(move |operations| match *d.lock().unwrap() {
+            Some(deadline) if operations % 1024 == 0 && Instant::now() > deadline => Some(Dynamic::from("time limit reached")),
+            _ => None,
+        });
+
+        Scripts { engine, scripts: Vec::new(), actions, deadline, time_limit: DEFAULT_TIME_LIMIT }
+    }
+
+    /// Load every `.rhai` file within `dir` (in alphabetical order).
+    /// Fails if any of them can't be read or has a syntax error
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Scripts, String> {
+        let entries = std::fs::read_dir(dir.as_ref()).map_err(|e| format!("{}: {}", dir.as_ref().display(), e))?;
+        let mut paths = entries.filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "rhai"))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let mut scripts = Scripts::new();
+        for path in paths {
+            let source = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            scripts = scripts.with_script(&path.display().to_string(), &source)?;
+        }
+        Ok(scripts)
+    }
+
+    /// Add a script. `file` is only used within error messages
+    pub fn with_script(mut self, file: &str, source: &str) -> Result<Scripts, String> {
+        let ast = self.engine.compile(source).map_err(|e| format!("{}: {}", file, e))?;
+        self.scripts.push(Script { file: String::from(file), ast });
+        Ok(self)
+    }
+
+    /// Change how long a single hook or command can run for
+    #[cfg(test)]
+    pub fn with_time_limit(mut self, limit: Duration) -> Scripts {
+        self.time_limit = limit;
+        self
+    }
+
+    /// The name of every custom command (without the `cmd_` prefix)
+    /// along with the file it's defined in
+    pub fn commands(&self) -> Vec<(String, String)> {
+        self.scripts.iter()
+            .flat_map(|s| s.ast.iter_functions()
+                .filter(|f| f.params.len() == 1)
+                .filter_map(|f| f.name.strip_prefix(COMMAND_PREFIX))
+                .map(|name| (name.to_ascii_lowercase(), s.file.clone())))
+            .collect()
+    }
+
+    /// Call the hook within every script which defines it.
+    /// A script which fails is reported and skipped
+    pub fn run_hook(&self, hook: Hook, event: &Map) -> Vec<ScriptAction> {
+        let mut actions = Vec::new();
+        for script in self.scripts.iter().filter(|s| Scripts::defines(s, hook.function())) {
+            match self.call(script, hook.function(), event) {
+                Ok(a) => actions.extend(a),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        actions
+    }
+
+    /// Run the custom command `name` (which must be one of `commands`).
+    /// If more than one script defines it, the last one loaded wins
+    pub fn run_command(&self, name: &str, event: &Map) -> Result<Vec<ScriptAction>, String> {
+        let found = self.scripts.iter().rev().find_map(|s| s.ast.iter_functions()
+            .find(|f| f.params.len() == 1 && f.name.strip_prefix(COMMAND_PREFIX).is_some_and(|n| n.eq_ignore_ascii_case(name)))
+            .map(|f| (s, f.name.to_string())));
+        match found {
+            Some((script, function)) => self.call(script, &function, event),
+            None => Err(format!("no script defines {}{}", COMMAND_PREFIX, name)),
+        }
+    }
+
+    /// Check if a script has a function called `name` which takes an event
+    fn defines(script: &Script, name: &str) -> bool {
+        script.ast.iter_functions().any(|f| f.name == name && f.params.len() == 1)
+    }
+
+    /// Call a function within a script and return what it asked the bot to do
+    fn call(&self, script: &Script, function: &str, event: &Map) -> Result<Vec<ScriptAction>, String> {
+        self.actions.lock().unwrap().clear();
+        *self.deadline.lock().unwrap() = Some(Instant::now() + self.time_limit);
+        // Only run the function, not the code at the top level of the file
+        let options = CallFnOptions::new().eval_ast(false);
+        let result = self.engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &script.ast, function, (event.clone(),));
+        *self.deadline.lock().unwrap() = None;
+
+        let actions = std::mem::take(&mut *self.actions.lock().unwrap());
+        match result {
+            Ok(_) => Ok(actions),
+            Err(e) => Err(format!("{}: {} failed: {}", script.file, function, e)),
+        }
+    }
+}
+
+impl Default for Scripts {
+    fn default() -> Scripts {
+        Scripts::new()
+    }
+}
+
+/// A custom command defined by a script (see `Scripts`).
+/// Names have to live forever to be a `Command` but scripts are only
+/// loaded once when the bot starts so leaking them is fine
+pub struct ScriptCommand {
+    name: &'static str,
+    usage: &'static str,
+}
+
+impl ScriptCommand {
+    /// The command `!q <name>` defined within `file`
+    pub fn new(name: &str, file: &str) -> ScriptCommand {
+        let usage = format!("`!q {}` - Custom command from {}", name, file);
+        ScriptCommand { name: Box::leak(name.to_string().into_boxed_str()), usage: Box::leak(usage.into_boxed_str()) }
+    }
+}
+
+impl Command for ScriptCommand {
+    fn name(&self) -> &'static str { self.name }
+    fn usage(&self) -> &'static str { self.usage }
+    fn handle(&self, bot: &mut QueueBot, msg: &Message, args: &[&str]) -> Outcome {
+        bot.run_script_command(self.name, msg, args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(topic: &str) -> Map {
+        let mut event = Map::new();
+        event.insert("topic".into(), Dynamic::from(String::from(topic)));
+        event
+    }
+
+    #[test]
+    fn hooks_and_commands_ask_the_bot_to_act() {
+        let scripts = Scripts::new()
+            .with_script("labs.rhai", r#"
+                fn on_join(event) { if event.topic == "lab4" { reply("Lab 4: https://example.edu/lab4"); } }
+                fn cmd_Labs(event) { dm("All labs"); print("sent"); }
+            "#).unwrap()
+            .with_script("other.rhai", "fn on_join(event) { reply(`Welcome (${event.topic})`); }").unwrap();
+
+        assert_eq!(scripts.run_hook(Hook::Join, &event("lab4")), [
+            ScriptAction::Reply(String::from("Lab 4: https://example.edu/lab4")),
+            ScriptAction::Reply(String::from("Welcome (lab4)")),
+        ]);
+        assert_eq!(scripts.run_hook(Hook::Join, &event("lab1")), [ScriptAction::Reply(String::from("Welcome (lab1)"))]);
+        assert!(scripts.run_hook(Hook::Leave, &event("")).is_empty());
+
+        assert_eq!(scripts.commands(), [(String::from("labs"), String::from("labs.rhai"))]);
+        assert_eq!(scripts.run_command("labs", &event("")).unwrap(), [
+            ScriptAction::Dm(String::from("All labs")),
+            ScriptAction::Log(String::from("sent")),
+        ]);
+    }
+
+    #[test]
+    fn scripts_are_sandboxed_and_time_limited() {
+        let scripts = Scripts::new()
+            .with_time_limit(Duration::from_millis(50))
+            .with_script("forever.rhai", "fn on_join(event) { reply(\"started\"); loop { } }").unwrap()
+            .with_script("after.rhai", "fn on_join(event) { reply(\"still running\"); }").unwrap();
+        let started = Instant::now();
+        // The stuck script is stopped (and what it asked for is thrown away) but the others still run
+        assert_eq!(scripts.run_hook(Hook::Join, &event("")), [ScriptAction::Reply(String::from("still running"))]);
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        assert!(Scripts::new().with_script("eval.rhai", "fn on_join(event) { eval(\"1\") }").is_err());
+        let import = Scripts::new().with_script("import.rhai", "fn cmd_x(event) { import \"secrets\" as s; }").unwrap();
+        assert!(import.run_command("x", &event("")).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{DiscordUser, User};
+
+/// How the bot talks to the outside world. On Discord this would call the
+/// API to post messages; this mockup prints them to the console instead.
+/// `Send` is required so the bot can be shared between threads
+pub trait Transport: Send {
+    /// Post a message within the channel the bot is responding to.
+    /// Returns the ID of the posted message
+    fn send(&self, message: &str) -> u64;
+
+    /// Send a direct (private) message to a user. Returns the ID of the message
+    fn send_dm(&self, user: &DiscordUser, message: &str) -> u64;
+
+    /// Post a message with buttons underneath it. Returns the ID of the posted message
+    fn send_buttons(&self, message: &str, buttons: &[Button]) -> u64;
+
+    /// Replace the contents of a message the bot posted (keeping any buttons)
+    fn edit(&self, id: u64, message: &str);
+
+    /// Delete a message the bot posted with `send`
+    fn delete(&self, id: u64);
+
+    /// Pin a message to the top of the channel
+    fn pin(&self, id: u64);
+
+    /// Add an emoji reaction to a message (so users can click it too)
+    fn react(&self, id: u64, emoji: &str);
+
+    /// Open a private thread which only `members` (and TAs) can see.
+    /// Returns the ID of the thread
+    fn create_thread(&self, name: &str, members: &[&DiscordUser]) -> u64;
+
+    /// Post a message within a thread
+    fn send_to_thread(&self, thread: u64, message: &str);
+
+    /// Close a thread. It can still be read but nobody can post in it
+    fn archive_thread(&self, thread: u64);
+
+    /// Show what the bot is doing (such as `[Kapua]: !q join` when a message
+    /// is received). This isn't sent to Discord, it's only for whoever is running the bot
+    fn log(&self, line: &str);
+}
+
+/// A button shown underneath a message. Clicking it sends the bot an
+/// interaction with the button's `id` (see `Interaction`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Button {
+    pub id: &'static str,
+    pub label: String,
+}
+
+/// Prints every message to stdout (the default transport)
+#[derive(Default)]
+pub struct ConsoleTransport {
+    /// The ID given to the last message posted
+    last_id: AtomicU64,
+}
+
+impl Transport for ConsoleTransport {
+    fn send(&self, message: &str) -> u64 {
+        println!("SEND> {}\n", message);
+        self.last_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn send_dm(&self, user: &DiscordUser, message: &str) -> u64 {
+        println!("DM {}> {}\n", user.get_tag(), message);
+        self.last_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn send_buttons(&self, message: &str, buttons: &[Button]) -> u64 {
+        let labels = buttons.iter().map(|b| format!("[{}]", b.label)).collect::<Vec<String>>();
+        self.send(&format!("{}\n{}", message, labels.join(" ")))
+    }
+
+    fn edit(&self, id: u64, message: &str) {
+        println!("EDIT {}> {}\n", id, message)
+    }
+
+    fn delete(&self, id: u64) {
+        println!("DELETE> message {}\n", id)
+    }
+
+    fn pin(&self, id: u64) {
+        println!("PIN> message {}\n", id)
+    }
+
+    fn react(&self, id: u64, emoji: &str) {
+        println!("REACT {}> {}\n", id, emoji)
+    }
+
+    fn create_thread(&self, name: &str, members: &[&DiscordUser]) -> u64 {
+        let id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let members = members.iter().map(|m| m.get_tag()).collect::<Vec<String>>();
+        println!("THREAD {}> \"{}\" with {}\n", id, name, members.join(", "));
+        id
+    }
+
+    fn send_to_thread(&self, thread: u64, message: &str) {
+        println!("THREAD {} SEND> {}\n", thread, message)
+    }
+
+    fn archive_thread(&self, thread: u64) {
+        println!("ARCHIVE> thread {}\n", thread)
+    }
+
+    fn log(&self, line: &str) {
+        println!("{}", line)
+    }
+}
+
+/// A message recorded by `MockTransport`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Sent {
+    Channel(String),
+    /// The ID of the user the message was sent to and the message
+    Direct(u64, String),
+    /// A channel message and the IDs of the buttons underneath it
+    Buttons(String, Vec<&'static str>),
+    /// The ID of a channel message which was edited and its new contents
+    Edited(u64, String),
+    /// The ID of a channel message which was deleted.
+    /// Messages (including direct ones) are given IDs counting up from 1
+    Deleted(u64),
+    Pinned(u64),
+    /// The ID of a message and the emoji the bot reacted with
+    Reacted(u64, String),
+    /// A private thread which was opened: its ID, name, and the IDs of its members
+    Thread(u64, String, Vec<u64>),
+    /// The ID of a thread and a message posted within it
+    InThread(u64, String),
+    Archived(u64),
+}
+
+/// Records every message instead of sending it so tests can check what was sent.
+/// Clones share the same record so a test can keep one while the bot owns another
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    sent: std::sync::Arc<std::sync::Mutex<Vec<Sent>>>,
+    last_id: std::sync::Arc<AtomicU64>,
+}
+
+impl MockTransport {
+    /// Remove and return everything sent so far
+    pub fn take(&self) -> Vec<Sent> {
+        std::mem::take(&mut *self.sent.lock().unwrap())
+    }
+}
+
+impl Transport for MockTransport {
+    fn send(&self, message: &str) -> u64 {
+        self.sent.lock().unwrap().push(Sent::Channel(String::from(message)));
+        self.last_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn send_buttons(&self, message: &str, buttons: &[Button]) -> u64 {
+        let ids = buttons.iter().map(|b| b.id).collect();
+        self.sent.lock().unwrap().push(Sent::Buttons(String::from(message), ids));
+        self.last_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn edit(&self, id: u64, message: &str) {
+        self.sent.lock().unwrap().push(Sent::Edited(id, String::from(message)));
+    }
+
+    fn send_dm(&self, user: &DiscordUser, message: &str) -> u64 {
+        self.sent.lock().unwrap().push(Sent::Direct(user.uuid, String::from(message)));
+        self.last_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn delete(&self, id: u64) {
+        self.sent.lock().unwrap().push(Sent::Deleted(id));
+    }
+
+    fn pin(&self, id: u64) {
+        self.sent.lock().unwrap().push(Sent::Pinned(id));
+    }
+
+    fn react(&self, id: u64, emoji: &str) {
+        self.sent.lock().unwrap().push(Sent::Reacted(id, String::from(emoji)));
+    }
+
+    fn create_thread(&self, name: &str, members: &[&DiscordUser]) -> u64 {
+        let id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let members = members.iter().map(|m| m.uuid).collect();
+        self.sent.lock().unwrap().push(Sent::Thread(id, String::from(name), members));
+        id
+    }
+
+    fn send_to_thread(&self, thread: u64, message: &str) {
+        self.sent.lock().unwrap().push(Sent::InThread(thread, String::from(message)));
+    }
+
+    fn archive_thread(&self, thread: u64) {
+        self.sent.lock().unwrap().push(Sent::Archived(thread));
+    }
+
+    // Tests only check what is sent
+    fn log(&self, _line: &str) {}
+}
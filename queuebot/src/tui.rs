@@ -0,0 +1,346 @@
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration as StdDuration;
+
+use chrono::Duration;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::widgets::{Block, List, Paragraph, Row, Table, TableState};
+use ratatui::Frame;
+
+use crate::actor::{BotHandle, IncomingMessage};
+use crate::history::format_duration;
+use crate::transport::{Button, Transport};
+use crate::{DiscordUser, QueueBot, User};
+
+/// How often the screen is redrawn (so wait times keep counting up)
+const REFRESH: StdDuration = StdDuration::from_millis(250);
+
+/// How many lines of the log are kept
+const MAX_LOG_LINES: usize = 200;
+
+/// Everything the bot sends, kept for the log at the bottom of the screen.
+/// Clones share the same log so the screen can read what the bot writes
+#[derive(Clone, Default)]
+pub struct LogTransport {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    last_id: Arc<AtomicU64>,
+}
+
+impl LogTransport {
+    /// Add a line to the log (dropping the oldest once it's full)
+    fn push(&self, line: String) {
+        let mut lines = self.lines.lock().unwrap();
+        lines.push_back(line);
+        while lines.len() > MAX_LOG_LINES {
+            lines.pop_front();
+        }
+    }
+
+    /// The most recent `count` lines, oldest first
+    fn recent(&self, count: usize) -> Vec<String> {
+        let lines = self.lines.lock().unwrap();
+        lines.iter().skip(lines.len().saturating_sub(count)).cloned().collect()
+    }
+
+    fn next_id(&self) -> u64 {
+        self.last_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+// Messages can span several lines, which are joined so each takes up one line of the log
+impl Transport for LogTransport {
+    fn send(&self, message: &str) -> u64 {
+        self.push(format!("SEND> {}", message.replace('\n', " | ")));
+        self.next_id()
+    }
+
+    fn send_dm(&self, user: &DiscordUser, message: &str) -> u64 {
+        self.push(format!("DM {}> {}", user.get_tag(), message.replace('\n', " | ")));
+        self.next_id()
+    }
+
+    fn send_buttons(&self, message: &str, _buttons: &[Button]) -> u64 {
+        self.send(message)
+    }
+
+    // The pinned message is already shown by the rest of the screen
+    fn edit(&self, _id: u64, _message: &str) {}
+
+    fn delete(&self, id: u64) {
+        self.push(format!("DELETE> message {}", id));
+    }
+
+    fn pin(&self, _id: u64) {}
+
+    fn react(&self, _id: u64, _emoji: &str) {}
+
+    fn create_thread(&self, name: &str, _members: &[&DiscordUser]) -> u64 {
+        let id = self.next_id();
+        self.push(format!("THREAD {}> opened \"{}\"", id, name));
+        id
+    }
+
+    fn send_to_thread(&self, thread: u64, message: &str) {
+        self.push(format!("THREAD {} SEND> {}", thread, message.replace('\n', " | ")));
+    }
+
+    fn archive_thread(&self, thread: u64) {
+        self.push(format!("ARCHIVE> thread {}", thread));
+    }
+
+    fn log(&self, line: &str) {
+        self.push(String::from(line));
+    }
+}
+
+/// A student waiting in the queue
+#[derive(Clone, Debug, PartialEq)]
+pub struct Waiting {
+    pub student: DiscordUser,
+    pub topic: Option<String>,
+    pub waited: Duration,
+}
+
+/// A TA and the student they're helping
+#[derive(Clone, Debug, PartialEq)]
+pub struct Helping {
+    pub ta: String,
+    pub student: String,
+    /// How long ago the student was called (if it's known)
+    pub since: Option<Duration>,
+}
+
+/// Everything shown on the screen about a server at one moment.
+/// Taken from the bot all at once so the screen is never half updated
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub name: String,
+    pub on_duty: Vec<String>,
+    pub waiting: Vec<Waiting>,
+    pub helping: Vec<Helping>,
+}
+
+impl Snapshot {
+    /// The current state of the server `guild`
+    pub fn of(bot: &QueueBot, guild: u64) -> Snapshot {
+        let now = bot.clock.now();
+        let g = bot.guild(guild);
+        let tag = |id: u64| bot.users.get(id).map_or(format!("<@{}>", id), |u| u.get_tag());
+
+        let waiting = bot.queued_users(guild).into_iter()
+            .map(|s| Waiting {
+                student: s.clone(),
+                topic: g.topics.get(&s.uuid).cloned(),
+                waited: g.joined.get(&s.uuid).map_or(Duration::zero(), |joined| now - *joined),
+            })
+            .collect();
+
+        let mut helping = g.called.iter()
+            .map(|(ta, student)| Helping {
+                ta: tag(*ta),
+                student: tag(*student),
+                since: bot.history.of(guild, *student).last().and_then(|s| s.called).map(|called| now - called),
+            })
+            .collect::<Vec<Helping>>();
+        helping.sort_by(|a, b| a.ta.cmp(&b.ta));
+
+        Snapshot { name: g.config.name.clone(), on_duty: g.on_duty.iter().map(|id| tag(*id)).collect(), waiting, helping }
+    }
+}
+
+/// What pressing a key should do
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Quit,
+    /// Run a command as the TA (the text of the message and anyone it mentions)
+    Command(String, Option<Vec<DiscordUser>>),
+    Nothing,
+}
+
+/// What the TA has selected on the screen
+#[derive(Default)]
+pub struct Dashboard {
+    /// The row of the queue which is selected
+    selected: usize,
+    /// Set after `c` is pressed once. Pressing it again clears the queue
+    confirm_clear: bool,
+}
+
+impl Dashboard {
+    /// Figure out what a key does. The shortcuts are
+    ///  - `n`: call the next student (`!q next`)
+    ///  - `r` or Delete: remove the selected student (`!q remove`)
+    ///  - `c` twice: clear the queue (`!q clear`)
+    ///  - Up/Down (or `k`/`j`): select a student
+    ///  - `q` or Esc: quit
+    pub fn handle_key(&mut self, key: KeyCode, snapshot: &Snapshot) -> Action {
+        let confirmed = std::mem::take(&mut self.confirm_clear);
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => Action::Quit,
+            KeyCode::Char('n') => Action::Command(String::from("!q next"), None),
+            KeyCode::Char('r') | KeyCode::Delete => match snapshot.waiting.get(self.selected) {
+                Some(w) => Action::Command(format!("!q remove {}", w.student.get_mention()), Some(vec![w.student.clone()])),
+                None => Action::Nothing,
+            },
+            KeyCode::Char('c') if confirmed => Action::Command(String::from("!q clear"), None),
+            KeyCode::Char('c') => {
+                self.confirm_clear = true;
+                Action::Nothing
+            },
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.selected = self.selected.saturating_sub(1);
+                Action::Nothing
+            },
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected += 1;
+                Action::Nothing
+            },
+            _ => Action::Nothing,
+        }
+    }
+
+    /// Draw the whole screen
+    pub fn draw(&mut self, frame: &mut Frame, snapshot: &Snapshot, log: &[String]) {
+        // The selected student may have left
+        self.selected = self.selected.min(snapshot.waiting.len().saturating_sub(1));
+
+        let [header, middle, bottom, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(6),
+            Constraint::Length(10),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+        let [queue_area, helping_area] = Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(middle);
+
+        let on_duty = match snapshot.on_duty.is_empty() {
+            true => String::from("nobody"),
+            false => snapshot.on_duty.join(", "),
+        };
+        frame.render_widget(Paragraph::new(format!("{} | {} waiting | On duty: {}", snapshot.name, snapshot.waiting.len(), on_duty)).bold(), header);
+
+        let rows = snapshot.waiting.iter().enumerate().map(|(i, w)| Row::new(vec![
+            (i + 1).to_string(),
+            w.student.get_tag(),
+            w.topic.clone().unwrap_or_default(),
+            format_duration(w.waited),
+        ]));
+        let widths = [Constraint::Length(4), Constraint::Percentage(35), Constraint::Percentage(40), Constraint::Length(8)];
+        let table = Table::new(rows, widths)
+            .header(Row::new(vec!["#", "Student", "Topic", "Waited"]).bold())
+            .block(Block::bordered().title(" Queue "))
+            .row_highlight_style(Style::new().reversed());
+        let mut state = TableState::default().with_selected((!snapshot.waiting.is_empty()).then_some(self.selected));
+        frame.render_stateful_widget(table, queue_area, &mut state);
+
+        let helping = snapshot.helping.iter().map(|h| match h.since {
+            Some(since) => format!("{} → {} ({})", h.ta, h.student, format_duration(since)),
+            None => format!("{} → {}", h.ta, h.student),
+        }).collect::<Vec<String>>();
+        frame.render_widget(List::new(helping).block(Block::bordered().title(" Being helped ")), helping_area);
+
+        let lines = log.iter().skip(log.len().saturating_sub(bottom.height.saturating_sub(2) as usize)).cloned().collect::<Vec<String>>();
+        frame.render_widget(List::new(lines).block(Block::bordered().title(" Log ")), bottom);
+
+        let keys = match self.confirm_clear {
+            true => "Press c again to clear the queue (any other key cancels)",
+            false => "n: next  r: remove selected  c: clear  ↑/↓: select  q: quit",
+        };
+        frame.render_widget(Paragraph::new(keys).reversed(), footer);
+    }
+}
+
+/// Show a live view of the server `guild` until the TA quits.
+/// Keys run commands as `ta` through the same bot everything else uses.
+/// Must be called from outside the Tokio runtime (see `BotHandle::blocking_query`)
+pub fn run(bot: BotHandle, log: LogTransport, guild: u64, ta: DiscordUser) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let mut dashboard = Dashboard::default();
+    let result = loop {
+        let snapshot = bot.blocking_query(move |bot| Snapshot::of(bot, guild));
+        let lines = log.recent(MAX_LOG_LINES);
+        if let Err(e) = terminal.draw(|frame| dashboard.draw(frame, &snapshot, &lines)) {
+            break Err(e);
+        }
+
+        let key = match event::poll(REFRESH).and_then(|ready| if ready { event::read().map(Some) } else { Ok(None) }) {
+            Ok(Some(Event::Key(key))) if key.kind == KeyEventKind::Press => key.code,
+            Ok(_) => continue,
+            Err(e) => break Err(e),
+        };
+        match dashboard.handle_key(key, &snapshot) {
+            Action::Quit => break Ok(()),
+            Action::Command(text, mentions) => {
+                let msg = IncomingMessage { mentions, ..IncomingMessage::new(guild, &ta, &text) };
+                bot.blocking_on_message(msg);
+            },
+            Action::Nothing => {},
+        }
+    };
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::guild::GuildConfig;
+    use crate::users::UserRegistry;
+    use crate::Message;
+
+    const GUILD: u64 = 1;
+
+    fn say(bot: &mut QueueBot, author: &DiscordUser, message: &str) {
+        bot.on_message(Message { id: 0, guild_id: GUILD, author, message: String::from(message), mentions: None });
+    }
+
+    #[test]
+    fn shows_the_queue_and_maps_keys_to_commands() {
+        let mut users = UserRegistry::new();
+        let ta = users.register("Ben", "0001", true).unwrap();
+        let kapua = users.register("Kapua", "0002", false).unwrap();
+        let russ = users.register("Russ", "0004", false).unwrap();
+        let clock = ManualClock::new(chrono::Utc::now());
+        let log = LogTransport::default();
+        let mut bot = QueueBot::new()
+            .with_guild(GUILD, GuildConfig::new("CS 120"))
+            .with_users(users)
+            .with_transport(log.clone())
+            .with_clock(clock.clone());
+
+        say(&mut bot, &kapua, "!q join");
+        say(&mut bot, &russ, "!q join lab2");
+        clock.advance(Duration::minutes(3));
+        say(&mut bot, &ta, "!q next");
+        clock.advance(Duration::seconds(30));
+
+        let snapshot = Snapshot::of(&bot, GUILD);
+        assert_eq!(snapshot.waiting, [Waiting { student: russ.clone(), topic: Some(String::from("lab2")), waited: Duration::seconds(210) }]);
+        assert_eq!(snapshot.helping, [Helping { ta: String::from("Ben#0001"), student: String::from("Kapua#0002"), since: Some(Duration::seconds(30)) }]);
+
+        let mut dashboard = Dashboard::default();
+        let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+        terminal.draw(|frame| dashboard.draw(frame, &snapshot, &log.recent(MAX_LOG_LINES))).unwrap();
+        let screen = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect::<String>();
+        for text in ["CS 120 | 1 waiting", "Russ#0004", "lab2", "3m 30s", "Ben#0001 → Kapua#0002 (30s)", "[Ben]: !q next"] {
+            assert!(screen.contains(text), "{} isn't shown", text);
+        }
+
+        assert_eq!(dashboard.handle_key(KeyCode::Char('n'), &snapshot), Action::Command(String::from("!q next"), None));
+        assert_eq!(dashboard.handle_key(KeyCode::Char('r'), &snapshot), Action::Command(String::from("!q remove <@Russ>"), Some(vec![russ])));
+        // Clearing has to be confirmed
+        assert_eq!(dashboard.handle_key(KeyCode::Char('c'), &snapshot), Action::Nothing);
+        assert_eq!(dashboard.handle_key(KeyCode::Char('c'), &snapshot), Action::Command(String::from("!q clear"), None));
+        dashboard.handle_key(KeyCode::Char('c'), &snapshot);
+        dashboard.handle_key(KeyCode::Down, &snapshot);
+        assert_eq!(dashboard.handle_key(KeyCode::Char('c'), &snapshot), Action::Nothing);
+        assert_eq!(dashboard.handle_key(KeyCode::Char('q'), &snapshot), Action::Quit);
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{DiscordUser, User};
+
+/// Discord's epoch (the first second of 2015) in milliseconds since the Unix epoch
+const DISCORD_EPOCH: u64 = 1_420_070_400_000;
+
+/// Creates unique IDs the same way Discord does.
+///
+/// A snowflake is a 64 bit number made up of (from most to least significant bits)
+///  - 42 bits: milliseconds since `DISCORD_EPOCH`
+///  - 5 bits: the ID of the worker that created it
+///  - 5 bits: the ID of the process that created it
+///  - 12 bits: a counter for IDs made within the same millisecond
+///
+/// This means IDs are unique, never change, and sort by when they were created.
+/// See <https://discord.com/developers/docs/reference#snowflakes>
+pub struct SnowflakeGenerator {
+    worker_id: u64,
+    process_id: u64,
+    last_timestamp: u64,
+    increment: u64,
+}
+
+impl SnowflakeGenerator {
+    /// Create a generator. Only the lowest 5 bits of each ID are used
+    pub fn new(worker_id: u8, process_id: u8) -> SnowflakeGenerator {
+        SnowflakeGenerator {
+            worker_id: (worker_id & 0x1F) as u64,
+            process_id: (process_id & 0x1F) as u64,
+            last_timestamp: 0,
+            increment: 0,
+        }
+    }
+
+    /// Create a new ID which is larger than every ID created before it
+    pub fn next_id(&mut self) -> u64 {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+            .saturating_sub(DISCORD_EPOCH);
+
+        if now > self.last_timestamp {
+            self.last_timestamp = now;
+            self.increment = 0;
+        } else {
+            // Either the clock went backwards or many IDs were made within the
+            // same millisecond. Keep counting (borrowing the next millisecond if needed)
+            self.increment += 1;
+            if self.increment > 0xFFF {
+                self.last_timestamp += 1;
+                self.increment = 0;
+            }
+        }
+        (self.last_timestamp << 22) | (self.worker_id << 17) | (self.process_id << 12) | self.increment
+    }
+}
+
+impl Default for SnowflakeGenerator {
+    fn default() -> SnowflakeGenerator {
+        SnowflakeGenerator::new(0, 0)
+    }
+}
+
+/// Keeps track of every user the bot knows about.
+///
+/// Users are identified by their snowflake ID which never changes. Their
+/// name can change (and two users can share a name) so the queue only
+/// stores IDs and looks the current name up here whenever it's displayed
+#[derive(Default)]
+pub struct UserRegistry {
+    users: HashMap<u64, DiscordUser>,
+    /// Map of lowercase `USERNAME#DDDD` to user ID
+    tags: HashMap<String, u64>,
+    ids: SnowflakeGenerator,
+}
+
+impl UserRegistry {
+    /// Create an empty registry
+    pub fn new() -> UserRegistry {
+        UserRegistry::default()
+    }
+
+    /// Use the Factory Design Pattern to create DiscordUsers.
+    /// Each user is given a new snowflake ID
+    /// Arguments
+    /// * `name` - The name of the user
+    /// * `discriminator` - A string containing four digits
+    /// * `is_ta` - Whether or not the user is a TA
+    ///
+    /// Fails if someone already has the same tag
+    pub fn register(&mut self, name: &str, discriminator: &str, is_ta: bool) -> Result<DiscordUser, String> {
+        let user = DiscordUser {
+            uuid: self.ids.next_id(),
+            name: String::from(name),
+            discriminator: String::from(discriminator),
+            is_ta,
+        };
+        if self.tags.contains_key(&user.get_tag().to_ascii_lowercase()) {
+            return Err(format!("{} is already taken", user.get_tag()));
+        }
+        self.observe(&user);
+        Ok(user)
+    }
+
+    /// Record the latest information about a user (such as the author
+    /// of a message). If their name changed, they keep the same ID so
+    /// anything stored about them (like their spot in the queue) still applies.
+    ///
+    /// If they were renamed to a tag someone else already has, the tag keeps
+    /// pointing at its original owner (so `find_by_tag` can't be used to
+    /// take over another user). They can still be found by their ID
+    pub fn observe(&mut self, user: &DiscordUser) {
+        if let Some(old) = self.users.get(&user.uuid) {
+            let old_tag = old.get_tag().to_ascii_lowercase();
+            if self.tags.get(&old_tag) == Some(&user.uuid) {
+                self.tags.remove(&old_tag);
+            }
+        }
+        self.tags.entry(user.get_tag().to_ascii_lowercase()).or_insert(user.uuid);
+        self.users.insert(user.uuid, user.clone());
+    }
+
+    /// Look up a user by their ID
+    pub fn get(&self, id: u64) -> Option<&DiscordUser> {
+        self.users.get(&id)
+    }
+
+    /// Look up a user by their `USERNAME#DDDD` tag (case-insensitive)
+    pub fn find_by_tag(&self, tag: &str) -> Option<&DiscordUser> {
+        self.tags.get(&tag.to_ascii_lowercase()).and_then(|id| self.get(*id))
+    }
+
+    /// Look up a user from a mention. Both Discord's `<@ID>` / `<@!ID>` form
+    /// and this mockup's `<@Username>` form are understood. A username which
+    /// belongs to more than one user doesn't match anyone
+    pub fn find_by_mention(&self, mention: &str) -> Option<&DiscordUser> {
+        let inner = mention.strip_prefix("<@")?.strip_suffix('>')?;
+        let inner = inner.strip_prefix('!').unwrap_or(inner);
+        if let Ok(id) = inner.parse::<u64>() {
+            return self.get(id);
+        }
+        let mut matches = self.users.values().filter(|u| u.name.eq_ignore_ascii_case(inner));
+        match (matches.next(), matches.next()) {
+            (Some(user), None) => Some(user),
+            _ => None,
+        }
+    }
+
+    /// Look up a user from an ID, tag, or mention
+    pub fn resolve(&self, text: &str) -> Option<&DiscordUser> {
+        text.parse::<u64>().ok().and_then(|id| self.get(id))
+            .or_else(|| self.find_by_mention(text))
+            .or_else(|| self.find_by_tag(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snowflakes_increase_and_contain_the_worker() {
+        let mut ids = SnowflakeGenerator::new(3, 33);
+        let mut last = 0;
+        for _ in 0..10_000 {
+            let id = ids.next_id();
+            assert!(id > last);
+            last = id;
+        }
+        assert_eq!((last >> 17) & 0x1F, 3);
+        // Only the lowest 5 bits of the process ID are kept
+        assert_eq!((last >> 12) & 0x1F, 1);
+    }
+
+    #[test]
+    fn snowflakes_borrow_the_next_millisecond_once_the_counter_runs_out() {
+        let mut ids = SnowflakeGenerator::new(0, 0);
+        // Pretend the clock is behind so every ID is within the same millisecond
+        let millisecond = ids.next_id() >> 22;
+        ids.last_timestamp = millisecond + 1000;
+        ids.increment = 0xFFE;
+        assert_eq!(ids.next_id(), ((millisecond + 1000) << 22) | 0xFFF);
+        assert_eq!(ids.next_id(), (millisecond + 1001) << 22);
+        assert_eq!(ids.next_id(), ((millisecond + 1001) << 22) | 1);
+    }
+
+    /// The ID of the user that was found (if any)
+    fn id(user: Option<&DiscordUser>) -> Option<u64> {
+        user.map(|u| u.uuid)
+    }
+
+    #[test]
+    fn users_can_be_found_after_being_renamed() {
+        let mut users = UserRegistry::new();
+        let kapua = users.register("Kapua", "0002", false).unwrap();
+        assert_eq!(users.register("kapua", "0002", false).err().unwrap(), "kapua#0002 is already taken");
+        assert!(users.register("Kapua", "0003", false).is_ok());
+
+        let renamed = DiscordUser { name: String::from("Kap"), ..kapua.clone() };
+        users.observe(&renamed);
+        assert_eq!(users.get(kapua.uuid).unwrap().name, "Kap");
+        assert_eq!(id(users.find_by_tag("KAP#0002")), Some(renamed.uuid));
+        assert_eq!(id(users.find_by_tag("Kapua#0002")), None);
+        assert_eq!(id(users.resolve("<@Kap>")), Some(renamed.uuid));
+        assert_eq!(id(users.resolve(&kapua.uuid.to_string())), Some(renamed.uuid));
+    }
+
+    #[test]
+    fn renaming_onto_a_taken_tag_keeps_the_original_owner() {
+        let mut users = UserRegistry::new();
+        let russ = users.register("Russ", "0004", false).unwrap();
+        let kapua = users.register("Kapua", "0004", false).unwrap();
+
+        let impostor = DiscordUser { name: String::from("Russ"), ..kapua.clone() };
+        users.observe(&impostor);
+        assert_eq!(id(users.find_by_tag("Russ#0004")), Some(russ.uuid));
+        assert_eq!(users.get(kapua.uuid).unwrap().name, "Russ");
+        // The name is shared now so a `<@Russ>` mention is ambiguous
+        assert_eq!(id(users.find_by_mention("<@Russ>")), None);
+
+        // Renaming back doesn't remove Russ's tag
+        users.observe(&kapua);
+        assert_eq!(id(users.find_by_tag("Russ#0004")), Some(russ.uuid));
+        assert_eq!(id(users.find_by_tag("Kapua#0004")), Some(kapua.uuid));
+    }
+}
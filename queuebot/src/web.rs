@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+
+use serde_json::{json, Value};
+
+use crate::actor::{BotHandle, IncomingMessage};
+use crate::audit::Outcome;
+use crate::commands::Role;
+use crate::metrics;
+use crate::{DiscordUser, QueueBot, User};
+
+/// A parsed HTTP request. Only the parts the status server uses are kept
+pub struct Request {
+    /// Empty if the request line couldn't be understood
+    pub method: String,
+    pub path: String,
+    /// Header names are stored in lowercase
+    pub headers: HashMap<String, String>,
+}
+
+/// A response to send back to the client
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: Value) -> Response {
+        Response { status, content_type: "application/json", body: body.to_string() }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response::json(status, json!({ "error": message }))
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+/// A small HTTP server which shows the queue to people who aren't on Discord
+/// (such as the projector in the lab). It talks to the same bot as
+/// the Discord side (through a `BotHandle`) so both always see the same queue.
+///
+/// Each server the bot is in has its own queue. `{name}` within the routes
+/// below is either the server's ID or its name in lowercase with
+/// anything other than letters and numbers replaced by dashes (`CS 120` is `cs-120`)
+///
+/// Routes:
+///  - `GET /` - An HTML dashboard which refreshes itself
+///  - `GET /queues` - Every queue as JSON
+///  - `GET /queues/{name}` - A single queue as JSON
+///  - `POST /queues/{name}/next` - Same as `!q next` (TAs only)
+///  - `POST /queues/{name}/clear` - Same as `!q clear` (TAs only)
+///  - `GET /metrics` - Metrics in the Prometheus text format (see `metrics::render`)
+///
+/// TAs authenticate with an `Authorization: Bearer <token>` header.
+/// Each token belongs to a TA so commands show up under their name,
+/// and they can only run commands within servers they are a TA in
+pub struct StatusServer {
+    bot: BotHandle,
+    tokens: HashMap<String, DiscordUser>,
+}
+
+impl StatusServer {
+    /// Create a server for the given bot. No one can use the POST routes
+    /// until a token is added with `with_ta_token`
+    pub fn new(bot: BotHandle) -> StatusServer {
+        StatusServer { bot, tokens: HashMap::new() }
+    }
+
+    /// Allow whoever sends `token` to run TA commands as `ta`
+    pub fn with_ta_token(mut self, token: &str, ta: DiscordUser) -> StatusServer {
+        self.tokens.insert(String::from(token), ta);
+        self
+    }
+
+    /// Listen on `addr` forever, handling each connection on its own thread.
+    /// This blocks so it must not be called from within an async task
+    pub fn serve<A: ToSocketAddrs>(self, addr: A) -> io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        let server = Arc::new(self);
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("HTTP connection failed: {}", e);
+                    continue;
+                },
+            };
+            let server = Arc::clone(&server);
+            thread::spawn(move || {
+                if let Err(e) = server.handle_connection(stream) {
+                    eprintln!("HTTP error: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Read a single request from the stream and write back the response
+    fn handle_connection(&self, mut stream: TcpStream) -> io::Result<()> {
+        let request = match read_request(&mut stream)? {
+            Some(r) => r,
+            None => return Ok(()),
+        };
+        let response = self.handle(&request);
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response.status, response.reason(), response.content_type, response.body.len(), response.body
+        )?;
+        stream.flush()
+    }
+
+    /// Route a request to the matching handler
+    pub fn handle(&self, request: &Request) -> Response {
+        let path = request.path.split('?').next().unwrap_or("");
+        let segments = path.split('/').filter(|s| !s.is_empty()).collect::<Vec<&str>>();
+
+        match (request.method.as_str(), segments.as_slice()) {
+            ("", _) => Response::error(400, "malformed request"),
+            ("GET", []) => Response { status: 200, content_type: "text/html", body: String::from(include_str!("static/dashboard.html")) },
+            ("GET", ["metrics"]) => Response { status: 200, content_type: "text/plain; version=0.0.4", body: self.bot.blocking_query(|bot| metrics::render(bot)) },
+            ("GET", ["queues"]) => Response::json(200, self.bot.blocking_query(|bot| all_queues_json(bot))),
+            ("GET", ["queues", name]) => match self.find_guild(name) {
+                None => Response::error(404, "no queue with that name"),
+                Some(guild) => Response::json(200, self.bot.blocking_query(move |bot| queue_json(bot, guild))),
+            },
+            ("POST", ["queues", name, action]) => match (self.find_guild(name), self.ta_for(request)) {
+                (None, _) => Response::error(404, "no queue with that name"),
+                (_, None) => Response::error(401, "a valid TA token is required"),
+                (Some(guild), Some(ta)) => match *action {
+                    "next" => self.run_next(guild, ta),
+                    "clear" => self.run_clear(guild, ta),
+                    _ => Response::error(404, "unknown action"),
+                },
+            },
+            (_, ["queues", ..]) => Response::error(405, "method not allowed"),
+            _ => Response::error(404, "not found"),
+        }
+    }
+
+    /// Find the TA who owns the bearer token sent with the request
+    fn ta_for(&self, request: &Request) -> Option<&DiscordUser> {
+        let token = request.headers.get("authorization")?.strip_prefix("Bearer ")?;
+        self.tokens.get(token.trim())
+    }
+
+    /// Find the ID of the server a `{name}` within a route refers to
+    fn find_guild(&self, name: &str) -> Option<u64> {
+        let name = name.to_ascii_lowercase();
+        self.bot.blocking_query(move |bot| {
+            if let Ok(id) = name.parse::<u64>() {
+                if bot.guilds.contains_key(&id) {
+                    return Some(id);
+                }
+            }
+            bot.guilds.iter().find(|(_, g)| slug(&g.config.name) == name).map(|(id, _)| *id)
+        })
+    }
+
+    /// Run `!q next` as the TA and respond with the student who was called
+    /// (`null` if the queue was empty)
+    fn run_next(&self, guild: u64, ta: &DiscordUser) -> Response {
+        let ta = ta.clone();
+        self.bot.blocking_query(move |bot| {
+            let front = bot.guild(guild).queue.get(0);
+            if let Err(response) = run_command(bot, guild, &ta, "!q next") {
+                return response;
+            }
+            // The student at the front was called if they aren't waiting anymore
+            let student = front.filter(|id| !bot.guild(guild).queue.contains(*id))
+                .map(|id| bot.users.get(id).map_or(id.to_string(), |s| s.get_tag()));
+            Response::json(200, json!({ "student": student }))
+        })
+    }
+
+    /// Run `!q clear` as the TA and respond with how many students were removed
+    fn run_clear(&self, guild: u64, ta: &DiscordUser) -> Response {
+        let ta = ta.clone();
+        self.bot.blocking_query(move |bot| {
+            let waiting = bot.guild(guild).queue.iter().collect::<Vec<u64>>();
+            if let Err(response) = run_command(bot, guild, &ta, "!q clear") {
+                return response;
+            }
+            let cleared = waiting.iter().filter(|id| !bot.guild(guild).queue.contains(**id)).count();
+            Response::json(200, json!({ "cleared": cleared }))
+        })
+    }
+}
+
+/// Run a TA command within a server. This has to happen within the same
+/// `query` as working out the response so nothing else changes the queue in between.
+/// Fails with the response to send if the user isn't a TA there or the command didn't work
+fn run_command(bot: &mut QueueBot, guild: u64, ta: &DiscordUser, command: &str) -> Result<(), Response> {
+    if bot.role_of(guild, ta) < Role::Ta {
+        return Err(Response::error(403, "only TAs of this queue can do that"));
+    }
+    match bot.on_message(IncomingMessage::new(guild, ta, command).as_message()) {
+        Some(Outcome::Ok) => Ok(()),
+        Some(Outcome::Rejected) => Err(Response::error(409, "the command was rejected")),
+        _ => Err(Response::error(500, "the command failed")),
+    }
+}
+
+/// The JSON representation of every server's queue (sorted by name)
+fn all_queues_json(bot: &QueueBot) -> Value {
+    let mut guilds = bot.guilds.iter().collect::<Vec<_>>();
+    guilds.sort_by(|(_, a), (_, b)| a.config.name.cmp(&b.config.name));
+    Value::Array(guilds.iter().map(|(id, _)| queue_json(bot, **id)).collect())
+}
+
+/// The JSON representation of a server's queue.
+/// The ID is sent as a string since Javascript can't store 64 bit numbers exactly
+fn queue_json(bot: &QueueBot, guild: u64) -> Value {
+    let g = bot.guild(guild);
+    let name = &g.config.name;
+    let students = bot.queued_users(guild).iter().enumerate()
+        .map(|(i, s)| json!({ "position": i + 1, "name": s.get_name(), "tag": s.get_tag(), "topic": g.topics.get(&s.uuid) }))
+        .collect::<Vec<Value>>();
+    json!({ "id": guild.to_string(), "name": name, "slug": slug(name), "length": students.len(), "students": students })
+}
+
+/// Turn a server's name into something that can be typed within a URL
+/// (`CS 120` becomes `cs-120`)
+fn slug(name: &str) -> String {
+    name.to_ascii_lowercase().chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '-' }).collect()
+}
+
+/// Parse the request line and headers of an HTTP request.
+/// Any body is read and thrown away since none of the routes use it.
+/// Returns `None` if the client disconnected without sending anything
+fn read_request(stream: &mut TcpStream) -> io::Result<Option<Request>> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_ascii_whitespace();
+    let (method, path) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(path), Some(version)) if path.starts_with('/') && version.starts_with("HTTP/") => (method.to_string(), path.to_string()),
+        // An empty method is answered with a 400 (see `handle`)
+        _ => (String::new(), String::new()),
+    };
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let length = headers.get("content-length").and_then(|l| l.parse::<u64>().ok()).unwrap_or(0);
+    io::copy(&mut reader.take(length), &mut io::sink())?;
+    Ok(Some(Request { method, path, headers }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::guild::GuildConfig;
+    use crate::transport::MockTransport;
+    use crate::users::UserRegistry;
+
+    const GUILD: u64 = 1;
+
+    /// A server for CS 120 where Ben is a TA and Kapua (a student) also has a token.
+    /// The runtime has to be kept alive for as long as the bot is used
+    fn setup() -> (tokio::runtime::Runtime, StatusServer, BotHandle, Vec<DiscordUser>) {
+        let mut users = UserRegistry::new();
+        let ben = users.register("Ben", "0001", true).unwrap();
+        let students = [users.register("Kapua", "0002", false).unwrap(), users.register("Russ", "0004", false).unwrap()];
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let bot = {
+            let _guard = runtime.enter();
+            BotHandle::spawn(QueueBot::new()
+                .with_guild(GUILD, GuildConfig::new("CS 120").with_help_threads(false))
+                .with_users(users)
+                .with_transport(MockTransport::default()))
+        };
+        for student in students.iter() {
+            bot.blocking_on_message(IncomingMessage::new(GUILD, student, "!q join"));
+        }
+        let server = StatusServer::new(bot.clone())
+            .with_ta_token("ta-token", ben)
+            .with_ta_token("student-token", students[0].clone());
+        (runtime, server, bot, students.to_vec())
+    }
+
+    fn request(method: &str, path: &str, token: Option<&str>) -> Request {
+        let headers = token.iter().map(|t| (String::from("authorization"), format!("Bearer {}", t))).collect();
+        Request { method: String::from(method), path: String::from(path), headers }
+    }
+
+    fn queue(bot: &BotHandle) -> Vec<u64> {
+        bot.blocking_query(|bot| bot.guild(GUILD).queue.iter().collect())
+    }
+
+    #[test]
+    fn only_tas_of_the_queue_can_run_commands() {
+        let (_runtime, server, bot, students) = setup();
+        assert_eq!(server.handle(&request("POST", "/queues/cs-120/next", None)).status, 401);
+        assert_eq!(server.handle(&request("POST", "/queues/cs-120/next", Some("wrong"))).status, 401);
+        let response = server.handle(&request("POST", "/queues/cs-120/clear", Some("student-token")));
+        assert_eq!((response.status, response.body.as_str()), (403, r#"{"error":"only TAs of this queue can do that"}"#));
+        assert_eq!(queue(&bot), [students[0].uuid, students[1].uuid]);
+    }
+
+    #[test]
+    fn next_and_clear_report_what_happened() {
+        let (_runtime, server, bot, students) = setup();
+        let response = server.handle(&request("POST", "/queues/cs-120/next", Some("ta-token")));
+        assert_eq!((response.status, response.body.as_str()), (200, r#"{"student":"Kapua#0002"}"#));
+        assert_eq!(queue(&bot), [students[1].uuid]);
+
+        let response = server.handle(&request("POST", "/queues/1/clear", Some("ta-token")));
+        assert_eq!((response.status, response.body.as_str()), (200, r#"{"cleared":1}"#));
+        assert!(queue(&bot).is_empty());
+
+        // Nobody is called from an empty queue
+        let response = server.handle(&request("POST", "/queues/cs-120/next", Some("ta-token")));
+        assert_eq!((response.status, response.body.as_str()), (200, r#"{"student":null}"#));
+    }
+
+    #[test]
+    fn unknown_routes_and_queues_are_not_found() {
+        let (_runtime, server, _bot, _) = setup();
+        assert_eq!(server.handle(&request("GET", "/queues/cs-999", None)).status, 404);
+        assert_eq!(server.handle(&request("POST", "/queues/cs-999/next", Some("ta-token"))).status, 404);
+        assert_eq!(server.handle(&request("POST", "/queues/cs-120/dance", Some("ta-token"))).status, 404);
+        assert_eq!(server.handle(&request("DELETE", "/queues/cs-120", Some("ta-token"))).status, 405);
+        let response = server.handle(&request("GET", "/queues/cs-120", None));
+        assert_eq!(response.status, 200);
+        assert!(response.body.contains(r#""length":2"#), "{}", response.body);
+    }
+
+    /// Send `raw` to the server over a real connection and return the status line of the response
+    fn status_line(server: &StatusServer, raw: &str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(raw.as_bytes()).unwrap();
+        server.handle_connection(listener.accept().unwrap().0).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response.lines().next().unwrap_or("").to_string()
+    }
+
+    #[test]
+    fn malformed_requests_are_rejected() {
+        let (_runtime, server, _bot, _) = setup();
+        assert_eq!(status_line(&server, "hello there\r\n\r\n"), "HTTP/1.1 400 Bad Request");
+        assert_eq!(status_line(&server, "GET\r\n\r\n"), "HTTP/1.1 400 Bad Request");
+        assert_eq!(status_line(&server, "POST /queues/cs-120/next HTTP/1.1\r\nAuthorization: Bearer ta-token\r\nContent-Length: 2\r\n\r\n{}"), "HTTP/1.1 200 OK");
+    }
+}
@@ -0,0 +1,537 @@
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Where deliveries which failed every attempt are written if nothing else is specified
+pub const DEFAULT_DEAD_LETTER_PATH: &str = "queuebot-webhooks-failed.jsonl";
+
+/// How long to wait for a receiver to connect or respond before giving up on an attempt
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Something which happened to a queue that webhooks can be sent for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// A student joined the queue (or a TA added them with `!q add`)
+    Join,
+    /// A student left the queue (or a TA removed them with `!q remove`)
+    Leave,
+    /// A TA called the next student with `!q next`
+    Next,
+    /// A TA finished helping a student with `!q done`
+    Done,
+    /// A TA emptied the queue with `!q clear`
+    Clear,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 5] = [WebhookEvent::Join, WebhookEvent::Leave, WebhookEvent::Next, WebhookEvent::Done, WebhookEvent::Clear];
+
+    /// The name used within payloads and the `X-QueueBot-Event` header
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::Join => "join",
+            WebhookEvent::Leave => "leave",
+            WebhookEvent::Next => "next",
+            WebhookEvent::Done => "done",
+            WebhookEvent::Clear => "clear",
+        }
+    }
+
+    /// The event with the given name (such as `join`)
+    pub fn parse(name: &str) -> Option<WebhookEvent> {
+        WebhookEvent::ALL.into_iter().find(|e| e.name() == name.trim().to_ascii_lowercase())
+    }
+}
+
+/// The JSON body of a webhook. IDs are sent as strings since
+/// Javascript can't store 64 bit numbers exactly
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub event: WebhookEvent,
+    pub timestamp: DateTime<Utc>,
+    /// ID of the server the queue belongs to
+    pub guild_id: String,
+    /// Name of the queue (the server's name)
+    pub queue: String,
+    /// How many students are waiting after the event
+    pub queue_length: usize,
+    /// Tag of the student the event is about (every event other than `clear`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub student: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub student_id: Option<String>,
+    /// Tag of the TA, if a TA caused the event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ta: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ta_id: Option<String>,
+    /// What the student needed help with (if they said)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+    /// Tags of everyone who was waiting when the queue was cleared
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cleared: Vec<String>,
+}
+
+/// A line within the dead-letter file: a delivery which failed every attempt.
+/// `payload` is exactly what would have been sent, so it can be replayed later
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub failed_at: DateTime<Utc>,
+    pub url: String,
+    pub attempts: u32,
+    /// Why the last attempt failed
+    pub error: String,
+    pub payload: WebhookPayload,
+}
+
+/// Somewhere to send webhooks to
+#[derive(Clone, Debug)]
+pub struct Webhook {
+    url: String,
+    /// `host:port` as written within the URL (sent as the `Host` header)
+    host: String,
+    path: String,
+    secret: String,
+    /// Events this webhook wants (every event if this is empty)
+    events: Vec<WebhookEvent>,
+}
+
+impl Webhook {
+    /// A webhook which `POST`s every event to `url` signed with `secret`.
+    /// Only `http://` URLs are supported (put a proxy in front of receivers which need HTTPS)
+    pub fn new(url: &str, secret: &str) -> Result<Webhook, String> {
+        let rest = match url.strip_prefix("http://") {
+            Some(rest) => rest,
+            None if url.starts_with("https://") => return Err(format!("{}: only http:// webhooks are supported", url)),
+            None => return Err(format!("{}: webhook URLs must start with http://", url)),
+        };
+        let (host, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        if host.is_empty() {
+            return Err(format!("{}: missing a host", url));
+        }
+        Ok(Webhook {
+            url: String::from(url),
+            host: String::from(host),
+            path: String::from(path),
+            secret: String::from(secret),
+            events: Vec::new(),
+        })
+    }
+
+    /// Only send the given events to this webhook
+    pub fn with_events(mut self, events: &[WebhookEvent]) -> Webhook {
+        self.events = events.to_vec();
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Check if this webhook wants to be told about `event`
+    fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+
+    /// Send a single request with the (already serialised) payload
+    fn post(&self, event: WebhookEvent, body: &str) -> Result<(), Failure> {
+        let connect_to = match self.host.contains(':') {
+            true => self.host.clone(),
+            false => format!("{}:80", self.host),
+        };
+        let addr = connect_to.to_socket_addrs()
+            .map_err(|e| Failure::Retry(format!("unable to resolve {}: {}", self.host, e)))?
+            .next()
+            .ok_or_else(|| Failure::Retry(format!("unable to resolve {}", self.host)))?;
+        let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT).map_err(Failure::io)?;
+        stream.set_read_timeout(Some(TIMEOUT)).map_err(Failure::io)?;
+        stream.set_write_timeout(Some(TIMEOUT)).map_err(Failure::io)?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: queuebot\r\nContent-Type: application/json\r\nContent-Length: {}\r\nX-QueueBot-Event: {}\r\nX-QueueBot-Signature: {}\r\nConnection: close\r\n\r\n{}",
+            self.path, self.host, body.len(), event.name(), sign(&self.secret, body), body
+        ).map_err(Failure::io)?;
+        stream.flush().map_err(Failure::io)?;
+
+        let mut status = String::new();
+        BufReader::new(stream).read_line(&mut status).map_err(Failure::io)?;
+        let status = status.trim();
+        match status.split_ascii_whitespace().nth(1).and_then(|s| s.parse::<u16>().ok()) {
+            Some(200..=299) => Ok(()),
+            // The receiver is overloaded or broken, which might not last
+            Some(429) | Some(500..=599) => Err(Failure::Retry(format!("receiver responded with {}", status))),
+            // Anything else (such as 404) will be rejected again
+            Some(_) => Err(Failure::GiveUp(format!("receiver responded with {}", status))),
+            None => Err(Failure::Retry(format!("invalid response: {:?}", status))),
+        }
+    }
+}
+
+/// Why a delivery attempt failed
+enum Failure {
+    /// Trying again later might work
+    Retry(String),
+    /// Trying again won't help
+    GiveUp(String),
+}
+
+impl Failure {
+    fn io(e: io::Error) -> Failure {
+        Failure::Retry(e.to_string())
+    }
+}
+
+/// The value of the `X-QueueBot-Signature` header: `sha256=` followed by the
+/// hex HMAC-SHA256 of the body keyed with the webhook's secret.
+/// Receivers should compute the same thing and reject requests which don't match
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    let hex = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    format!("sha256={}", hex)
+}
+
+/// A payload waiting to be delivered to a webhook
+struct Delivery {
+    webhook: Webhook,
+    payload: WebhookPayload,
+}
+
+/// How deliveries are retried and where the ones which never succeed end up
+#[derive(Clone)]
+struct RetryPolicy {
+    attempts: u32,
+    /// How long to wait after the first failure (doubled after each failure)
+    backoff: Duration,
+    dead_letters: Option<PathBuf>,
+}
+
+/// Sends queue events to other course tools (such as a grading sheet or a lab
+/// status board) as JSON `POST` requests.
+///
+/// Webhooks are delivered by a background thread, one at a time in the order
+/// the events happened, so a slow receiver never holds up the bot. Failed
+/// deliveries are retried with exponential backoff (1s, 2s, 4s, ... by default)
+/// and once every attempt has failed, the delivery is appended to a
+/// dead-letter file as JSON-lines so nothing is silently lost.
+/// Dropping `Webhooks` waits for every queued delivery to finish
+pub struct Webhooks {
+    webhooks: Vec<Webhook>,
+    policy: RetryPolicy,
+    /// Started the first time there is something to deliver
+    worker: Option<(Sender<Delivery>, JoinHandle<()>)>,
+}
+
+impl Default for Webhooks {
+    fn default() -> Webhooks {
+        Webhooks::new()
+    }
+}
+
+impl Webhooks {
+    /// No webhooks, which sends nothing. Failed deliveries go to `DEFAULT_DEAD_LETTER_PATH`
+    pub fn new() -> Webhooks {
+        Webhooks {
+            webhooks: Vec::new(),
+            policy: RetryPolicy {
+                attempts: 5,
+                backoff: Duration::from_secs(1),
+                dead_letters: Some(PathBuf::from(DEFAULT_DEAD_LETTER_PATH)),
+            },
+            worker: None,
+        }
+    }
+
+    /// Send events to the given webhook too
+    pub fn with_webhook(mut self, webhook: Webhook) -> Webhooks {
+        self.webhooks.push(webhook);
+        self
+    }
+
+    /// Try each delivery up to `attempts` times, waiting `backoff` after the
+    /// first failure and twice as long after each one after that
+    pub fn with_retries(mut self, attempts: u32, backoff: Duration) -> Webhooks {
+        self.policy.attempts = attempts.max(1);
+        self.policy.backoff = backoff;
+        self
+    }
+
+    /// Write deliveries which failed every attempt to `path` (`None` throws them away)
+    pub fn with_dead_letters<P: AsRef<Path>>(mut self, path: Option<P>) -> Webhooks {
+        self.policy.dead_letters = path.map(|p| p.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.webhooks.is_empty()
+    }
+
+    /// Queue the payload for delivery to every webhook which wants its event
+    pub fn emit(&mut self, payload: WebhookPayload) {
+        let targets = self.webhooks.iter().filter(|w| w.wants(payload.event)).cloned().collect::<Vec<Webhook>>();
+        if targets.is_empty() {
+            return;
+        }
+        let policy = self.policy.clone();
+        let (sender, _) = self.worker.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel::<Delivery>();
+            let handle = thread::spawn(move || {
+                for delivery in receiver {
+                    deliver(&delivery, &policy);
+                }
+            });
+            (sender, handle)
+        });
+        for webhook in targets {
+            // The worker only stops once the sender is dropped
+            let _ = sender.send(Delivery { webhook, payload: payload.clone() });
+        }
+    }
+}
+
+impl Drop for Webhooks {
+    fn drop(&mut self) {
+        if let Some((sender, handle)) = self.worker.take() {
+            drop(sender);
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Deliver a payload, retrying until it succeeds or runs out of attempts
+/// (in which case it's written to the dead-letter file)
+fn deliver(delivery: &Delivery, policy: &RetryPolicy) {
+    let body = serde_json::to_string(&delivery.payload).expect("payloads can always be serialised");
+    let mut backoff = policy.backoff;
+    let mut attempts = 0;
+    let error = loop {
+        attempts += 1;
+        match delivery.webhook.post(delivery.payload.event, &body) {
+            Ok(()) => return,
+            Err(Failure::Retry(e)) if attempts < policy.attempts => {
+                eprintln!("Webhook to {} failed ({}), retrying in {:?}", delivery.webhook.url, e, backoff);
+                thread::sleep(backoff);
+                backoff *= 2;
+            },
+            Err(Failure::Retry(e)) | Err(Failure::GiveUp(e)) => break e,
+        }
+    };
+    eprintln!("Webhook to {} failed after {} attempt(s): {}", delivery.webhook.url, attempts, error);
+
+    let path = match &policy.dead_letters {
+        Some(p) => p,
+        None => return,
+    };
+    let letter = DeadLetter {
+        failed_at: Utc::now(),
+        url: delivery.webhook.url.clone(),
+        attempts,
+        error,
+        payload: delivery.payload.clone(),
+    };
+    let line = serde_json::to_string(&letter).expect("dead letters can always be serialised");
+    let written = OpenOptions::new().create(true).append(true).open(path)
+        .and_then(|mut file| writeln!(file, "{}", line));
+    if let Err(e) = written {
+        eprintln!("Unable to write to {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    use crate::guild::GuildConfig;
+    use crate::transport::MockTransport;
+    use crate::users::UserRegistry;
+    use crate::{Message, QueueBot};
+
+    /// A request received by `receiver`
+    struct Received {
+        request_line: String,
+        headers: Vec<(String, String)>,
+        body: String,
+    }
+
+    impl Received {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+        }
+    }
+
+    /// A local HTTP server which responds to each request with the next status
+    /// within `statuses` and returns its address along with every request it received
+    fn receiver(statuses: &[u16]) -> (String, JoinHandle<Vec<Received>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let statuses = statuses.to_vec();
+        let handle = thread::spawn(move || {
+            let mut received = Vec::new();
+            for status in statuses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut headers = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim().split_once(':') {
+                        Some((name, value)) => headers.push((name.trim().to_string(), value.trim().to_string())),
+                        None => break,
+                    }
+                }
+                let length = headers.iter().find(|(n, _)| n == "Content-Length").map_or(0, |(_, v)| v.parse::<u64>().unwrap());
+                let mut body = String::new();
+                reader.take(length).read_to_string(&mut body).unwrap();
+                write!(stream, "HTTP/1.1 {} Whatever\r\nContent-Length: 0\r\n\r\n", status).unwrap();
+                received.push(Received { request_line: request_line.trim().to_string(), headers, body });
+            }
+            received
+        });
+        (addr, handle)
+    }
+
+    fn payload(event: WebhookEvent) -> WebhookPayload {
+        WebhookPayload {
+            event,
+            timestamp: Utc::now(),
+            guild_id: String::from("1"),
+            queue: String::from("CS 120"),
+            queue_length: 1,
+            student: Some(String::from("Kapua#0002")),
+            student_id: Some(String::from("2")),
+            ta: None,
+            ta_id: None,
+            topic: None,
+            cleared: Vec::new(),
+        }
+    }
+
+    fn dead_letter_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("queuebot-webhooks-{}-{}.jsonl", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn urls_are_parsed() {
+        let webhook = Webhook::new("http://localhost:9000/hooks/queue", "s").unwrap();
+        assert_eq!((webhook.host.as_str(), webhook.path.as_str()), ("localhost:9000", "/hooks/queue"));
+        assert_eq!(Webhook::new("http://example.com", "s").unwrap().path, "/");
+        assert!(Webhook::new("https://example.com/", "s").unwrap_err().contains("only http://"));
+        assert!(Webhook::new("example.com", "s").is_err());
+        assert_eq!(WebhookEvent::parse(" Next"), Some(WebhookEvent::Next));
+    }
+
+    #[test]
+    fn payloads_are_signed_and_retried() {
+        let (addr, server) = receiver(&[500, 503, 200]);
+        let path = dead_letter_path("retried");
+        let mut webhooks = Webhooks::new()
+            .with_webhook(Webhook::new(&format!("http://{}/queue", addr), "hunter2").unwrap())
+            .with_retries(3, Duration::from_millis(10))
+            .with_dead_letters(Some(&path));
+        let sent = payload(WebhookEvent::Join);
+        webhooks.emit(sent.clone());
+        drop(webhooks);
+
+        let received = server.join().unwrap();
+        assert_eq!(received.len(), 3);
+        let last = &received[2];
+        assert_eq!(last.request_line, "POST /queue HTTP/1.1");
+        assert_eq!(last.header("X-QueueBot-Event"), Some("join"));
+        assert_eq!(last.header("X-QueueBot-Signature"), Some(sign("hunter2", &last.body).as_str()));
+        assert_ne!(last.header("X-QueueBot-Signature"), Some(sign("wrong", &last.body).as_str()));
+        assert_eq!(serde_json::from_str::<WebhookPayload>(&last.body).unwrap(), sent);
+        // Every attempt sends exactly the same request
+        assert!(received.iter().all(|r| r.body == last.body));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn failed_deliveries_are_dead_lettered() {
+        let (addr, server) = receiver(&[500, 500, 404]);
+        let path = dead_letter_path("failed");
+        let url = format!("http://{}/", addr);
+        let mut webhooks = Webhooks::new()
+            .with_webhook(Webhook::new(&url, "s").unwrap())
+            // Only wants `next` so the `join` isn't sent at all
+            .with_webhook(Webhook::new(&url, "s").unwrap().with_events(&[WebhookEvent::Next]))
+            .with_retries(2, Duration::from_millis(10))
+            .with_dead_letters(Some(&path));
+        webhooks.emit(payload(WebhookEvent::Join));
+        // A 404 isn't retried
+        webhooks.emit(payload(WebhookEvent::Leave));
+        drop(webhooks);
+        assert_eq!(server.join().unwrap().len(), 3);
+
+        let letters = std::fs::read_to_string(&path).unwrap().lines()
+            .map(|l| serde_json::from_str::<DeadLetter>(l).unwrap())
+            .collect::<Vec<DeadLetter>>();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(letters.len(), 2);
+        assert_eq!((letters[0].attempts, letters[0].payload.event), (2, WebhookEvent::Join));
+        assert_eq!(letters[0].url, url);
+        assert!(letters[0].error.contains("500"), "{}", letters[0].error);
+        assert_eq!((letters[1].attempts, letters[1].payload.event), (1, WebhookEvent::Leave));
+        assert!(letters[1].error.contains("404"), "{}", letters[1].error);
+    }
+
+    #[test]
+    fn queue_events_are_sent() {
+        // `done` is sent whether or not the server uses help threads
+        for help_threads in [true, false] {
+            let mut users = UserRegistry::new();
+            let ta = users.register("Ben", "0001", true).unwrap();
+            let kapua = users.register("Kapua", "0002", false).unwrap();
+            let russ = users.register("Russ", "0004", false).unwrap();
+            let (addr, server) = receiver(&[200; 7]);
+            let mut bot = QueueBot::new()
+                .with_guild(1, GuildConfig::new("CS 120").with_help_threads(help_threads))
+                .with_users(users)
+                .with_transport(MockTransport::default())
+                .with_webhooks(Webhooks::new()
+                    .with_webhook(Webhook::new(&format!("http://{}/", addr), "s").unwrap())
+                    .with_dead_letters(None::<PathBuf>));
+            for (author, message) in [(&kapua, "!q join recursion"), (&russ, "!q join"), (&ta, "!q next"), (&ta, "!q done"), (&russ, "!q leave"), (&ta, "!q list"), (&kapua, "!q join"), (&ta, "!q clear")] {
+                bot.on_message(Message { id: 0, guild_id: 1, author, message: String::from(message), mentions: None });
+            }
+            drop(bot);
+
+            let payloads = server.join().unwrap().iter()
+                .map(|r| serde_json::from_str::<WebhookPayload>(&r.body).unwrap())
+                .collect::<Vec<WebhookPayload>>();
+            let summary = payloads.iter()
+                .map(|p| (p.event, p.student.as_deref(), p.ta.as_deref(), p.topic.as_deref(), p.queue_length))
+                .collect::<Vec<_>>();
+            assert_eq!(summary, [
+                (WebhookEvent::Join, Some("Kapua#0002"), None, Some("recursion"), 1),
+                (WebhookEvent::Join, Some("Russ#0004"), None, None, 2),
+                (WebhookEvent::Next, Some("Kapua#0002"), Some("Ben#0001"), Some("recursion"), 1),
+                (WebhookEvent::Done, Some("Kapua#0002"), Some("Ben#0001"), None, 1),
+                (WebhookEvent::Leave, Some("Russ#0004"), None, None, 0),
+                (WebhookEvent::Join, Some("Kapua#0002"), None, None, 1),
+                (WebhookEvent::Clear, None, Some("Ben#0001"), None, 0),
+            ]);
+            assert_eq!(payloads[6].cleared, ["Kapua#0002"]);
+            assert!(payloads.iter().all(|p| p.queue == "CS 120" && p.guild_id == "1"));
+            assert_eq!(payloads[5].student_id.as_deref(), Some(kapua.uuid.to_string().as_str()));
+        }
+    }
+}
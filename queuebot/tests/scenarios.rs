@@ -0,0 +1,18 @@
+//! Replays every scenario within `tests/scenarios` (see `queuebot::scenario`).
+//! After changing what the bot says on purpose, update them with
+//! `cargo run -- scenario --bless tests/scenarios`
+
+use queuebot::scenario::{scenario_files, Scenario};
+
+#[test]
+fn scenarios_match_their_expected_replies() {
+    let files = scenario_files(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/scenarios")).unwrap();
+    assert!(!files.is_empty());
+
+    let failures = files.iter().filter_map(|file| {
+        let text = std::fs::read_to_string(file).unwrap();
+        let result = Scenario::parse(&text).and_then(|s| s.check());
+        result.err().map(|e| format!("{}\n{}", file.display(), e))
+    }).collect::<Vec<String>>();
+    assert!(failures.is_empty(), "\n{}\nRun `cargo run -- scenario --bless tests/scenarios` if these changes are intended", failures.join("\n"));
+}
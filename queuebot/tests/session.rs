@@ -0,0 +1,210 @@
+//! The scripted office hours session from `queuebot::demo` (which `main` runs),
+//! checked step by step. The bot uses a mock transport and a stopped clock
+//! so replies (including how long students waited) are always the same
+
+use chrono::{TimeZone, Utc};
+
+use queuebot::audit::AuditLog;
+use queuebot::clock::ManualClock;
+use queuebot::demo;
+use queuebot::interactions::{JOIN_BUTTON, LEAVE_BUTTON};
+use queuebot::scripting::{Scripts, DEFAULT_SCRIPTS_DIR};
+use queuebot::transport::{MockTransport, Sent};
+
+/// A message sent within the channel
+fn channel(text: &str) -> Sent {
+    Sent::Channel(String::from(text))
+}
+
+/// A direct message sent to the user with the given ID
+fn direct(id: u64, text: &str) -> Sent {
+    Sent::Direct(id, String::from(text))
+}
+
+/// The message `!q pin` posts (and keeps up to date) for the CS 150 queue
+fn pinned_queue(waiting: &str) -> String {
+    format!("**CS 150 queue**: {}\nClick Join or react with 🙋 to join the queue, or Leave or 👋 to leave it", waiting)
+}
+
+/// Run the session with the example scripts and an audit log (so `!q audit` works),
+/// returning everything the bot sent after each step
+fn run_session() -> (Vec<Vec<Sent>>, demo::People) {
+    let audit_path = std::env::temp_dir().join(format!("queuebot-session-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&audit_path);
+    let transport = MockTransport::default();
+    let clock = ManualClock::new(Utc.with_ymd_and_hms(2022, 2, 1, 14, 0, 0).unwrap());
+    let (bot, people) = demo::setup();
+    let mut bot = bot
+        .with_audit_log(AuditLog::new(&audit_path))
+        .with_scripts(Scripts::load(DEFAULT_SCRIPTS_DIR).unwrap())
+        .unwrap()
+        .with_transport(transport.clone())
+        .with_clock(clock);
+
+    let mut steps = Vec::new();
+    demo::run(&mut bot, &people, |_| steps.push(transport.take()));
+    std::fs::remove_file(&audit_path).unwrap();
+    (steps, people)
+}
+
+#[test]
+fn demo_session() {
+    let (steps, p) = run_session();
+    let mut steps = steps.into_iter();
+    let mut next = || steps.next().expect("the session has fewer steps than expected");
+    let added = || channel("✅ <@Ben> The student has been added to the queue!");
+
+    // CS 120, where Ben runs office hours on his own
+    assert_eq!(next(), [channel("Pong!")]);
+    assert_eq!(next(), [channel("Office hours: Mon/Wed 2-4pm and Fri 10am-12pm in Boyd 307")]);
+
+    // Every student joins the queue
+    assert_eq!(next(), [channel("✅ <@Kapua> You have been added to the queue at position 1")]);
+    assert_eq!(next(), [channel("✅ <@Bennett> You have been added to the queue at position 2")]);
+    assert_eq!(next(), [channel("✅ <@Russ> You have been added to the queue at position 3")]);
+    assert_eq!(next(), [channel("✅ <@Jordan> You have been added to the queue at position 4")]);
+    assert_eq!(next(), [channel("Queue: [Kapua#0002, Bennett#0003, Russ#0004, Jordan#0003]")]);
+
+    // Ben isn't in the queue so he can't leave it
+    assert_eq!(next(), [channel("⚠️ <@Ben> You are not in the queue!")]);
+
+    // Russ leaves and checks he's not in the queue anymore
+    assert_eq!(next(), [
+        channel("✅ <@Russ> You have been removed from the queue"),
+        direct(p.jordan.uuid, "<@Jordan> You are now at position 3 in the CS 120 queue. Please get ready!"),
+    ]);
+    assert_eq!(next(), [channel("⚠️ <@Russ> You are not in the queue!")]);
+
+    // Jordan switches to Spanish
+    assert_eq!(next(), [channel("✅ <@Jordan> Tu idioma se ha cambiado a Español")]);
+    assert_eq!(next(), [channel("<@Jordan> Estás en la posición 3")]);
+    assert_eq!(next(), [channel("Queue: [Kapua#0002, Bennett#0003, Jordan#0003]")]);
+
+    // Ben calls Kapua then clears the queue
+    assert_eq!(next(), [
+        channel("The next person in line is <@Kapua>"),
+        direct(p.bennett.uuid, "<@Bennett> You are next in line in the CS 120 queue! A TA will be with you soon."),
+    ]);
+    assert_eq!(next(), [channel("✅ <@Ben> The queue has been cleared")]);
+    assert_eq!(next(), [channel("Queue: []")]);
+
+    // Ben adds everyone back
+    for _ in 0..4 {
+        assert_eq!(next(), [added()]);
+    }
+    assert_eq!(next(), [channel("Queue: [Kapua#0002, Bennett#0003, Russ#0004, Jordan#0003]")]);
+
+    // Ben removes Russ, who complains so Ben checks the audit log
+    assert_eq!(next(), [
+        channel("✅ <@Ben> The student has been removed from the queue!"),
+        direct(p.jordan.uuid, "<@Jordan> Ahora estás en la posición 3 de la cola de CS 120. ¡Prepárate!"),
+    ]);
+    assert_eq!(next(), [channel("Queue: [Kapua#0002, Bennett#0003, Jordan#0003]")]);
+    assert_eq!(next(), [
+        channel("<@Ben> Audit log for Russ#0004 (7 entries):\n[2022-02-01 14:00:00 UTC] Russ#0004 join -> ok\n[2022-02-01 14:00:00 UTC] Russ#0004 leave -> ok\n[2022-02-01 14:00:00 UTC] Russ#0004 pos -> rejected\n[2022-02-01 14:00:00 UTC] Russ#0004 list -> ok\n[2022-02-01 14:00:00 UTC] Ben#0001 add <@Russ> -> ok\n[2022-02-01 14:00:00 UTC] Russ#0004 list -> ok\n[2022-02-01 14:00:00 UTC] Ben#0001 remove <@Russ> -> ok"),
+    ]);
+
+    // Kapua checks how long she waited last time (the clock never moves)
+    assert_eq!(next(), [
+        channel("<@Kapua> History for Kapua#0002 (1 visit):\n`2022-02-01 14:00` Waited 0s and was helped by Ben#0001"),
+    ]);
+
+    // Jordan has everything about him deleted
+    assert_eq!(next(), [
+        channel("✅ <@Jordan> Se eliminaron tu historial y tus preferencias de idioma y recordatorios. El registro de auditoría de tus comandos se conserva hasta que pase el periodo de retención del servidor"),
+    ]);
+
+    // Kapua changes her name but keeps her spot
+    assert_eq!(next(), [channel("<@Kapua_K> You are at position 1")]);
+    assert_eq!(next(), [channel("Queue: [Kapua_K#0002, Bennett#0003, Jordan#0003]")]);
+
+    // CS 150, which uses Dana's shift, edits, buttons, help threads and surveys
+    assert_eq!(next(), [channel("✅ <@Dana> You are now on duty. On duty: Dana#0005")]);
+
+    // Russ joins for help with a lab (so the example script sends him its handout) then fixes his message
+    assert_eq!(next(), [
+        channel("✅ <@Russ> You have been added to the queue at position 1"),
+        channel("Russ, while you wait here's the lab2 handout: https://example.edu/cs/labs/lab2"),
+    ]);
+    assert_eq!(next(), [channel("✅ <@Russ> You are now in the queue for: lab3 part b")]);
+
+    // `!q labs` is added by the example script
+    assert_eq!(next(), [channel("https://example.edu/cs/labs/lab3")]);
+
+    // Deleting a command deletes its replies in CS 150
+    assert_eq!(next(), [channel("Queue: [Russ#0004 (lab3 part b)]\nOn duty: Dana#0005")]);
+    assert_eq!(next(), [Sent::Deleted(37)]);
+
+    // Students click the pinned message instead of typing
+    assert_eq!(next(), [
+        Sent::Buttons(pinned_queue("1 student waiting\n1. Russ#0004 (lab3 part b)"), vec![JOIN_BUTTON, LEAVE_BUTTON]),
+        Sent::Pinned(38),
+        Sent::Reacted(38, String::from("🙋")),
+        Sent::Reacted(38, String::from("👋")),
+    ]);
+    assert_eq!(next(), [
+        channel("✅ <@Bennett> You have been added to the queue at position 2"),
+        Sent::Edited(38, pinned_queue("2 students waiting\n1. Russ#0004 (lab3 part b)\n2. Bennett#0003")),
+    ]);
+    assert_eq!(next(), [
+        channel("✅ <@Kapua> You have been added to the queue at position 3"),
+        Sent::Edited(38, pinned_queue("3 students waiting\n1. Russ#0004 (lab3 part b)\n2. Bennett#0003\n3. Kapua#0002")),
+    ]);
+    assert_eq!(next(), [
+        channel("✅ <@Bennett> You have been removed from the queue"),
+        Sent::Edited(38, pinned_queue("2 students waiting\n1. Russ#0004 (lab3 part b)\n2. Kapua#0002")),
+    ]);
+
+    // Russ is called but doesn't turn up so he goes back behind Kapua
+    assert_eq!(next(), [
+        Sent::Thread(42, String::from("Russ#0004 and Dana#0005"), vec![p.russ.uuid, p.dana.uuid]),
+        Sent::InThread(42, String::from("<@Russ> <@Dana> will help you here. Only the two of you and other TAs can see this thread")),
+        channel("The next person in line is <@Russ>, who will be helped by <@Dana>"),
+        Sent::Edited(38, pinned_queue("1 student waiting\n1. Kapua#0002")),
+    ]);
+    assert_eq!(next(), [
+        Sent::Archived(42),
+        direct(p.russ.uuid, "<@Russ> A TA called you from the CS 150 queue but couldn't find you, so you were moved back to position 2. If you miss your turn 1 more time you will be removed from the queue"),
+        channel("✅ <@Russ> didn't show up and was moved back to position 2 (strike 1 of 2)"),
+        Sent::Edited(38, pinned_queue("2 students waiting\n1. Kapua#0002\n2. Russ#0004")),
+    ]);
+
+    // Dana helps Kapua in a private thread, then Kapua is asked to rate her
+    assert_eq!(next(), [
+        Sent::Thread(46, String::from("Kapua#0002 and Dana#0005"), vec![p.kapua.uuid, p.dana.uuid]),
+        Sent::InThread(46, String::from("<@Kapua> <@Dana> will help you here. Only the two of you and other TAs can see this thread")),
+        channel("The next person in line is <@Kapua>, who will be helped by <@Dana>"),
+        Sent::Edited(38, pinned_queue("1 student waiting\n1. Russ#0004")),
+    ]);
+    let survey = next();
+    assert_eq!(survey[..3], [
+        Sent::Archived(46),
+        channel("✅ <@Dana> The help thread with <@Kapua> has been archived"),
+        direct(p.kapua.uuid, "<@Kapua> How was the help you got from Dana#0005 in CS 150? React with 1️⃣ to 5️⃣ (5 is best), or reply with `!q feedback 1-5 [comment]` to leave a comment too. Your answer is anonymous"),
+    ]);
+    assert_eq!(survey[3..], ["1️⃣", "2️⃣", "3️⃣", "4️⃣", "5️⃣"].map(|emoji| Sent::Reacted(49, String::from(emoji))));
+
+    // Kapua's answer is deleted from the channel and only Ben sees it
+    let answer = next();
+    assert!(matches!(answer[0], Sent::Deleted(_)));
+    assert_eq!(answer[1..], [direct(p.kapua.uuid, "✅ <@Kapua> Thanks for your feedback!")]);
+    assert_eq!(next(), [
+        direct(p.ben.uuid, "<@Ben> Feedback for CS 150:\n**Dana#0005**: 5.0 from 1 rating (1★ 0, 2★ 0, 3★ 0, 4★ 0, 5★ 1)\n> Dana explained recursion really well"),
+    ]);
+
+    // Russ is warned when the last TA goes off duty
+    assert_eq!(next(), [
+        channel("✅ <@Dana> You are now off duty"),
+        channel("⚠️ <@Russ> There are no TAs on duty right now, so the queue won't move until one comes back"),
+    ]);
+
+    // Bennett books one of Dana's appointments
+    assert_eq!(next(), [channel("✅ <@Dana> Added 3 20m slots from Fri 4 Feb 10:00 to 11:00 (`#1` to `#3`)")]);
+    assert_eq!(next(), [
+        channel("✅ <@Bennett> You have booked slot `#1` at Fri 4 Feb 10:00 with Dana#0005. You will be put at the front of the queue when it starts. You can cancel with `!q cancel` until Fri 4 Feb 09:00"),
+    ]);
+
+    // Dana isn't a TA in CS 120
+    assert_eq!(next(), [channel("⚠️ <@Dana> invalid format.")]);
+    assert!(steps.next().is_none(), "the session has more steps than expected");
+}